@@ -0,0 +1,263 @@
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use crate::extraction::format::sniff_format;
+use crate::extraction::registry::{ContainerView, TextField};
+use crate::extraction::{ExtractedMetadata, MetadataExtractor, TagExtractor};
+use std::io::Cursor;
+
+/// Largest image accepted in an upload
+pub(crate) const MAX_UPLOAD_SIZE: usize = 64 * 1024 * 1024;
+/// Largest request body, all multipart fields together
+pub(crate) const MAX_REQUEST_SIZE: usize = 256 * 1024 * 1024;
+/// Most files accepted in one upload request
+pub(crate) const MAX_UPLOAD_FILES: usize = 16;
+
+/// Size and count limits for requests carrying files. Handlers use the defaults unless a
+/// `web::Data<UploadLimits>` is registered on the app.
+#[derive(Debug, Clone, Copy)]
+pub struct UploadLimits {
+    pub max_file_size: usize,
+    pub max_request_size: usize,
+    pub max_files: usize,
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        UploadLimits {
+            max_file_size: MAX_UPLOAD_SIZE,
+            max_request_size: MAX_REQUEST_SIZE,
+            max_files: MAX_UPLOAD_FILES,
+        }
+    }
+}
+
+impl UploadLimits {
+    pub(crate) fn for_request(req: &HttpRequest) -> Self {
+        req.app_data::<web::Data<UploadLimits>>().map(|limits| *limits.get_ref()).unwrap_or_default()
+    }
+}
+
+/// A file received in a request, from a multipart field or the raw body
+pub(crate) struct UploadedFile {
+    pub file_name: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Read the files of a `multipart/form-data` request (every field with a filename), or
+/// the whole body as one file for any other content type. Requests with more than
+/// `limits.max_files` files are rejected before the extra file is read.
+pub(crate) async fn read_uploads(
+    req: &HttpRequest,
+    mut payload: web::Payload,
+    limits: UploadLimits,
+) -> Result<Vec<UploadedFile>, String> {
+    let is_multipart = req
+        .headers()
+        .get(actix_web::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"));
+
+    if !is_multipart {
+        let mut data = Vec::new();
+        while let Some(chunk) = payload.next().await {
+            let chunk = chunk.map_err(|e| format!("Failed to read body: {}", e))?;
+            if data.len() + chunk.len() > limits.max_file_size {
+                return Err(format!("Upload exceeds {} bytes", limits.max_file_size));
+            }
+            data.extend_from_slice(&chunk);
+        }
+        return Ok(vec![UploadedFile { file_name: None, data }]);
+    }
+
+    let mut multipart = Multipart::new(req.headers(), payload);
+    let mut files = Vec::new();
+    let mut request_size = 0;
+    while let Some(field) = multipart.next().await {
+        let mut field = field.map_err(|e| format!("Invalid multipart body: {}", e))?;
+        let file_name = field
+            .content_disposition()
+            .and_then(|disposition| disposition.get_filename())
+            .map(String::from);
+        if file_name.is_some() && files.len() == limits.max_files {
+            return Err(format!("At most {} files per request", limits.max_files));
+        }
+
+        // Plain form values are read and dropped, so the next field can be reached
+        let mut data = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| format!("Failed to read upload: {}", e))?;
+            request_size += chunk.len();
+            if request_size > limits.max_request_size {
+                return Err(format!("Request exceeds {} bytes", limits.max_request_size));
+            }
+            if data.len() + chunk.len() > limits.max_file_size {
+                return Err(format!("Upload exceeds {} bytes", limits.max_file_size));
+            }
+            if file_name.is_some() {
+                data.extend_from_slice(&chunk);
+            }
+        }
+        if file_name.is_some() {
+            files.push(UploadedFile { file_name, data });
+        }
+    }
+    Ok(files)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DecodedTag {
+    pub name: String,
+    pub tag_type: String,
+    pub confidence: f64,
+}
+
+/// Everything extracted from an uploaded image
+#[derive(Debug, Serialize)]
+pub struct DecodeResponse {
+    pub file_name: Option<String>,
+    pub file_size: usize,
+    pub format: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Prompt, parameters, typed params, stages, sources and other fields
+    pub metadata: ExtractedMetadata,
+    pub tags: Vec<DecodedTag>,
+    /// Text chunks as stored in the file (PNG tEXt/zTXt/iTXt, WebP text chunks)
+    pub chunks: Vec<TextField>,
+}
+
+/// Run the extraction pipeline over an image held in memory, without storing anything
+pub fn decode_image_bytes(data: &[u8], file_name: Option<String>) -> anyhow::Result<DecodeResponse> {
+    let metadata = MetadataExtractor::extract_from_bytes(data)?;
+    let format = sniff_format(data);
+
+    // Same tags ingestion would assign: from the prompt, plus embedded keywords
+    let mut tags = Vec::new();
+    if let Some(prompt) = &metadata.prompt {
+        let extracted = TagExtractor::new().extract_from_prompt(prompt, metadata.negative_prompt.as_deref())?;
+        tags.extend(extracted.into_iter().map(|(name, tag_type, confidence)| DecodedTag { name, tag_type, confidence }));
+    }
+    for keyword in &metadata.keywords {
+        tags.push(DecodedTag { name: keyword.to_lowercase(), tag_type: "keyword".to_string(), confidence: 1.0 });
+    }
+
+    // Dimensions come from the header, the pixels are not decoded
+    let dimensions = image::io::Reader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok());
+
+    Ok(DecodeResponse {
+        file_name,
+        file_size: data.len(),
+        format: format.map(|f| f.as_str().to_string()),
+        width: dimensions.map(|(width, _)| width),
+        height: dimensions.map(|(_, height)| height),
+        chunks: ContainerView::new(data, format).text_fields().to_vec(),
+        metadata,
+        tags,
+    })
+}
+
+/// Decode an uploaded image (multipart `file` field or raw body) and return its metadata;
+/// nothing is written to the library
+pub async fn decode_image(req: HttpRequest, payload: web::Payload) -> impl Responder {
+    // Only one image is decoded, so a second file is refused rather than buffered
+    let limits = UploadLimits { max_files: 1, ..UploadLimits::for_request(&req) };
+    let upload = match read_uploads(&req, payload, limits).await {
+        Ok(files) => files.into_iter().next(),
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e
+            }))
+        }
+    };
+    let upload = match upload {
+        Some(upload) if !upload.data.is_empty() => upload,
+        _ => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "No image in request body"
+            }))
+        }
+    };
+
+    // Parsing (and inflating compressed chunks) is CPU-bound, keep it off the async workers
+    let decoded = actix_web::rt::task::spawn_blocking(move || decode_image_bytes(&upload.data, upload.file_name)).await;
+    match decoded {
+        Ok(Ok(decoded)) => HttpResponse::Ok().json(decoded),
+        Ok(Err(e)) => HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": format!("Failed to decode image: {}", e)
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": format!("Decoding failed: {}", e)
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+
+    fn png_with_parameters(parameters: &str) -> Vec<u8> {
+        let text = [b"parameters\0", parameters.as_bytes()].concat();
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend_from_slice(&(text.len() as u32).to_be_bytes());
+        png.extend_from_slice(b"tEXt");
+        png.extend_from_slice(&text);
+        png.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        png.extend_from_slice(b"IEND");
+        png
+    }
+
+    #[actix_web::test]
+    async fn test_decode_raw_and_multipart_uploads() {
+        let app = test::init_service(App::new().route("/decode", web::post().to(decode_image))).await;
+        let png = png_with_parameters("masterpiece, a fox in the snow\nSteps: 28, Sampler: Euler a, CFG scale: 6, Seed: 9");
+
+        let raw = test::TestRequest::post().uri("/decode").set_payload(png.clone()).to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, raw).await;
+        assert_eq!(body["format"], "png");
+        assert_eq!(body["metadata"]["prompt"], "masterpiece, a fox in the snow");
+        assert_eq!(body["metadata"]["params"]["steps"], 28);
+        assert_eq!(body["chunks"][0]["key"], "parameters");
+        assert!(body["tags"].as_array().unwrap().iter().any(|tag| tag["name"] == "masterpiece"));
+
+        let boundary = "decode-test-boundary";
+        let mut multipart = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"fox.png\"\r\nContent-Type: image/png\r\n\r\n"
+        )
+        .into_bytes();
+        multipart.extend_from_slice(&png);
+        multipart.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+        let request = test::TestRequest::post()
+            .uri("/decode")
+            .insert_header(("content-type", format!("multipart/form-data; boundary={boundary}")))
+            .set_payload(multipart)
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(body["file_name"], "fox.png");
+        assert_eq!(body["metadata"]["seed"], "9");
+
+        let empty = test::TestRequest::post().uri("/decode").to_request();
+        assert_eq!(test::call_service(&app, empty).await.status(), 400);
+
+        let mut two_files = Vec::new();
+        for name in ["one.png", "two.png"] {
+            two_files.extend_from_slice(
+                format!("--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{name}\"\r\n\r\n").as_bytes(),
+            );
+            two_files.extend_from_slice(&png);
+            two_files.extend_from_slice(b"\r\n");
+        }
+        two_files.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+        let request = test::TestRequest::post()
+            .uri("/decode")
+            .insert_header(("content-type", format!("multipart/form-data; boundary={boundary}")))
+            .set_payload(two_files)
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 400);
+    }
+}
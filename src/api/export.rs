@@ -134,7 +134,7 @@ pub async fn export_collection(
 
     match format {
         "markdown" => {
-            let mut markdown = String::from(format!("# Collection: {}\n\n", collection.name));
+            let mut markdown = format!("# Collection: {}\n\n", collection.name);
             
             if let Some(desc) = &collection.description {
                 markdown.push_str(&format!("{}\n\n", desc));
@@ -158,7 +158,7 @@ pub async fn export_collection(
                     markdown.push_str(&format!("- **Size:** {}x{}\n", w, h));
                 }
                 markdown.push_str(&format!("- **File Size:** {} bytes\n", image.file_size));
-                markdown.push_str("\n");
+                markdown.push('\n');
             }
 
             // Add prompts section
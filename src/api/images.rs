@@ -1,7 +1,10 @@
 use actix_web::{web, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 use crate::api::ApiState;
+use crate::api::decode::{read_uploads, UploadLimits};
 use crate::ingestion::IngestionService;
+use crate::storage::image_repo::Image;
+use crate::storage::params_repo::ParamsFilter;
 use std::sync::Mutex;
 use std::path::PathBuf;
 use log::{info, warn};
@@ -21,6 +24,22 @@ pub struct ScanRequest {
     pub recursive: Option<bool>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct UploadQuery {
+    pub collection_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadedImage {
+    #[serde(flatten)]
+    pub image: Image,
+    /// The same content was already in the library; `image` is the existing record
+    pub duplicate: bool,
+    /// Set when the image was stored but could not be added to the requested collection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
 static SCAN_PROGRESS: Mutex<Option<ScanProgressResponse>> = Mutex::new(None);
 
 // Helper to update scan progress
@@ -41,8 +60,17 @@ pub async fn list_images(
         .and_then(|v| v.parse::<usize>().ok())
         .unwrap_or(50);
     
-    // Support tag filtering via query parameter
+    // Support tag and generator filtering via query parameters
     let tag_filter = query.get("tag").map(|s| s.as_str());
+    let generator_filter = query.get("generator").map(|s| s.as_str());
+    let params_filter = match params_filter(&query) {
+        Ok(filter) => filter,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e
+            }))
+        }
+    };
 
     match state.image_repo.list_all() {
         Ok(mut images) => {
@@ -57,6 +85,25 @@ pub async fn list_images(
                     }
                 });
             }
+
+            if let Some(generator) = generator_filter {
+                images.retain(|image| generator_matches(image, generator));
+            }
+
+            // Numeric parameter ranges are answered by the indexed generation_params table
+            if !params_filter.is_empty() {
+                match state.params_repo.find_image_ids(&params_filter) {
+                    Ok(ids) => {
+                        let ids: std::collections::HashSet<String> = ids.into_iter().collect();
+                        images.retain(|image| ids.contains(&image.id));
+                    }
+                    Err(e) => {
+                        return HttpResponse::InternalServerError().json(serde_json::json!({
+                            "error": format!("Failed to filter by generation parameters: {}", e)
+                        }))
+                    }
+                }
+            }
             
             let total = images.len();
             let start = (page - 1) * limit;
@@ -69,7 +116,7 @@ pub async fn list_images(
                     "page": page,
                     "limit": limit,
                     "total": total,
-                    "pages": (total + limit - 1) / limit
+                    "pages": total.div_ceil(limit)
                 }
             }))
         }
@@ -79,6 +126,34 @@ pub async fn list_images(
     }
 }
 
+/// `seed`, `min_steps`, `max_steps`, `min_cfg`, `max_cfg`, `width` and `height` query
+/// parameters; a value that does not parse is an error naming the parameter
+fn params_filter(query: &std::collections::HashMap<String, String>) -> Result<ParamsFilter, String> {
+    fn get<T: std::str::FromStr>(query: &std::collections::HashMap<String, String>, key: &str) -> Result<Option<T>, String> {
+        query
+            .get(key)
+            .map(|v| v.parse().map_err(|_| format!("Invalid value for {}: {}", key, v)))
+            .transpose()
+    }
+    Ok(ParamsFilter {
+        seed: get(query, "seed")?,
+        min_steps: get(query, "min_steps")?,
+        max_steps: get(query, "max_steps")?,
+        min_cfg: get(query, "min_cfg")?,
+        max_cfg: get(query, "max_cfg")?,
+        width: get(query, "width")?,
+        height: get(query, "height")?,
+    })
+}
+
+/// Images without a detected generator match "unknown", the bucket used by the stats facet
+pub(crate) fn generator_matches(image: &Image, generator: &str) -> bool {
+    match &image.generator {
+        Some(detected) => detected.eq_ignore_ascii_case(generator),
+        None => generator.eq_ignore_ascii_case("unknown"),
+    }
+}
+
 pub async fn get_image(
     state: web::Data<ApiState>,
     path: web::Path<String>,
@@ -86,7 +161,36 @@ pub async fn get_image(
     let id = path.into_inner();
 
     match state.image_repo.find_by_id(&id) {
-        Ok(Some(image)) => HttpResponse::Ok().json(image),
+        Ok(Some(image)) => match state.metadata_repo.find_by_image_id(&id) {
+            Ok(metadata) => {
+                // Provenance (e.g. C2PA Content Credentials) is shown alongside the image record
+                let provenance: serde_json::Map<String, serde_json::Value> = metadata
+                    .into_iter()
+                    .filter(|m| m.metadata_type == "provenance")
+                    .map(|m| (m.key, serde_json::Value::String(m.value)))
+                    .collect();
+                let mut body = serde_json::to_value(&image).unwrap_or_default();
+                body["provenance"] = serde_json::Value::Object(provenance);
+                body["params"] = match state.params_repo.find_by_image_id(&id) {
+                    Ok(Some(stored)) => serde_json::to_value(&stored.params).unwrap_or_default(),
+                    _ => serde_json::Value::Null,
+                };
+                // Where each field was read from, with the candidates that lost
+                body["sources"] = match state.source_repo.find_by_image_id(&id) {
+                    Ok(sources) => serde_json::to_value(&sources).unwrap_or_default(),
+                    Err(_) => serde_json::Value::Array(Vec::new()),
+                };
+                // Base, hires fix, refiner and upscale passes in pipeline order
+                body["stages"] = match state.stage_repo.find_by_image_id(&id) {
+                    Ok(stages) => serde_json::to_value(&stages).unwrap_or_default(),
+                    Err(_) => serde_json::Value::Array(Vec::new()),
+                };
+                HttpResponse::Ok().json(body)
+            }
+            Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Failed to get image metadata: {}", e)
+            })),
+        },
         Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
             "error": "Image not found"
         })),
@@ -96,6 +200,78 @@ pub async fn get_image(
     }
 }
 
+/// Look for an invisible Stable Diffusion watermark in the image and store the result as provenance
+///
+/// POST /api/v1/images/{id}/watermark
+pub async fn detect_image_watermark(
+    state: web::Data<ApiState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    use crate::extraction::watermark::{apply_watermark_to_metadata, detect_watermark_in_file};
+    use crate::extraction::ExtractedMetadata;
+    use crate::storage::metadata_repo::Metadata;
+
+    let id = path.into_inner();
+
+    let image = match state.image_repo.find_by_id(&id) {
+        Ok(Some(image)) => image,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Image not found"
+            }))
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Failed to get image: {}", e)
+            }))
+        }
+    };
+
+    // Decoding full-size pixels is CPU-bound, keep it off the async workers
+    let file_path = image.file_path.clone();
+    let found = match actix_web::rt::task::spawn_blocking(move || detect_watermark_in_file(&file_path)).await {
+        Ok(Ok(found)) => found,
+        Ok(Err(e)) => {
+            return HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                "error": format!("Failed to decode image: {}", e)
+            }))
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Watermark detection failed: {}", e)
+            }))
+        }
+    };
+
+    let mut metadata = ExtractedMetadata::empty();
+    apply_watermark_to_metadata(found.as_ref(), &mut metadata);
+    // Replace every row of an earlier run, including a bit accuracy this run doesn't report
+    if let Err(e) = state.metadata_repo.delete_by_key_prefix(&id, "provenance", "watermark") {
+        warn!("Failed to clear earlier watermark result for image {}: {}", id, e);
+    }
+    let now = chrono::Utc::now().to_rfc3339();
+    for entry in metadata.entries {
+        let row = Metadata {
+            id: uuid::Uuid::new_v4().to_string(),
+            image_id: id.clone(),
+            key: entry.key,
+            value: entry.value,
+            metadata_type: entry.metadata_type,
+            created_at: now.clone(),
+        };
+        if let Err(e) = state.metadata_repo.create(&row) {
+            warn!("Failed to store watermark result for image {}: {}", id, e);
+        }
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "image_id": id,
+        "detected": found.is_some(),
+        "watermark": found.as_ref().map(|m| m.name),
+        "bit_accuracy": found.as_ref().map(|m| m.bit_accuracy),
+    }))
+}
+
 pub async fn get_thumbnail(
     state: web::Data<ApiState>,
     path: web::Path<String>,
@@ -123,6 +299,8 @@ pub async fn get_thumbnail(
                             Some("png") => "image/png",
                             Some("jpg") | Some("jpeg") => "image/jpeg",
                             Some("webp") => "image/webp",
+                            Some("avif") => "image/avif",
+                            Some("heic") | Some("heif") => "image/heif",
                             _ => "image/jpeg", // Default
                         };
                         
@@ -148,6 +326,10 @@ pub async fn get_thumbnail(
                                 "png" => "image/png",
                                 "jpg" | "jpeg" => "image/jpeg",
                                 "webp" => "image/webp",
+                                "avif" => "image/avif",
+                                "heic" | "heif" => "image/heif",
+                                "gif" => "image/gif",
+                                "tif" | "tiff" => "image/tiff",
                                 _ => "application/octet-stream",
                             };
                             
@@ -205,6 +387,10 @@ pub async fn get_image_file(
                         "png" => "image/png",
                         "jpg" | "jpeg" => "image/jpeg",
                         "webp" => "image/webp",
+                        "avif" => "image/avif",
+                        "heic" | "heif" => "image/heif",
+                        "gif" => "image/gif",
+                        "tif" | "tiff" => "image/tiff",
                         _ => "application/octet-stream",
                     };
                     
@@ -320,7 +506,7 @@ pub async fn scan_directory(
                 processed: progress.processed,
                 skipped: progress.skipped,
                 errors: progress.errors,
-                current_file: progress.current_file.as_ref().map(|s| s.clone()),
+                current_file: progress.current_file.clone(),
             });
         }
         
@@ -396,3 +582,293 @@ pub async fn get_scan_status() -> impl Responder {
     }
 }
 
+
+/// Store uploaded images (multipart file fields) in the managed upload directory and ingest
+/// them right away, optionally adding them to a collection
+pub async fn upload_images(
+    state: web::Data<ApiState>,
+    ingestion_service: web::Data<IngestionService>,
+    query: web::Query<UploadQuery>,
+    req: actix_web::HttpRequest,
+    payload: web::Payload,
+) -> impl Responder {
+    let collection_id = query.into_inner().collection_id;
+    if let Some(collection_id) = &collection_id {
+        match state.collection_repo.find_by_id(collection_id) {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                return HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Collection not found"
+                }))
+            }
+            Err(e) => {
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": format!("Failed to get collection: {}", e)
+                }))
+            }
+        }
+    }
+
+    let limits = UploadLimits::for_request(&req);
+    let files = match read_uploads(&req, payload, limits).await {
+        Ok(files) => files,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e
+            }))
+        }
+    };
+    let files: Vec<_> = files.into_iter().filter(|file| !file.data.is_empty()).collect();
+    if files.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "No files in upload"
+        }));
+    }
+
+    // Hashing, extraction and thumbnails are blocking work
+    let service = ingestion_service.get_ref().clone();
+    let collection_repo = state.collection_repo.clone();
+    let results = actix_web::rt::task::spawn_blocking(move || {
+        let mut images = Vec::new();
+        let mut errors = Vec::new();
+        for file in files {
+            let name = file.file_name.clone().unwrap_or_default();
+            match service.ingest_upload(&file.data, file.file_name.as_deref()) {
+                Ok((image, duplicate)) => {
+                    // The image is stored either way; a failed assignment is only reported
+                    let warning = collection_id.as_ref().and_then(|collection_id| {
+                        let added = collection_repo.add_image(collection_id, &image.id);
+                        added.err().map(|e| {
+                            warn!("Failed to add upload {} to collection {}: {}", name, collection_id, e);
+                            format!("Not added to collection: {}", e)
+                        })
+                    });
+                    images.push(UploadedImage { image, duplicate, warning });
+                }
+                Err(e) => {
+                    warn!("Failed to ingest upload {}: {}", name, e);
+                    errors.push(serde_json::json!({ "file_name": name, "error": e.to_string() }));
+                }
+            }
+        }
+        (images, errors)
+    })
+    .await;
+
+    match results {
+        Ok((images, errors)) => HttpResponse::Ok().json(serde_json::json!({
+            "uploaded": images.iter().filter(|i| !i.duplicate).count(),
+            "duplicates": images.iter().filter(|i| i.duplicate).count(),
+            "images": images,
+            "errors": errors
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": format!("Upload failed: {}", e)
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DatabaseConfig;
+    use crate::storage::{
+        CollectionRepository, Database, ImageRepository, MetadataRepository, ParamsRepository, PromptRepository,
+        SourceRepository, StageRepository, TagRepository,
+    };
+    use crate::storage::collection_repo::Collection;
+    use crate::storage::stage_repo::Stage;
+    use actix_web::{test, App};
+    use tempfile::TempDir;
+
+    const BOUNDARY: &str = "upload-test-boundary";
+
+    fn api_state(db: &Database) -> web::Data<ApiState> {
+        web::Data::new(ApiState {
+            db: db.clone(),
+            image_repo: ImageRepository::new(db.clone()),
+            prompt_repo: PromptRepository::new(db.clone()),
+            metadata_repo: MetadataRepository::new(db.clone()),
+            collection_repo: CollectionRepository::new(db.clone()),
+            tag_repo: TagRepository::new(db.clone()),
+            stage_repo: StageRepository::new(db.clone()),
+            params_repo: ParamsRepository::new(db.clone()),
+            source_repo: SourceRepository::new(db.clone()),
+        })
+    }
+
+    fn png(color: [u8; 3]) -> Vec<u8> {
+        let mut png = Vec::new();
+        image::RgbImage::from_pixel(8, 8, image::Rgb(color))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+        png
+    }
+
+    fn multipart(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (name, data) in files {
+            body.extend_from_slice(
+                format!("--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{name}\"\r\n\r\n")
+                    .as_bytes(),
+            );
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{BOUNDARY}--\r\n").as_bytes());
+        body
+    }
+
+    fn upload_request(uri: &str, body: Vec<u8>) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri(uri)
+            .insert_header(("content-type", format!("multipart/form-data; boundary={BOUNDARY}")))
+            .set_payload(body)
+    }
+
+    #[actix_web::test]
+    async fn test_upload_endpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(&DatabaseConfig {
+            database_path: temp_dir.path().join("test.db").to_str().unwrap().to_string(),
+        })
+        .unwrap();
+        let collection = Collection {
+            id: "favorites".to_string(),
+            name: "Favorites".to_string(),
+            description: None,
+            folder_path: None,
+            is_folder_based: false,
+            created_at: String::new(),
+            updated_at: String::new(),
+        };
+        CollectionRepository::new(db.clone()).create(&collection).unwrap();
+
+        let service = IngestionService::new(db.clone()).with_upload_path(temp_dir.path().join("uploads"));
+        let limits = UploadLimits { max_file_size: 4096, max_request_size: 8192, max_files: 3 };
+        let app = test::init_service(
+            App::new()
+                .app_data(api_state(&db))
+                .app_data(web::Data::new(service))
+                .app_data(web::Data::new(limits))
+                .route("/images/upload", web::post().to(upload_images)),
+        )
+        .await;
+
+        let (red, green) = (png([200, 40, 40]), png([40, 200, 40]));
+        let request = upload_request("/images/upload?collection_id=favorites", multipart(&[("red.png", &red), ("green.png", &green)])).to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!((body["uploaded"].as_u64(), body["duplicates"].as_u64()), (Some(2), Some(0)));
+        assert!(body["images"][0].get("warning").is_none());
+        let red_id = body["images"][0]["id"].clone();
+
+        // The same content again is reported as a duplicate of the existing record
+        let request = upload_request("/images/upload", multipart(&[("copy.png", &red)])).to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!((body["uploaded"].as_u64(), body["duplicates"].as_u64()), (Some(0), Some(1)));
+        assert_eq!(body["images"][0]["id"], red_id);
+        assert_eq!(body["images"][0]["duplicate"], true);
+
+        let request = upload_request("/images/upload?collection_id=missing", multipart(&[("red.png", &red)])).to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 404);
+
+        // One file more than the limit
+        let request = upload_request("/images/upload", multipart(&[("a.png", &red), ("b.png", &green), ("c.png", &red), ("d.png", &green)])).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), 400);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["error"], "At most 3 files per request");
+
+        // Each file is under the per-file limit, together they exceed the request limit
+        let filler = vec![0u8; 3000];
+        let request = upload_request("/images/upload", multipart(&[("a.bin", &filler), ("b.bin", &filler)])).to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(body["errors"].as_array().unwrap().len(), 2);
+        let request = upload_request("/images/upload", multipart(&[("a.bin", &filler), ("b.bin", &filler), ("c.bin", &filler)])).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), 400);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["error"], "Request exceeds 8192 bytes");
+
+        let assigned = CollectionRepository::new(db.clone()).get_image_ids("favorites").unwrap();
+        assert_eq!(assigned.len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_get_image_includes_stages() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(&DatabaseConfig {
+            database_path: temp_dir.path().join("test.db").to_str().unwrap().to_string(),
+        })
+        .unwrap();
+        ImageRepository::new(db.clone())
+            .create(&Image {
+                id: "img".to_string(),
+                file_path: "/images/img.png".to_string(),
+                file_name: "img.png".to_string(),
+                file_size: 0,
+                format: "png".to_string(),
+                width: None,
+                height: None,
+                hash: None,
+                generator: None,
+                generator_version: None,
+                extension_mismatch: false,
+                created_at: String::new(),
+                updated_at: String::new(),
+                last_scanned_at: String::new(),
+            })
+            .unwrap();
+        let stages = StageRepository::new(db.clone());
+        for (index, kind) in ["base", "hires_fix"].into_iter().enumerate() {
+            stages
+                .create(&Stage {
+                    id: kind.to_string(),
+                    image_id: "img".to_string(),
+                    stage_index: index as u32,
+                    kind: kind.to_string(),
+                    model: None,
+                    sampler: None,
+                    scheduler: None,
+                    steps: Some("20".to_string()),
+                    cfg_scale: None,
+                    seed: None,
+                    denoise: None,
+                    size: None,
+                    upscaler: None,
+                    start_step: None,
+                    end_step: None,
+                    created_at: String::new(),
+                })
+                .unwrap();
+        }
+
+        let app = test::init_service(App::new().app_data(api_state(&db)).route("/images/{id}", web::get().to(get_image))).await;
+        let request = test::TestRequest::get().uri("/images/img").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        let kinds: Vec<_> = body["stages"].as_array().unwrap().iter().map(|stage| stage["kind"].as_str().unwrap()).collect();
+        assert_eq!(kinds, vec!["base", "hires_fix"]);
+        assert_eq!(body["stages"][1]["steps"], "20");
+    }
+
+    #[actix_web::test]
+    async fn test_list_images_rejects_invalid_param_filters() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(&DatabaseConfig {
+            database_path: temp_dir.path().join("test.db").to_str().unwrap().to_string(),
+        })
+        .unwrap();
+        let app = test::init_service(App::new().app_data(api_state(&db)).route("/images", web::get().to(list_images))).await;
+
+        for (query, parameter) in [("min_steps=abc", "min_steps"), ("seed=-1x", "seed"), ("max_cfg=7,5", "max_cfg")] {
+            let request = test::TestRequest::get().uri(&format!("/images?{}", query)).to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), 400);
+            let body: serde_json::Value = test::read_body_json(response).await;
+            assert!(body["error"].as_str().unwrap().contains(parameter));
+        }
+
+        let request = test::TestRequest::get().uri("/images?min_steps=20&max_cfg=7.5").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 200);
+    }
+}
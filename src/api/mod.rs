@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use crate::storage::{
     Database, ImageRepository, PromptRepository, MetadataRepository,
-    CollectionRepository, TagRepository,
+    CollectionRepository, TagRepository, StageRepository, ParamsRepository, SourceRepository,
 };
 
 pub mod server;
@@ -16,6 +16,7 @@ pub mod export;
 pub mod stats;
 pub mod version_check;
 pub mod clip;
+pub mod decode;
 
 pub struct ApiState {
     pub db: Database,
@@ -24,6 +25,9 @@ pub struct ApiState {
     pub metadata_repo: MetadataRepository,
     pub collection_repo: CollectionRepository,
     pub tag_repo: TagRepository,
+    pub stage_repo: StageRepository,
+    pub params_repo: ParamsRepository,
+    pub source_repo: SourceRepository,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
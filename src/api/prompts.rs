@@ -59,7 +59,7 @@ pub async fn list_prompts(
                     "page": page,
                     "limit": limit,
                     "total": total,
-                    "pages": (total + limit - 1) / limit
+                    "pages": total.div_ceil(limit)
                 }
             }))
         }
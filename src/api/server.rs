@@ -10,10 +10,11 @@ use crate::api::tags::*;
 use crate::api::export::*;
 use crate::api::stats::*;
 use crate::api::clip;
+use crate::api::decode::decode_image;
 use crate::config::Config;
 use crate::storage::{
     Database, ImageRepository, PromptRepository, MetadataRepository,
-    CollectionRepository, TagRepository,
+    CollectionRepository, TagRepository, StageRepository, ParamsRepository, SourceRepository,
 };
 use crate::ingestion::IngestionService;
 use std::fs;
@@ -37,6 +38,9 @@ pub async fn start_server(config: Config) -> std::io::Result<()> {
     let metadata_repo = MetadataRepository::new(db.clone());
     let collection_repo = CollectionRepository::new(db.clone());
     let tag_repo = TagRepository::new(db.clone());
+    let stage_repo = StageRepository::new(db.clone());
+    let params_repo = ParamsRepository::new(db.clone());
+    let source_repo = SourceRepository::new(db.clone());
     
     // Initialize ingestion service (for scan endpoint) with config for thumbnail generation
     let ingestion_service = IngestionService::with_config(db.clone(), &config);
@@ -49,6 +53,9 @@ pub async fn start_server(config: Config) -> std::io::Result<()> {
         metadata_repo: metadata_repo.clone(),
         collection_repo: collection_repo.clone(),
         tag_repo: tag_repo.clone(),
+        stage_repo: stage_repo.clone(),
+        params_repo: params_repo.clone(),
+        source_repo: source_repo.clone(),
     });
     
     // Create ingestion service state for scan endpoint
@@ -83,10 +90,14 @@ pub async fn start_server(config: Config) -> std::io::Result<()> {
                     .route("/images/{id}", web::get().to(get_image))
                     .route("/images/{id}/thumbnail", web::get().to(get_thumbnail))
                     .route("/images/{id}/file", web::get().to(get_image_file))
+                    .route("/images/{id}/watermark", web::post().to(detect_image_watermark))
                     .route("/images/{id}", web::delete().to(delete_image))
                     .app_data(ingestion_state.clone())
                     .route("/images/scan", web::post().to(scan_directory))
                     .route("/images/scan/status", web::get().to(get_scan_status))
+                    .route("/images/upload", web::post().to(upload_images))
+                    // Decode an uploaded image without adding it to the library
+                    .route("/decode", web::post().to(decode_image))
                     // Prompts
                     .route("/prompts", web::get().to(list_prompts))
                     .route("/prompts/{id}", web::get().to(get_prompt))
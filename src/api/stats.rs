@@ -1,8 +1,24 @@
 use actix_web::{web, HttpResponse, Responder};
 use crate::api::ApiState;
+use crate::api::images::generator_matches;
+
+pub async fn get_stats(
+    state: web::Data<ApiState>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> impl Responder {
+    let mut images = state.image_repo.list_all().unwrap_or_default();
+
+    // Generator counts cover the whole library so the facet stays usable while filtered
+    let mut generator_counts = std::collections::HashMap::new();
+    for image in &images {
+        let generator = image.generator.clone().unwrap_or_else(|| "unknown".to_string());
+        *generator_counts.entry(generator).or_insert(0) += 1;
+    }
+
+    if let Some(generator) = query.get("generator") {
+        images.retain(|image| generator_matches(image, generator));
+    }
 
-pub async fn get_stats(state: web::Data<ApiState>) -> impl Responder {
-    let images = state.image_repo.list_all().unwrap_or_default();
     let collections = state.collection_repo.list_all().unwrap_or_default();
 
     // Count prompts
@@ -25,6 +41,7 @@ pub async fn get_stats(state: web::Data<ApiState>) -> impl Responder {
         "images": {
             "total": images.len()
         },
+        "generators": generator_counts,
         "prompts": {
             "total": prompt_count
         },
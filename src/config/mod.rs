@@ -8,6 +8,7 @@ pub struct Config {
     pub storage: StorageConfig,
     pub thumbnail: ThumbnailConfig,
     pub scanning: ScanningConfig,
+    pub watermark: WatermarkConfig,
     pub logging: LoggingConfig,
 }
 
@@ -26,6 +27,8 @@ pub struct DatabaseConfig {
 pub struct StorageConfig {
     pub thumbnail_path: String,
     pub max_thumbnail_size: u32,
+    /// Managed directory that uploaded images are stored in
+    pub upload_path: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,6 +44,12 @@ pub struct ScanningConfig {
     pub scan_interval: u64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatermarkConfig {
+    /// Decode every image during ingestion to look for invisible watermarks (slow)
+    pub detect_on_ingestion: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoggingConfig {
     pub level: String,
@@ -70,6 +79,8 @@ impl Config {
                     .unwrap_or_else(|_| "512".to_string())
                     .parse()
                     .unwrap_or(512),
+                upload_path: env::var("UPLOAD_PATH")
+                    .unwrap_or_else(|_| "./data/uploads".to_string()),
             },
             thumbnail: ThumbnailConfig {
                 enabled: env::var("THUMBNAIL_ENABLED")
@@ -95,6 +106,12 @@ impl Config {
                     .parse()
                     .unwrap_or(3600),
             },
+            watermark: WatermarkConfig {
+                detect_on_ingestion: env::var("WATERMARK_DETECTION")
+                    .unwrap_or_else(|_| "false".to_string())
+                    .parse()
+                    .unwrap_or(false),
+            },
             logging: LoggingConfig {
                 level: env::var("LOG_LEVEL").unwrap_or_else(|_| "info".to_string()),
             },
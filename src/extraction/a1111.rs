@@ -0,0 +1,327 @@
+use crate::extraction::{ExtractedMetadata, GenerationStage};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// One `Key: value` pair; quoted values come back unquoted and unescaped
+static SETTING_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"\s*([\w][\w \-/+().\[\]']*?):\s*("(?:\\.|[^\\"])*"|[^,]*)(?:,|$)"#).unwrap()
+});
+
+/// A1111/Forge `parameters` text split into its three sections
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct A1111Parameters {
+    pub prompt: String,
+    pub negative_prompt: Option<String>,
+    /// Every `Key: value` pair from the settings line, in order
+    pub settings: Vec<(String, String)>,
+}
+
+impl A1111Parameters {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.settings
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+            .filter(|v| !v.is_empty())
+    }
+}
+
+/// Parse an A1111 parameters string:
+///
+/// ```text
+/// prompt, which may span
+/// several lines
+/// Negative prompt: negative prompt, which may also
+/// span several lines
+/// Steps: 20, Sampler: Euler a, CFG scale: 7, Seed: 1, Size: 512x512, Lora hashes: "a: 1f2e, b: 9c8d"
+/// ```
+///
+/// The settings line is the last line that parses as at least two `Key: value` pairs and
+/// starts with "Steps:" (or, failing that, the last line that parses as three or more pairs
+/// including a known key such as "Sampler" or "Seed").
+pub fn parse_a1111_parameters(params: &str) -> A1111Parameters {
+    let lines: Vec<&str> = params.lines().collect();
+
+    let settings_index = lines
+        .iter()
+        .rposition(|line| line.trim_start().starts_with("Steps:") && parse_settings(line).len() >= 2)
+        .or_else(|| lines.iter().rposition(|line| looks_like_settings_line(line)));
+
+    let (text_lines, settings) = match settings_index {
+        Some(index) => {
+            let mut settings = parse_settings(lines[index]);
+            // Anything after the settings line (e.g. extension output) is kept as extra settings
+            for line in &lines[index + 1..] {
+                settings.extend(parse_settings(line));
+            }
+            (&lines[..index], settings)
+        }
+        None => (&lines[..], Vec::new()),
+    };
+
+    let negative_index = text_lines
+        .iter()
+        .position(|line| line.trim_start().starts_with("Negative prompt:"));
+
+    let (prompt_lines, negative_prompt) = match negative_index {
+        Some(index) => {
+            let mut negative_lines = vec![text_lines[index]
+                .trim_start()
+                .strip_prefix("Negative prompt:")
+                .unwrap_or("")
+                .trim_start()];
+            negative_lines.extend_from_slice(&text_lines[index + 1..]);
+            let negative = negative_lines.join("\n").trim().to_string();
+            (&text_lines[..index], (!negative.is_empty()).then_some(negative))
+        }
+        None => (text_lines, None),
+    };
+
+    A1111Parameters {
+        prompt: prompt_lines.join("\n").trim().to_string(),
+        negative_prompt,
+        settings,
+    }
+}
+
+/// Settings keys every A1111-style writer emits; a line without any of them is prompt text,
+/// however many `key: value` pairs it has
+const KNOWN_SETTINGS_KEYS: &[&str] = &["Steps", "Sampler", "Seed", "CFG scale", "Size", "Model"];
+
+fn looks_like_settings_line(line: &str) -> bool {
+    let settings = parse_settings(line);
+    settings.len() >= 3
+        && settings
+            .iter()
+            .any(|(key, _)| KNOWN_SETTINGS_KEYS.iter().any(|known| key.eq_ignore_ascii_case(known)))
+}
+
+/// Split a settings line into `Key: value` pairs, honouring quoted values
+pub fn parse_settings(line: &str) -> Vec<(String, String)> {
+    let mut settings = Vec::new();
+    let mut expected_start = 0;
+
+    for caps in SETTING_RE.captures_iter(line) {
+        let whole = caps.get(0).unwrap();
+        // Stop at text that isn't part of a `Key: value` list (e.g. a prompt that contains a colon)
+        if !line[expected_start..whole.start()].trim().is_empty() {
+            break;
+        }
+        expected_start = whole.end();
+
+        let key = caps[1].trim().to_string();
+        let value = unquote(caps[2].trim());
+        settings.push((key, value));
+    }
+
+    settings
+}
+
+/// Undo A1111's `json.dumps`-style quoting of values that contain commas or colons
+fn unquote(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        if let Ok(unquoted) = serde_json::from_str::<String>(value) {
+            return unquoted;
+        }
+        return value[1..value.len() - 1].to_string();
+    }
+    value.to_string()
+}
+
+/// Apply parsed parameters to metadata: known keys go to typed fields, everything else to `other`.
+/// Like `ExtractedMetadata::merge`, values only fill fields that are still empty.
+pub fn apply_a1111_to_metadata(parsed: &A1111Parameters, metadata: &mut ExtractedMetadata) {
+    let fill = |field: &mut Option<String>, value: &str| {
+        if field.is_none() && !value.is_empty() {
+            *field = Some(value.to_string());
+        }
+    };
+
+    fill(&mut metadata.prompt, &parsed.prompt);
+    if let Some(negative) = &parsed.negative_prompt {
+        fill(&mut metadata.negative_prompt, negative);
+    }
+
+    for (key, value) in &parsed.settings {
+        match key.as_str() {
+            "Steps" => fill(&mut metadata.steps, value),
+            "Sampler" => fill(&mut metadata.sampler, value),
+            "CFG scale" => fill(&mut metadata.cfg_scale, value),
+            "Seed" => fill(&mut metadata.seed, value),
+            "Size" => fill(&mut metadata.size, value),
+            "Model" => fill(&mut metadata.model, value),
+            _ => {
+                if !metadata.other.iter().any(|(k, _)| k == key) {
+                    metadata.other.push((key.clone(), value.clone()));
+                }
+            }
+        }
+    }
+
+    if metadata.stages.is_empty() {
+        metadata.stages = build_a1111_stages(parsed, metadata);
+    }
+}
+
+/// Split A1111 settings into generation passes: the base txt2img/img2img pass, an optional
+/// hires fix pass ("Hires upscale", "Hires steps", ...) and an optional SDXL refiner pass
+pub fn build_a1111_stages(parsed: &A1111Parameters, metadata: &ExtractedMetadata) -> Vec<GenerationStage> {
+    let get = |key: &str| parsed.get(key).map(|v| v.to_string());
+
+    if metadata.steps.is_none() && metadata.sampler.is_none() {
+        return Vec::new();
+    }
+
+    let is_hires = ["Hires upscale", "Hires resize", "Hires steps", "Hires upscaler"]
+        .iter()
+        .any(|key| get(key).is_some());
+
+    let mut base = GenerationStage::new("base");
+    base.model = metadata.model.clone();
+    base.sampler = metadata.sampler.clone();
+    base.scheduler = get("Schedule type");
+    base.steps = metadata.steps.clone();
+    base.cfg_scale = metadata.cfg_scale.clone();
+    base.seed = metadata.seed.clone();
+    base.size = metadata.size.clone();
+    if !is_hires {
+        // Without hires fix, denoising strength belongs to an img2img base pass
+        base.denoise = get("Denoising strength");
+    }
+
+    let mut stages = vec![base.clone()];
+
+    if let Some(refiner) = get("Refiner") {
+        let mut stage = GenerationStage::new("refiner");
+        stage.model = Some(refiner);
+        stage.sampler = base.sampler.clone();
+        stage.scheduler = base.scheduler.clone();
+        stage.steps = base.steps.clone();
+        stage.cfg_scale = base.cfg_scale.clone();
+        stage.seed = base.seed.clone();
+        stage.size = base.size.clone();
+        // The refiner takes over the remaining steps of the same pass
+        let total_steps = base.steps.as_deref().and_then(|s| s.parse::<f64>().ok());
+        let switch_at = get("Refiner switch at").and_then(|s| s.parse::<f64>().ok());
+        if let (Some(total), Some(switch_at)) = (total_steps, switch_at) {
+            stage.start_step = Some(((total * switch_at) as u64).to_string());
+            stage.end_step = base.steps.clone();
+        }
+        stages.push(stage);
+    }
+
+    if is_hires {
+        let mut stage = GenerationStage::new("hires_fix");
+        stage.model = get("Hires checkpoint")
+            .filter(|v| v != "Use same checkpoint")
+            .or_else(|| base.model.clone());
+        stage.sampler = get("Hires sampler")
+            .filter(|v| v != "Use same sampler")
+            .or_else(|| base.sampler.clone());
+        stage.scheduler = get("Hires schedule type").or_else(|| base.scheduler.clone());
+        // "Hires steps: 0" means "same as the base pass"
+        stage.steps = get("Hires steps")
+            .filter(|v| v != "0")
+            .or_else(|| base.steps.clone());
+        stage.cfg_scale = get("Hires CFG Scale").or_else(|| base.cfg_scale.clone());
+        stage.seed = base.seed.clone();
+        stage.denoise = get("Denoising strength");
+        stage.upscaler = get("Hires upscaler");
+        stage.size = get("Hires resize").or_else(|| {
+            let scale = get("Hires upscale")?.parse::<f64>().ok()?;
+            let (w, h) = base.size.as_deref()?.split_once('x')?;
+            let (w, h) = (w.trim().parse::<f64>().ok()?, h.trim().parse::<f64>().ok()?);
+            Some(format!("{}x{}", (w * scale) as u64, (h * scale) as u64))
+        });
+        stages.push(stage);
+    }
+
+    stages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multiline_prompt_and_negative() {
+        let params = "masterpiece, a tall lighthouse,
+storm clouds, crashing waves
+Negative prompt: lowres, bad anatomy,
+watermark
+Steps: 30, Sampler: DPM++ 2M Karras, CFG scale: 7, Seed: 99, Size: 512x768";
+
+        let parsed = parse_a1111_parameters(params);
+        assert_eq!(parsed.prompt, "masterpiece, a tall lighthouse,\nstorm clouds, crashing waves");
+        assert_eq!(parsed.negative_prompt, Some("lowres, bad anatomy,\nwatermark".to_string()));
+        assert_eq!(parsed.get("Steps"), Some("30"));
+        assert_eq!(parsed.get("Size"), Some("512x768"));
+    }
+
+    #[test]
+    fn test_quoted_and_escaped_values() {
+        let params = r#"a cat
+Steps: 20, Sampler: Euler a, CFG scale: 7, Seed: 1, Size: 512x512, Model hash: 6ce0161689, Lora hashes: "add_detail: 7c6bad76eb54, film: 1f2e3d4c", ADetailer prompt: "a \"smiling\" face, detailed eyes", ADetailer model: face_yolov8n.pt, Version: v1.6.0"#;
+
+        let parsed = parse_a1111_parameters(params);
+        assert_eq!(parsed.prompt, "a cat");
+        assert_eq!(parsed.get("Lora hashes"), Some("add_detail: 7c6bad76eb54, film: 1f2e3d4c"));
+        assert_eq!(parsed.get("ADetailer prompt"), Some("a \"smiling\" face, detailed eyes"));
+        assert_eq!(parsed.get("ADetailer model"), Some("face_yolov8n.pt"));
+        assert_eq!(parsed.get("Version"), Some("v1.6.0"));
+    }
+
+    #[test]
+    fn test_known_keys_typed_rest_in_other() {
+        let params = "portrait of an old sailor
+Negative prompt: blurry
+Steps: 25, Sampler: DPM++ SDE, Schedule type: Karras, CFG scale: 5.5, Seed: 1234, Size: 832x1216, Model hash: 31e35c80fc, Model: juggernautXL_v9, VAE: sdxl_vae.safetensors, Clip skip: 2, ENSD: 31337, TI hashes: \"easynegative: c74b4e810b03\", ControlNet 0: \"Module: canny, Model: control_v11p_sd15_canny, Weight: 1.0\"";
+
+        let mut metadata = ExtractedMetadata::empty();
+        apply_a1111_to_metadata(&parse_a1111_parameters(params), &mut metadata);
+
+        assert_eq!(metadata.model, Some("juggernautXL_v9".to_string()));
+        assert_eq!(metadata.cfg_scale, Some("5.5".to_string()));
+        let other = |key: &str| metadata.other.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+        assert_eq!(other("Model hash"), Some("31e35c80fc"));
+        assert_eq!(other("VAE"), Some("sdxl_vae.safetensors"));
+        assert_eq!(other("Clip skip"), Some("2"));
+        assert_eq!(other("ENSD"), Some("31337"));
+        assert_eq!(other("Schedule type"), Some("Karras"));
+        assert_eq!(other("TI hashes"), Some("easynegative: c74b4e810b03"));
+        assert_eq!(other("ControlNet 0"), Some("Module: canny, Model: control_v11p_sd15_canny, Weight: 1.0"));
+    }
+
+    #[test]
+    fn test_only_fills_empty_fields() {
+        let mut metadata = ExtractedMetadata::empty();
+        metadata.prompt = Some("from the Description".to_string());
+        metadata.steps = Some("28".to_string());
+        apply_a1111_to_metadata(&parse_a1111_parameters("a cat\nNegative prompt: blurry\nSteps: 20, Seed: 3"), &mut metadata);
+
+        assert_eq!(metadata.prompt, Some("from the Description".to_string()));
+        assert_eq!(metadata.steps, Some("28".to_string()));
+        assert_eq!(metadata.negative_prompt, Some("blurry".to_string()));
+        assert_eq!(metadata.seed, Some("3".to_string()));
+    }
+
+    #[test]
+    fn test_prompt_with_colon_is_not_settings() {
+        let params = "style: watercolor, subject: a fox\nSteps: 20, Seed: 3";
+        let parsed = parse_a1111_parameters(params);
+        assert_eq!(parsed.prompt, "style: watercolor, subject: a fox");
+        assert_eq!(parsed.get("Seed"), Some("3"));
+    }
+
+    #[test]
+    fn test_prompt_of_key_value_pairs_is_not_settings() {
+        let parsed = parse_a1111_parameters("style: watercolor, subject: a fox, mood: calm");
+        assert_eq!(parsed.prompt, "style: watercolor, subject: a fox, mood: calm");
+        assert!(parsed.settings.is_empty());
+
+        // Without "Steps:" first, a line with known keys is still the settings line
+        let parsed = parse_a1111_parameters("a fox\nSampler: Euler a, Seed: 3, CFG scale: 7");
+        assert_eq!(parsed.prompt, "a fox");
+        assert_eq!(parsed.get("Seed"), Some("3"));
+    }
+}
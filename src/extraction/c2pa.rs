@@ -0,0 +1,409 @@
+use crate::extraction::cbor::Cbor;
+use crate::extraction::isobmff::boxes;
+use crate::extraction::{ExtractedMetadata, MetadataEntry};
+use base64::{Engine as _, engine::general_purpose};
+use serde::Serialize;
+
+/// Extended type of the ISOBMFF `uuid` box that carries a C2PA manifest store
+pub const C2PA_UUID: [u8; 16] = [
+    0xd8, 0xfe, 0xc3, 0xd6, 0x1b, 0x0e, 0x48, 0x3c, 0x92, 0x97, 0x58, 0x28, 0x87, 0x7e, 0xc4, 0x81,
+];
+/// Common identifier of JPEG XT (JUMBF) APP11 segments
+const JPEG_XT_ID: &[u8] = b"JP";
+
+/// One manifest of a C2PA manifest store. Read-only: nothing here validates hashes,
+/// certificates or signatures.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct C2paManifest {
+    pub label: Option<String>,
+    pub claim_generator: Option<String>,
+    pub title: Option<String>,
+    pub actions: Vec<C2paAction>,
+    pub ingredients: Vec<C2paIngredient>,
+    /// A COSE_Sign1 structure with a non-empty signature was found
+    pub signature_present: bool,
+    /// COSE algorithm from the protected header, e.g. "ES256"
+    pub signature_algorithm: Option<String>,
+}
+
+/// An entry of a `c2pa.actions` assertion
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct C2paAction {
+    pub action: String,
+    /// IPTC digital source type, e.g. `.../digitalsourcetype/trainedAlgorithmicMedia`
+    pub digital_source_type: Option<String>,
+    pub software_agent: Option<String>,
+}
+
+/// A `c2pa.ingredient` assertion
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct C2paIngredient {
+    pub title: Option<String>,
+    pub relationship: Option<String>,
+    /// Base64 hash of the ingredient's own manifest, when it had one
+    pub manifest_hash: Option<String>,
+}
+
+/// A JUMBF superbox: its description label and content boxes
+struct Superbox<'a> {
+    label: Option<String>,
+    children: Vec<(&'a [u8; 4], &'a [u8])>,
+}
+
+impl<'a> Superbox<'a> {
+    fn parse(payload: &'a [u8]) -> Option<Self> {
+        let children = boxes(payload);
+        let (kind, description) = children.first()?;
+        if *kind != b"jumd" || description.len() < 17 {
+            return None;
+        }
+        // Content type UUID, toggles, then an optional null-terminated label
+        let toggles = description[16];
+        let label = (toggles & 0x02 != 0).then(|| {
+            let label = &description[17..];
+            let end = label.iter().position(|b| *b == 0).unwrap_or(label.len());
+            String::from_utf8_lossy(&label[..end]).to_string()
+        });
+        Some(Superbox { label, children: children[1..].to_vec() })
+    }
+
+    fn superboxes(&self) -> impl Iterator<Item = Superbox<'a>> + '_ {
+        self.children
+            .iter()
+            .filter(|(kind, _)| *kind == b"jumb")
+            .filter_map(|(_, payload)| Superbox::parse(payload))
+    }
+
+    fn child(&self, label: impl Fn(&str) -> bool) -> Option<Superbox<'a>> {
+        self.superboxes().find(|b| b.label.as_deref().is_some_and(&label))
+    }
+
+    fn cbor(&self) -> Option<Cbor> {
+        let (_, payload) = self.children.iter().find(|(kind, _)| *kind == b"cbor")?;
+        Cbor::decode(payload)
+    }
+}
+
+/// Parse a JUMBF manifest store (the `c2pa` superbox). The active manifest is the last one.
+pub fn parse_manifest_store(jumbf: &[u8]) -> Vec<C2paManifest> {
+    let store = boxes(jumbf)
+        .into_iter()
+        .filter(|(kind, _)| *kind == b"jumb")
+        .filter_map(|(_, payload)| Superbox::parse(payload))
+        .find(|b| b.label.as_deref() == Some("c2pa"));
+
+    match store {
+        Some(store) => store.superboxes().map(|manifest| parse_manifest(&manifest)).collect(),
+        None => Vec::new(),
+    }
+}
+
+fn parse_manifest(manifest: &Superbox) -> C2paManifest {
+    let mut result = C2paManifest {
+        label: manifest.label.clone(),
+        ..Default::default()
+    };
+
+    if let Some(claim) = manifest.child(|l| l.starts_with("c2pa.claim")).and_then(|b| b.cbor()) {
+        result.claim_generator = claim
+            .get("claim_generator")
+            .and_then(Cbor::as_str)
+            .map(|s| s.to_string())
+            .or_else(|| {
+                // v2 claims only have claim_generator_info: a map (or, in 1.x, a list of maps)
+                let info = claim.get("claim_generator_info")?;
+                let info = info.as_array().and_then(|a| a.first()).unwrap_or(info);
+                name_and_version(info)
+            });
+        result.title = claim.get("dc:title").and_then(Cbor::as_str).map(|s| s.to_string());
+    }
+
+    if let Some(assertions) = manifest.child(|l| l == "c2pa.assertions") {
+        for assertion in assertions.superboxes() {
+            let label = assertion.label.as_deref().unwrap_or_default();
+            // Repeated assertions get a `__n` suffix
+            let base_label = label.split("__").next().unwrap_or(label);
+            let Some(content) = assertion.cbor() else { continue };
+            match base_label {
+                "c2pa.actions" | "c2pa.actions.v2" => {
+                    let actions = content.get("actions").and_then(Cbor::as_array).unwrap_or_default();
+                    result.actions.extend(actions.iter().filter_map(parse_action));
+                }
+                "c2pa.ingredient" | "c2pa.ingredient.v2" | "c2pa.ingredient.v3" => {
+                    result.ingredients.push(parse_ingredient(&content));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if let Some(signature) = manifest.child(|l| l == "c2pa.signature").and_then(|b| b.cbor()) {
+        // COSE_Sign1: [protected header bytes, unprotected header, payload, signature]
+        // (normally wrapped in tag 18, which `as_array` looks through)
+        if let Some([protected, _, _, sig]) = signature.as_array() {
+            result.signature_present = sig.as_bytes().is_some_and(|s| !s.is_empty());
+            result.signature_algorithm = protected
+                .as_bytes()
+                .and_then(Cbor::decode)
+                .and_then(|header| header.get_int(1).and_then(Cbor::as_integer))
+                .map(cose_algorithm);
+        }
+    }
+
+    result
+}
+
+fn parse_action(action: &Cbor) -> Option<C2paAction> {
+    Some(C2paAction {
+        action: action.get("action")?.as_str()?.to_string(),
+        digital_source_type: action.get("digitalSourceType").and_then(Cbor::as_str).map(|s| s.to_string()),
+        software_agent: action.get("softwareAgent").and_then(|agent| {
+            agent.as_str().map(|s| s.to_string()).or_else(|| name_and_version(agent))
+        }),
+    })
+}
+
+fn parse_ingredient(ingredient: &Cbor) -> C2paIngredient {
+    let text = |key: &str| ingredient.get(key).and_then(Cbor::as_str).map(|s| s.to_string());
+    C2paIngredient {
+        title: text("dc:title").or_else(|| text("title")),
+        relationship: text("relationship"),
+        manifest_hash: ingredient
+            .get("activeManifest")
+            .or_else(|| ingredient.get("c2pa_manifest"))
+            .and_then(|uri| uri.get("hash"))
+            .and_then(Cbor::as_bytes)
+            .map(|hash| general_purpose::STANDARD.encode(hash)),
+    }
+}
+
+/// "name version" of a claim_generator_info / softwareAgent map
+fn name_and_version(info: &Cbor) -> Option<String> {
+    let name = info.get("name")?.as_str()?;
+    Some(match info.get("version").and_then(Cbor::as_str) {
+        Some(version) => format!("{} {}", name, version),
+        None => name.to_string(),
+    })
+}
+
+fn cose_algorithm(id: i128) -> String {
+    match id {
+        -7 => "ES256".to_string(),
+        -35 => "ES384".to_string(),
+        -36 => "ES512".to_string(),
+        -37 => "PS256".to_string(),
+        -38 => "PS384".to_string(),
+        -39 => "PS512".to_string(),
+        -8 => "Ed25519".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Reassemble the JUMBF box split over JPEG APP11 segments. Each segment carries
+/// `JP`, a box instance number, a sequence number, and repeats the box header.
+pub fn c2pa_from_jpeg_segments<'a>(app11_segments: impl Iterator<Item = &'a [u8]>) -> Option<Vec<u8>> {
+    let mut packets: Vec<(u16, u32, &[u8])> = app11_segments
+        .filter(|payload| payload.len() >= 16 && payload.starts_with(JPEG_XT_ID) && &payload[12..16] == b"jumb")
+        .map(|payload| {
+            let instance = u16::from_be_bytes([payload[2], payload[3]]);
+            let sequence = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]);
+            (instance, sequence, &payload[8..])
+        })
+        .collect();
+    packets.sort_by_key(|(instance, sequence, _)| (*instance, *sequence));
+
+    // The first instance is the manifest store; other JUMBF boxes are unrelated
+    let instance = packets.first()?.0;
+    let mut jumbf = Vec::new();
+    for (index, (_, _, data)) in packets.iter().filter(|(i, _, _)| *i == instance).enumerate() {
+        jumbf.extend_from_slice(if index == 0 { data } else { &data[8..] });
+    }
+    Some(jumbf)
+}
+
+/// Payload of the ISOBMFF C2PA `uuid` box (after its extended type): version and flags,
+/// a purpose string, and for "manifest" a 64-bit offset before the JUMBF data
+pub fn c2pa_from_uuid_box(payload: &[u8]) -> Option<&[u8]> {
+    let rest = payload.strip_prefix(&C2PA_UUID[..])?.get(4..)?;
+    let end = rest.iter().position(|b| *b == 0)?;
+    if &rest[..end] != b"manifest" {
+        return None;
+    }
+    rest.get(end + 1 + 8..)
+}
+
+/// Store the active manifest as "provenance" entries; a claim generator also feeds
+/// generator detection
+pub fn apply_c2pa_to_metadata(manifests: &[C2paManifest], metadata: &mut ExtractedMetadata) {
+    let Some(active) = manifests.last() else {
+        return;
+    };
+    let mut entry = |key: &str, value: &str| {
+        metadata.entries.push(MetadataEntry::new(key, value, "provenance"));
+    };
+
+    entry("c2pa.manifests", &manifests.len().to_string());
+    if let Some(label) = &active.label {
+        entry("c2pa.label", label);
+    }
+    if let Some(generator) = &active.claim_generator {
+        entry("c2pa.claim_generator", generator);
+    }
+    if let Some(title) = &active.title {
+        entry("c2pa.title", title);
+    }
+    if !active.actions.is_empty() {
+        let actions: Vec<&str> = active.actions.iter().map(|a| a.action.as_str()).collect();
+        entry("c2pa.actions", &actions.join(", "));
+    }
+    if let Some(source_type) = active.actions.iter().find_map(|a| a.digital_source_type.as_deref()) {
+        entry("c2pa.digital_source_type", source_type);
+    }
+    if let Some(agent) = active.actions.iter().find_map(|a| a.software_agent.as_deref()) {
+        entry("c2pa.software_agent", agent);
+    }
+    if !active.ingredients.is_empty() {
+        let ingredients: Vec<String> = active
+            .ingredients
+            .iter()
+            .map(|i| {
+                let mut text = i.title.clone().unwrap_or_else(|| "untitled".to_string());
+                if let Some(relationship) = &i.relationship {
+                    text.push_str(&format!(" ({})", relationship));
+                }
+                if let Some(hash) = &i.manifest_hash {
+                    text.push_str(&format!(" {}", hash));
+                }
+                text
+            })
+            .collect();
+        entry("c2pa.ingredients", &ingredients.join("; "));
+    }
+    let signature = match (&active.signature_present, &active.signature_algorithm) {
+        (true, Some(algorithm)) => format!("present ({})", algorithm),
+        (true, None) => "present".to_string(),
+        (false, _) => "missing".to_string(),
+    };
+    entry("c2pa.signature", &signature);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn bx(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn superbox(label: &str, content: &[Vec<u8>]) -> Vec<u8> {
+        let mut jumd = vec![0u8; 16];
+        jumd.push(0x03);
+        jumd.extend_from_slice(label.as_bytes());
+        jumd.push(0);
+        let mut payload = bx(b"jumd", &jumd);
+        for child in content {
+            payload.extend_from_slice(child);
+        }
+        bx(b"jumb", &payload)
+    }
+
+    fn text(s: &str) -> Vec<u8> {
+        let mut data = vec![0x78, s.len() as u8];
+        data.extend_from_slice(s.as_bytes());
+        data
+    }
+
+    fn map(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut data = vec![0xa0 | entries.len() as u8];
+        for (key, value) in entries {
+            data.extend(text(key));
+            data.extend_from_slice(value);
+        }
+        data
+    }
+
+    /// Manifest store with a generative "created" action, one ingredient and a signature
+    pub(crate) fn manifest_store() -> Vec<u8> {
+        let actions = map(&[(
+            "actions",
+            [
+                vec![0x81],
+                map(&[
+                    ("action", text("c2pa.created")),
+                    ("digitalSourceType", text("http://cv.iptc.org/newscodes/digitalsourcetype/trainedAlgorithmicMedia")),
+                    ("softwareAgent", map(&[("name", text("GPT-4o"))])),
+                ]),
+            ]
+            .concat(),
+        )]);
+        let ingredient = map(&[
+            ("dc:title", text("sketch.png")),
+            ("relationship", text("inputTo")),
+            ("activeManifest", map(&[("url", text("self#jumbf=c2pa/urn:c2pa:1")), ("hash", vec![0x43, 1, 2, 3])])),
+        ]);
+        let claim = map(&[
+            ("claim_generator_info", map(&[("name", text("ChatGPT")), ("version", text("1.0"))])),
+            ("dc:title", text("image.png")),
+        ]);
+        // 18([h'a10126' (alg ES256), {}, null, h'0102'])
+        let signature = vec![0xd2, 0x84, 0x43, 0xa1, 0x01, 0x26, 0xa0, 0xf6, 0x42, 0x01, 0x02];
+
+        let manifest = superbox(
+            "urn:c2pa:2",
+            &[
+                superbox(
+                    "c2pa.assertions",
+                    &[
+                        superbox("c2pa.actions.v2", &[bx(b"cbor", &actions)]),
+                        superbox("c2pa.ingredient.v3", &[bx(b"cbor", &ingredient)]),
+                    ],
+                ),
+                superbox("c2pa.claim.v2", &[bx(b"cbor", &claim)]),
+                superbox("c2pa.signature", &[bx(b"cbor", &signature)]),
+            ],
+        );
+        superbox("c2pa", &[superbox("urn:c2pa:1", &[]), manifest])
+    }
+
+    #[test]
+    fn test_parse_manifest_store() {
+        let manifests = parse_manifest_store(&manifest_store());
+        assert_eq!(manifests.len(), 2);
+
+        let active = manifests.last().unwrap();
+        assert_eq!(active.label, Some("urn:c2pa:2".to_string()));
+        assert_eq!(active.claim_generator, Some("ChatGPT 1.0".to_string()));
+        assert_eq!(active.title, Some("image.png".to_string()));
+        assert_eq!(active.actions[0].action, "c2pa.created");
+        assert_eq!(active.actions[0].software_agent, Some("GPT-4o".to_string()));
+        assert_eq!(active.ingredients[0].manifest_hash, Some("AQID".to_string()));
+        assert!(active.signature_present);
+        assert_eq!(active.signature_algorithm, Some("ES256".to_string()));
+
+        let mut metadata = ExtractedMetadata::empty();
+        apply_c2pa_to_metadata(&manifests, &mut metadata);
+        assert!(metadata.entries.contains(&MetadataEntry::new("c2pa.signature", "present (ES256)", "provenance")));
+        assert!(metadata.entries.contains(&MetadataEntry::new("c2pa.ingredients", "sketch.png (inputTo) AQID", "provenance")));
+    }
+
+    #[test]
+    fn test_jpeg_app11_reassembly() {
+        let store = manifest_store();
+        let (first, second) = store.split_at(40);
+        let segment = |sequence: u32, data: &[u8]| {
+            let mut payload = b"JP\0\x01".to_vec();
+            payload.extend_from_slice(&sequence.to_be_bytes());
+            // Every segment repeats the superbox header
+            payload.extend_from_slice(&store[..8]);
+            payload.extend_from_slice(data);
+            payload
+        };
+        // Out of order on purpose; the first segment already starts with the header
+        let segments = [segment(2, second), segment(1, &first[8..])];
+        let jumbf = c2pa_from_jpeg_segments(segments.iter().map(|s| s.as_slice())).unwrap();
+        assert_eq!(jumbf, store);
+    }
+}
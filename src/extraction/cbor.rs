@@ -0,0 +1,238 @@
+/// Nesting limit, so hostile input can't exhaust the stack
+const MAX_DEPTH: usize = 64;
+
+/// A decoded CBOR data item (RFC 8949). Only what metadata readers need: no
+/// canonical-form checks, and tags are kept but not interpreted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cbor {
+    Integer(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Cbor>),
+    Map(Vec<(Cbor, Cbor)>),
+    Tag(u64, Box<Cbor>),
+    Bool(bool),
+    Float(f64),
+    Null,
+}
+
+impl Cbor {
+    /// Decode a single data item from the start of `data`
+    pub fn decode(data: &[u8]) -> Option<Cbor> {
+        let mut reader = CborReader { data, position: 0 };
+        reader.item(0)
+    }
+
+    /// Value of a text key in a map
+    pub fn get(&self, key: &str) -> Option<&Cbor> {
+        self.get_key(|k| k.as_str() == Some(key))
+    }
+
+    /// Value of an integer key in a map (COSE headers use these)
+    pub fn get_int(&self, key: i128) -> Option<&Cbor> {
+        self.get_key(|k| matches!(k, Cbor::Integer(i) if *i == key))
+    }
+
+    fn get_key(&self, matches: impl Fn(&Cbor) -> bool) -> Option<&Cbor> {
+        match self.untagged() {
+            Cbor::Map(entries) => entries.iter().find(|(k, _)| matches(k)).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// The item inside any tags
+    pub fn untagged(&self) -> &Cbor {
+        match self {
+            Cbor::Tag(_, inner) => inner.untagged(),
+            other => other,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self.untagged() {
+            Cbor::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self.untagged() {
+            Cbor::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Cbor]> {
+        match self.untagged() {
+            Cbor::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i128> {
+        match self.untagged() {
+            Cbor::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+}
+
+struct CborReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl CborReader<'_> {
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.data.get(self.position)?;
+        self.position += 1;
+        Some(byte)
+    }
+
+    fn bytes(&mut self, count: u64) -> Option<&[u8]> {
+        let count = usize::try_from(count).ok()?;
+        let bytes = self.data.get(self.position..self.position.checked_add(count)?)?;
+        self.position += count;
+        Some(bytes)
+    }
+
+    fn uint(&mut self, size: u64) -> Option<u64> {
+        Some(self.bytes(size)?.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    /// Argument of the initial byte; `None` for the indefinite-length marker
+    fn argument(&mut self, info: u8) -> Option<Option<u64>> {
+        Some(Some(match info {
+            0..=23 => info as u64,
+            24 => self.uint(1)?,
+            25 => self.uint(2)?,
+            26 => self.uint(4)?,
+            27 => self.uint(8)?,
+            31 => return Some(None),
+            _ => return None,
+        }))
+    }
+
+    fn at_break(&self) -> bool {
+        self.data.get(self.position) == Some(&0xff)
+    }
+
+    fn item(&mut self, depth: usize) -> Option<Cbor> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let initial = self.byte()?;
+        let (major, info) = (initial >> 5, initial & 0x1f);
+
+        if major == 7 {
+            return self.simple(info);
+        }
+        let argument = self.argument(info)?;
+
+        Some(match (major, argument) {
+            (0, Some(n)) => Cbor::Integer(n as i128),
+            (1, Some(n)) => Cbor::Integer(-1 - n as i128),
+            (2, Some(length)) => Cbor::Bytes(self.bytes(length)?.to_vec()),
+            (3, Some(length)) => Cbor::Text(String::from_utf8_lossy(self.bytes(length)?).to_string()),
+            // Indefinite-length strings are a series of definite-length chunks
+            (2 | 3, None) => {
+                let mut joined = Vec::new();
+                while !self.at_break() {
+                    match self.item(depth + 1)? {
+                        Cbor::Bytes(chunk) if major == 2 => joined.extend(chunk),
+                        Cbor::Text(chunk) if major == 3 => joined.extend(chunk.into_bytes()),
+                        _ => return None,
+                    }
+                }
+                self.position += 1;
+                if major == 2 {
+                    Cbor::Bytes(joined)
+                } else {
+                    Cbor::Text(String::from_utf8_lossy(&joined).to_string())
+                }
+            }
+            (4, length) => {
+                let mut items = Vec::new();
+                while length.map_or(!self.at_break(), |n| (items.len() as u64) < n) {
+                    items.push(self.item(depth + 1)?);
+                }
+                if length.is_none() {
+                    self.position += 1;
+                }
+                Cbor::Array(items)
+            }
+            (5, length) => {
+                let mut entries = Vec::new();
+                while length.map_or(!self.at_break(), |n| (entries.len() as u64) < n) {
+                    let key = self.item(depth + 1)?;
+                    let value = self.item(depth + 1)?;
+                    entries.push((key, value));
+                }
+                if length.is_none() {
+                    self.position += 1;
+                }
+                Cbor::Map(entries)
+            }
+            (6, Some(tag)) => Cbor::Tag(tag, Box::new(self.item(depth + 1)?)),
+            _ => return None,
+        })
+    }
+
+    fn simple(&mut self, info: u8) -> Option<Cbor> {
+        Some(match info {
+            20 => Cbor::Bool(false),
+            21 => Cbor::Bool(true),
+            22 | 23 => Cbor::Null,
+            25 => Cbor::Float(half_to_f64(self.uint(2)? as u16)),
+            26 => Cbor::Float(f32::from_bits(self.uint(4)? as u32) as f64),
+            27 => Cbor::Float(f64::from_bits(self.uint(8)?)),
+            _ => return None,
+        })
+    }
+}
+
+/// IEEE 754 half precision to f64
+fn half_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let fraction = (bits & 0x3ff) as f64;
+    sign * match exponent {
+        0 => fraction * 2f64.powi(-24),
+        31 if fraction == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + fraction / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_nested_items() {
+        // {"a": [1, -2, h'0102'], "b": 1.5 (half), 1: true}
+        let data = [
+            0xa3, 0x61, b'a', 0x83, 0x01, 0x21, 0x42, 0x01, 0x02, 0x61, b'b', 0xf9, 0x3e, 0x00, 0x01, 0xf5,
+        ];
+        let item = Cbor::decode(&data).unwrap();
+        assert_eq!(
+            item.get("a").and_then(Cbor::as_array),
+            Some(&[Cbor::Integer(1), Cbor::Integer(-2), Cbor::Bytes(vec![1, 2])][..])
+        );
+        assert_eq!(item.get("b"), Some(&Cbor::Float(1.5)));
+        assert_eq!(item.get_int(1), Some(&Cbor::Bool(true)));
+    }
+
+    #[test]
+    fn test_decode_indefinite_and_tagged() {
+        // 18([_ "ab", "c"]) with an indefinite array and indefinite text
+        let data = [0xd2, 0x9f, 0x7f, 0x62, b'a', b'b', 0x61, b'c', 0xff, 0xff];
+        let item = Cbor::decode(&data).unwrap();
+        assert!(matches!(item, Cbor::Tag(18, _)));
+        assert_eq!(item.as_array().unwrap()[0].as_str(), Some("abc"));
+
+        // Truncated input and runaway nesting are rejected rather than panicking
+        assert_eq!(Cbor::decode(&[0x82, 0x01]), None);
+        assert_eq!(Cbor::decode(&[0x81; 100]), None);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::extraction::ExtractedMetadata;
+use crate::extraction::{ExtractedMetadata, GenerationStage};
 use serde_json::Value;
 
 #[derive(Debug, Clone)]
@@ -13,11 +13,495 @@ pub struct ComfyUIWorkflow {
     pub width: Option<String>,
     pub height: Option<String>,
     pub lora: Option<String>,
+    pub stages: Vec<GenerationStage>,
+}
+
+/// Maximum number of links followed when tracing an input back through the graph
+const MAX_LINK_DEPTH: usize = 64;
+
+/// Inputs that carry prompt text, in order of preference
+const TEXT_INPUT_KEYS: &[&str] = &[
+    "populated_text",
+    "text",
+    "text_g",
+    "string",
+    "value",
+    "prompt",
+    "wildcard_text",
+];
+
+/// Inputs that pass conditioning through unchanged (ControlNet, area and timestep nodes)
+const CONDITIONING_INPUT_KEYS: &[&str] = &["conditioning", "conditioning_to", "conditioning_1"];
+
+/// Inputs that carry a numeric value on primitive/constant nodes
+const NUMBER_INPUT_KEYS: &[&str] = &["value", "seed", "noise_seed", "int", "float", "number"];
+
+/// Inputs that carry a latent or image from an upstream node
+const LATENT_INPUT_KEYS: &[&str] = &["samples", "latent_image", "latent", "pixels", "image"];
+
+/// Nodes that a custom sampler pulls its settings from
+const SAMPLER_COMPONENT_KEYS: &[&str] = &["guider", "sampler", "sigmas", "noise"];
+
+/// ComfyUI API-format graph: node IDs mapped to `{ "class_type", "inputs" }` objects.
+/// Inputs are either literal values or `["node_id", output_slot]` links.
+struct ComfyGraph<'a> {
+    nodes: &'a serde_json::Map<String, Value>,
+}
+
+impl<'a> ComfyGraph<'a> {
+    fn node(&self, id: &str) -> Option<&'a Value> {
+        self.nodes.get(id)
+    }
+
+    fn class_type(node: &Value) -> &str {
+        node.get("class_type").and_then(|v| v.as_str()).unwrap_or("")
+    }
+
+    fn input<'n>(node: &'n Value, key: &str) -> Option<&'n Value> {
+        node.get("inputs").and_then(|i| i.get(key))
+    }
+
+    /// Decode a `["node_id", slot]` link reference
+    fn link(value: &Value) -> Option<(String, u64)> {
+        let arr = value.as_array()?;
+        if arr.len() != 2 {
+            return None;
+        }
+        let id = match &arr[0] {
+            Value::String(s) => s.clone(),
+            Value::Number(n) => n.to_string(),
+            _ => return None,
+        };
+        Some((id, arr[1].as_u64()?))
+    }
+
+    /// Node IDs in numeric order, so traversal doesn't depend on JSON key order
+    fn sorted_ids(&self) -> Vec<&'a String> {
+        let mut ids: Vec<&String> = self.nodes.keys().collect();
+        ids.sort_by_key(|id| (id.parse::<u64>().unwrap_or(u64::MAX), id.to_string()));
+        ids
+    }
+
+    fn is_sampler(node: &Value) -> bool {
+        let class_type = Self::class_type(node);
+        class_type.contains("Sampler")
+            && ["positive", "guider", "latent_image"]
+                .iter()
+                .any(|key| Self::input(node, key).is_some())
+    }
+
+    /// Look up a sampler setting on the sampler itself, or on the guider/sampler/sigmas/noise
+    /// nodes that `SamplerCustom*` nodes delegate to
+    fn sampler_input(&self, sampler: &'a Value, key: &str) -> Option<&'a Value> {
+        if let Some(value) = Self::input(sampler, key) {
+            return Some(value);
+        }
+        for component in SAMPLER_COMPONENT_KEYS {
+            let linked = Self::input(sampler, component)
+                .and_then(Self::link)
+                .and_then(|(id, _)| self.node(&id));
+            if let Some(value) = linked.and_then(|node| Self::input(node, key)) {
+                return Some(value);
+            }
+        }
+        // BasicGuider takes a single "conditioning" instead of positive/negative
+        if key == "positive" {
+            return self.sampler_input(sampler, "conditioning");
+        }
+        None
+    }
+
+    /// Resolve an input to prompt text, following links through encoder, concat and string nodes
+    fn resolve_text(&self, value: &Value, depth: usize) -> Option<String> {
+        if depth > MAX_LINK_DEPTH {
+            return None;
+        }
+        match value {
+            Value::String(s) if !s.trim().is_empty() => Some(s.clone()),
+            Value::Array(_) => {
+                let (id, slot) = Self::link(value)?;
+                self.resolve_node_text(&id, slot, depth + 1)
+            }
+            _ => None,
+        }
+    }
+
+    fn resolve_node_text(&self, id: &str, slot: u64, depth: usize) -> Option<String> {
+        let node = self.node(id)?;
+        let class_type = Self::class_type(node);
+
+        // Nodes with both positive and negative inputs expose them on separate output slots.
+        // Efficient Loader outputs MODEL first, then CONDITIONING+ and CONDITIONING-.
+        if Self::input(node, "positive").is_some() && Self::input(node, "negative").is_some() {
+            let first_conditioning_slot = if class_type.contains("Loader") { 1 } else { 0 };
+            let key = if slot == first_conditioning_slot + 1 { "negative" } else { "positive" };
+            return Self::input(node, key).and_then(|v| self.resolve_text(v, depth));
+        }
+
+        // Concatenating nodes: join every text-bearing input
+        if class_type.contains("Concat") || class_type.contains("Combine") || class_type.contains("Join") {
+            return self.resolve_joined_text(node, depth);
+        }
+
+        // SDXL encoders carry separate G and L prompts; report both when they differ
+        if let Some(text_g) = Self::input(node, "text_g").and_then(|v| self.resolve_text(v, depth)) {
+            return match Self::input(node, "text_l").and_then(|v| self.resolve_text(v, depth)) {
+                Some(text_l) if text_l.trim() != text_g.trim() => Some(format!("{}, {}", text_g, text_l)),
+                _ => Some(text_g),
+            };
+        }
+
+        TEXT_INPUT_KEYS
+            .iter()
+            .chain(CONDITIONING_INPUT_KEYS)
+            .filter_map(|key| Self::input(node, key))
+            .find_map(|v| self.resolve_text(v, depth))
+    }
+
+    fn resolve_joined_text(&self, node: &Value, depth: usize) -> Option<String> {
+        let inputs = node.get("inputs")?.as_object()?;
+        let delimiter = ["delimiter", "separator"]
+            .iter()
+            .find_map(|key| inputs.get(*key).and_then(|v| v.as_str()))
+            .unwrap_or(", ");
+
+        // ConditioningConcat appends "from" onto "to"; everything else joins in key order
+        let mut keys: Vec<&String> = inputs
+            .keys()
+            .filter(|k| !matches!(k.as_str(), "delimiter" | "separator" | "clean_whitespace"))
+            .collect();
+        keys.sort_by_key(|k| match k.as_str() {
+            "conditioning_to" => (0, k.to_string()),
+            "conditioning_from" => (1, k.to_string()),
+            _ => (2, k.to_string()),
+        });
+
+        let parts: Vec<String> = keys
+            .into_iter()
+            .filter_map(|k| self.resolve_text(&inputs[k], depth))
+            .collect();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(delimiter))
+        }
+    }
+
+    /// Follow a model input back to its checkpoint, collecting LoRAs applied along the way
+    fn resolve_model(&self, value: &Value, loras: &mut Vec<String>, depth: usize) -> Option<String> {
+        if depth > MAX_LINK_DEPTH {
+            return None;
+        }
+        let (id, _) = Self::link(value)?;
+        let node = self.node(&id)?;
+
+        if let Some(lora) = Self::input(node, "lora_name").and_then(|v| v.as_str()) {
+            if lora != "None" && !loras.iter().any(|l| l == lora) {
+                loras.push(lora.to_string());
+            }
+        }
+        // Stacked LoRA loaders (e.g. rgthree Power Lora Loader) store `{ "on", "lora", ... }` objects
+        if let Some(inputs) = node.get("inputs").and_then(|i| i.as_object()) {
+            for entry in inputs.values() {
+                let enabled = entry.get("on").and_then(|v| v.as_bool()).unwrap_or(false);
+                if let (true, Some(lora)) = (enabled, entry.get("lora").and_then(|v| v.as_str())) {
+                    if !loras.iter().any(|l| l == lora) {
+                        loras.push(lora.to_string());
+                    }
+                }
+            }
+        }
+
+        for key in ["ckpt_name", "unet_name", "model_name"] {
+            if let Some(name) = Self::input(node, key).and_then(|v| v.as_str()) {
+                return Some(name.to_string());
+            }
+        }
+
+        Self::input(node, "model").and_then(|v| self.resolve_model(v, loras, depth + 1))
+    }
+
+    /// Follow a latent input back to the node that set its resolution
+    fn resolve_latent_size(&self, value: &Value, depth: usize) -> Option<(u64, u64)> {
+        if depth > MAX_LINK_DEPTH {
+            return None;
+        }
+        let (id, _) = Self::link(value)?;
+        let node = self.node(&id)?;
+
+        for (w_key, h_key) in [("width", "height"), ("empty_latent_width", "empty_latent_height")] {
+            let width = Self::input(node, w_key).and_then(|v| self.resolve_number(v, depth));
+            let height = Self::input(node, h_key).and_then(|v| self.resolve_number(v, depth));
+            if let (Some(w), Some(h)) = (width, height) {
+                return Some((w as u64, h as u64));
+            }
+        }
+
+        let upstream = LATENT_INPUT_KEYS
+            .iter()
+            .filter_map(|key| Self::input(node, key))
+            .find_map(|v| self.resolve_latent_size(v, depth + 1));
+
+        let scale = Self::input(node, "scale_by").and_then(|v| self.resolve_number(v, depth));
+        match (upstream, scale) {
+            (Some((w, h)), Some(scale)) => Some(((w as f64 * scale) as u64, (h as f64 * scale) as u64)),
+            (size, _) => size,
+        }
+    }
+
+    /// Resolve a numeric input, following links to primitive/constant nodes
+    fn resolve_number(&self, value: &Value, depth: usize) -> Option<f64> {
+        if depth > MAX_LINK_DEPTH {
+            return None;
+        }
+        match value {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) => s.trim().parse().ok(),
+            Value::Array(_) => {
+                let (id, _) = Self::link(value)?;
+                let node = self.node(&id)?;
+                NUMBER_INPUT_KEYS
+                    .iter()
+                    .filter_map(|key| Self::input(node, key))
+                    .find_map(|v| self.resolve_number(v, depth + 1))
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolve an input to a string, following links to primitive/selector nodes
+    fn resolve_string(&self, value: &Value, depth: usize) -> Option<String> {
+        if depth > MAX_LINK_DEPTH {
+            return None;
+        }
+        match value {
+            Value::String(s) => Some(s.clone()),
+            Value::Array(_) => {
+                let (id, _) = Self::link(value)?;
+                let node = self.node(&id)?;
+                ["sampler_name", "scheduler", "value", "string", "text"]
+                    .iter()
+                    .filter_map(|key| Self::input(node, key))
+                    .find_map(|v| self.resolve_string(v, depth + 1))
+            }
+            _ => None,
+        }
+    }
+
+    /// The sampler that produced a latent/image input, if any (i.e. this is a hires/refiner pass
+    /// or an upscale of that sampler's output)
+    fn upstream_sampler(&self, value: &Value, depth: usize) -> Option<&'a str> {
+        if depth > MAX_LINK_DEPTH {
+            return None;
+        }
+        let (id, _) = Self::link(value)?;
+        let (id, node) = self.nodes.get_key_value(&id)?;
+        if Self::is_sampler(node) {
+            return Some(id.as_str());
+        }
+        LATENT_INPUT_KEYS
+            .iter()
+            .filter_map(|key| Self::input(node, key))
+            .find_map(|v| self.upstream_sampler(v, depth + 1))
+    }
+
+    /// The first upscaler between a latent input and the sampler that produced it
+    fn resolve_upscaler(&self, value: &Value, depth: usize) -> Option<String> {
+        if depth > MAX_LINK_DEPTH {
+            return None;
+        }
+        let (id, _) = Self::link(value)?;
+        let node = self.node(&id)?;
+        if Self::is_sampler(node) {
+            return None;
+        }
+        if let Some(model) = Self::input(node, "upscale_model").and_then(Self::link) {
+            if let Some(name) = self
+                .node(&model.0)
+                .and_then(|loader| Self::input(loader, "model_name"))
+                .and_then(|v| v.as_str())
+            {
+                return Some(name.to_string());
+            }
+        }
+        if let Some(method) = Self::input(node, "upscale_method").and_then(|v| v.as_str()) {
+            return Some(method.to_string());
+        }
+        LATENT_INPUT_KEYS
+            .iter()
+            .filter_map(|key| Self::input(node, key))
+            .find_map(|v| self.resolve_upscaler(v, depth + 1))
+    }
+
+    /// Number of samplers upstream of this one
+    fn sampler_depth(&self, sampler: &Value, depth: usize) -> usize {
+        if depth > MAX_LINK_DEPTH {
+            return 0;
+        }
+        self.sampler_input(sampler, "latent_image")
+            .and_then(|v| self.upstream_sampler(v, 0))
+            .and_then(|id| self.node(id))
+            .map(|upstream| 1 + self.sampler_depth(upstream, depth + 1))
+            .unwrap_or(0)
+    }
+
+    /// Sampler node IDs in numeric order
+    fn sampler_ids(&self) -> Vec<&'a String> {
+        self.sorted_ids()
+            .into_iter()
+            .filter(|id| Self::is_sampler(&self.nodes[id.as_str()]))
+            .collect()
+    }
+
+    /// The sampler that produced the initial image: the first one whose latent does not come
+    /// from another sampler
+    fn primary_sampler(&self) -> Option<&'a Value> {
+        let samplers = self.sampler_ids();
+        samplers
+            .iter()
+            .map(|id| &self.nodes[id.as_str()])
+            .find(|node| self.sampler_depth(node, 0) == 0)
+            .or_else(|| samplers.first().map(|id| &self.nodes[id.as_str()]))
+    }
+
+    /// Every sampler and model-upscale pass, ordered by position in the pipeline. Samplers
+    /// sort by how many samplers feed into them; an upscale sorts right after the sampler
+    /// whose output it consumes.
+    fn pipeline(&self) -> Vec<GenerationStage> {
+        let mut ordered: Vec<(usize, GenerationStage)> = Vec::new();
+        // Later passes are told apart by whether they swap the primary sampler's model, so it
+        // has to be known before samplers are visited in node order
+        let base_model = self
+            .primary_sampler()
+            .and_then(|sampler| self.sampler_input(sampler, "model"))
+            .and_then(|v| self.resolve_model(v, &mut Vec::new(), 0));
+
+        for id in self.sampler_ids() {
+            let sampler = &self.nodes[id.as_str()];
+            let depth = self.sampler_depth(sampler, 0);
+            let latent = self.sampler_input(sampler, "latent_image");
+
+            let mut loras = Vec::new();
+            let model = self
+                .sampler_input(sampler, "model")
+                .and_then(|v| self.resolve_model(v, &mut loras, 0));
+
+            let kind = if depth == 0 {
+                "base"
+            } else if model.is_some() && model != base_model {
+                "refiner"
+            } else {
+                "hires_fix"
+            };
+
+            let mut stage = GenerationStage::new(kind);
+            stage.model = model;
+            stage.sampler = self
+                .sampler_input(sampler, "sampler_name")
+                .and_then(|v| self.resolve_string(v, 0));
+            stage.scheduler = self
+                .sampler_input(sampler, "scheduler")
+                .and_then(|v| self.resolve_string(v, 0));
+            stage.steps = self
+                .sampler_input(sampler, "steps")
+                .and_then(|v| self.resolve_number(v, 0))
+                .map(|n| (n as u64).to_string());
+            stage.cfg_scale = self
+                .sampler_input(sampler, "cfg")
+                .and_then(|v| self.resolve_number(v, 0))
+                .map(|n| n.to_string());
+            stage.seed = self
+                .sampler_input(sampler, "seed")
+                .or_else(|| self.sampler_input(sampler, "noise_seed"))
+                .and_then(|v| self.resolve_number(v, 0))
+                .map(|n| (n as u64).to_string());
+            stage.denoise = self
+                .sampler_input(sampler, "denoise")
+                .and_then(|v| self.resolve_number(v, 0))
+                .map(|n| n.to_string());
+            stage.start_step = self
+                .sampler_input(sampler, "start_at_step")
+                .and_then(|v| self.resolve_number(v, 0))
+                .map(|n| (n as u64).to_string());
+            stage.end_step = self
+                .sampler_input(sampler, "end_at_step")
+                .and_then(|v| self.resolve_number(v, 0))
+                .map(|n| (n as u64).to_string());
+            if let Some((width, height)) = latent.and_then(|v| self.resolve_latent_size(v, 0)) {
+                stage.size = Some(format!("{}x{}", width, height));
+            }
+            if depth > 0 {
+                stage.upscaler = latent.and_then(|v| self.resolve_upscaler(v, 0));
+            }
+
+            ordered.push((depth * 2, stage));
+        }
+
+        for id in self.sorted_ids() {
+            let node = &self.nodes[id.as_str()];
+            let Some(upscale_model) = Self::input(node, "upscale_model") else {
+                continue;
+            };
+            // Upscales feeding a later sampler are reported as that sampler's upscaler
+            let feeds_sampler = self.nodes.values().any(|other| {
+                Self::is_sampler(other)
+                    && self
+                        .sampler_input(other, "latent_image")
+                        .is_some_and(|v| self.latent_path_contains(v, id, 0))
+            });
+            if feeds_sampler {
+                continue;
+            }
+
+            let depth = Self::input(node, "image")
+                .and_then(|v| self.upstream_sampler(v, 0))
+                .and_then(|sampler_id| self.node(sampler_id))
+                .map(|sampler| self.sampler_depth(sampler, 0))
+                .unwrap_or(0);
+
+            let mut stage = GenerationStage::new("upscale");
+            stage.upscaler = self.upscale_model_name(upscale_model).map(|s| s.to_string());
+            ordered.push((depth * 2 + 1, stage));
+        }
+
+        // Stable sort keeps numeric node order within the same pipeline position
+        ordered.sort_by_key(|(position, _)| *position);
+        ordered.into_iter().map(|(_, stage)| stage).collect()
+    }
+
+    /// Whether `target` lies on the latent/image path feeding into `value`, stopping at samplers
+    fn latent_path_contains(&self, value: &Value, target: &str, depth: usize) -> bool {
+        if depth > MAX_LINK_DEPTH {
+            return false;
+        }
+        let Some((id, _)) = Self::link(value) else {
+            return false;
+        };
+        if id == target {
+            return true;
+        }
+        match self.node(&id) {
+            Some(node) if !Self::is_sampler(node) => LATENT_INPUT_KEYS
+                .iter()
+                .filter_map(|key| Self::input(node, key))
+                .any(|v| self.latent_path_contains(v, target, depth + 1)),
+            _ => false,
+        }
+    }
+
+    fn upscale_model_name(&self, value: &Value) -> Option<&'a str> {
+        let (id, _) = Self::link(value)?;
+        Self::input(self.node(&id)?, "model_name")?.as_str()
+    }
 }
 
 pub fn parse_comfyui_workflow(json_str: &str) -> anyhow::Result<ComfyUIWorkflow> {
     let json: Value = serde_json::from_str(json_str)?;
-    
+    Ok(parse_comfyui_graph(&json))
+}
+
+/// Parse an already-decoded API-format graph
+pub(crate) fn parse_comfyui_graph(json: &Value) -> ComfyUIWorkflow {
     let mut workflow = ComfyUIWorkflow {
         readable_prompt: None,
         negative_prompt: None,
@@ -29,82 +513,145 @@ pub fn parse_comfyui_workflow(json_str: &str) -> anyhow::Result<ComfyUIWorkflow>
         width: None,
         height: None,
         lora: None,
+        stages: Vec::new(),
     };
 
     // ComfyUI workflows are stored as objects with node IDs as keys
     if let Value::Object(nodes) = json {
-        for (_node_id, node_value) in nodes {
-            // node_value is already a Value::Object
-            if let Value::Object(node) = &node_value {
-                // Look for ImpactWildcardProcessor or similar nodes with populated_text
-                if let Some(class_type) = node.get("class_type").and_then(|v| v.as_str()) {
-                    if class_type.contains("Wildcard") || class_type.contains("Text") || class_type.contains("Prompt") {
-                        // Extract populated_text (the readable prompt) - this is the actual prompt text
-                        if let Some(populated_text) = node
+        let graph = ComfyGraph { nodes };
+
+        // Follow the links out of the sampler so the reported prompt is the one that
+        // actually conditioned the image, not just the first text node in the file
+        if let Some(sampler) = graph.primary_sampler() {
+            apply_sampler_links(&graph, sampler, &mut workflow);
+        }
+        workflow.stages = graph.pipeline();
+
+        // Fill anything the link walk couldn't resolve from well-known node types
+        apply_class_heuristics(nodes, &mut workflow);
+    }
+
+    workflow
+}
+
+fn apply_sampler_links(graph: &ComfyGraph, sampler: &Value, workflow: &mut ComfyUIWorkflow) {
+    workflow.readable_prompt = graph
+        .sampler_input(sampler, "positive")
+        .and_then(|v| graph.resolve_text(v, 0));
+    workflow.negative_prompt = graph
+        .sampler_input(sampler, "negative")
+        .and_then(|v| graph.resolve_text(v, 0));
+
+    let mut loras = Vec::new();
+    workflow.model = graph
+        .sampler_input(sampler, "model")
+        .and_then(|v| graph.resolve_model(v, &mut loras, 0));
+    if !loras.is_empty() {
+        workflow.lora = Some(loras.join(", "));
+    }
+
+    if let Some((width, height)) = graph
+        .sampler_input(sampler, "latent_image")
+        .and_then(|v| graph.resolve_latent_size(v, 0))
+    {
+        workflow.width = Some(width.to_string());
+        workflow.height = Some(height.to_string());
+    }
+
+    workflow.steps = graph
+        .sampler_input(sampler, "steps")
+        .and_then(|v| graph.resolve_number(v, 0))
+        .map(|n| (n as u64).to_string());
+    workflow.cfg_scale = graph
+        .sampler_input(sampler, "cfg")
+        .and_then(|v| graph.resolve_number(v, 0))
+        .map(|n| n.to_string());
+    workflow.sampler = graph
+        .sampler_input(sampler, "sampler_name")
+        .and_then(|v| graph.resolve_string(v, 0));
+    workflow.seed = graph
+        .sampler_input(sampler, "seed")
+        .or_else(|| graph.sampler_input(sampler, "noise_seed"))
+        .and_then(|v| graph.resolve_number(v, 0))
+        .map(|n| (n as u64).to_string());
+}
+
+/// Class-name based extraction for graphs whose sampler links couldn't be resolved.
+/// Only fills fields that are still empty.
+fn apply_class_heuristics(nodes: &serde_json::Map<String, Value>, workflow: &mut ComfyUIWorkflow) {
+    for node_value in nodes.values() {
+        // node_value is already a Value::Object
+        if let Value::Object(node) = node_value {
+            // Look for ImpactWildcardProcessor or similar nodes with populated_text
+            if let Some(class_type) = node.get("class_type").and_then(|v| v.as_str()) {
+                if class_type.contains("Wildcard") || class_type.contains("Text") || class_type.contains("Prompt") {
+                    // Extract populated_text (the readable prompt) - this is the actual prompt text
+                    if let Some(populated_text) = node
+                        .get("inputs")
+                        .and_then(|i| i.get("populated_text"))
+                        .and_then(|v| v.as_str())
+                    {
+                        if workflow.readable_prompt.is_none() && !populated_text.is_empty() {
+                            workflow.readable_prompt = Some(populated_text.to_string());
+                        }
+                    }
+
+                    // Also check for wildcard_text as fallback
+                    if workflow.readable_prompt.is_none() {
+                        if let Some(wildcard_text) = node
                             .get("inputs")
-                            .and_then(|i| i.get("populated_text"))
+                            .and_then(|i| i.get("wildcard_text"))
                             .and_then(|v| v.as_str())
                         {
-                            if workflow.readable_prompt.is_none() && !populated_text.is_empty() {
-                                workflow.readable_prompt = Some(populated_text.to_string());
-                            }
-                        }
-
-                        // Also check for wildcard_text as fallback
-                        if workflow.readable_prompt.is_none() {
-                            if let Some(wildcard_text) = node
-                                .get("inputs")
-                                .and_then(|i| i.get("wildcard_text"))
-                                .and_then(|v| v.as_str())
-                            {
-                                if !wildcard_text.is_empty() {
-                                    workflow.readable_prompt = Some(wildcard_text.to_string());
-                                }
+                            if !wildcard_text.is_empty() {
+                                workflow.readable_prompt = Some(wildcard_text.to_string());
                             }
                         }
                     }
+                }
 
-                    // Extract from Efficient Loader or CheckpointLoaderSimple
-                    if class_type.contains("Loader") || class_type.contains("Checkpoint") {
-                        // Extract model name
-                        if workflow.model.is_none() {
-                            if let Some(ckpt_name) = node
-                                .get("inputs")
-                                .and_then(|i| i.get("ckpt_name"))
-                                .and_then(|v| v.as_str())
-                            {
-                                workflow.model = Some(ckpt_name.to_string());
-                            }
+                // Extract from Efficient Loader or CheckpointLoaderSimple
+                if class_type.contains("Loader") || class_type.contains("Checkpoint") {
+                    // Extract model name
+                    if workflow.model.is_none() {
+                        if let Some(ckpt_name) = node
+                            .get("inputs")
+                            .and_then(|i| i.get("ckpt_name"))
+                            .and_then(|v| v.as_str())
+                        {
+                            workflow.model = Some(ckpt_name.to_string());
                         }
+                    }
 
-                        // Extract negative prompt
-                        if workflow.negative_prompt.is_none() {
-                            if let Some(negative) = node
-                                .get("inputs")
-                                .and_then(|i| i.get("negative"))
-                                .and_then(|v| v.as_str())
-                            {
-                                if !negative.is_empty() {
-                                    workflow.negative_prompt = Some(negative.to_string());
-                                }
+                    // Extract negative prompt
+                    if workflow.negative_prompt.is_none() {
+                        if let Some(negative) = node
+                            .get("inputs")
+                            .and_then(|i| i.get("negative"))
+                            .and_then(|v| v.as_str())
+                        {
+                            if !negative.is_empty() {
+                                workflow.negative_prompt = Some(negative.to_string());
                             }
                         }
+                    }
 
-                        // Extract LoRA
-                        if workflow.lora.is_none() {
-                            if let Some(lora_name) = node
-                                .get("inputs")
-                                .and_then(|i| i.get("lora_name"))
-                                .and_then(|v| v.as_str())
-                            {
-                                workflow.lora = Some(lora_name.to_string());
-                            }
+                    // Extract LoRA
+                    if workflow.lora.is_none() {
+                        if let Some(lora_name) = node
+                            .get("inputs")
+                            .and_then(|i| i.get("lora_name"))
+                            .and_then(|v| v.as_str())
+                        {
+                            workflow.lora = Some(lora_name.to_string());
                         }
                     }
+                }
 
-                    // Extract from KSampler or KSampler (Efficient)
-                    if class_type.contains("Sampler") {
-                        // Extract steps
+                // Extract from KSampler or KSampler (Efficient)
+                if class_type.contains("Sampler") {
+                    // Extract steps
+                    if workflow.steps.is_none() {
                         if let Some(steps) = node
                             .get("inputs")
                             .and_then(|i| i.get("steps"))
@@ -112,8 +659,10 @@ pub fn parse_comfyui_workflow(json_str: &str) -> anyhow::Result<ComfyUIWorkflow>
                         {
                             workflow.steps = Some(steps.to_string());
                         }
+                    }
 
-                        // Extract CFG scale
+                    // Extract CFG scale
+                    if workflow.cfg_scale.is_none() {
                         if let Some(cfg) = node
                             .get("inputs")
                             .and_then(|i| i.get("cfg"))
@@ -121,8 +670,10 @@ pub fn parse_comfyui_workflow(json_str: &str) -> anyhow::Result<ComfyUIWorkflow>
                         {
                             workflow.cfg_scale = Some(cfg.to_string());
                         }
+                    }
 
-                        // Extract sampler name
+                    // Extract sampler name
+                    if workflow.sampler.is_none() {
                         if let Some(sampler_name) = node
                             .get("inputs")
                             .and_then(|i| i.get("sampler_name"))
@@ -130,8 +681,10 @@ pub fn parse_comfyui_workflow(json_str: &str) -> anyhow::Result<ComfyUIWorkflow>
                         {
                             workflow.sampler = Some(sampler_name.to_string());
                         }
+                    }
 
-                        // Extract seed
+                    // Extract seed
+                    if workflow.seed.is_none() {
                         if let Some(seed) = node
                             .get("inputs")
                             .and_then(|i| i.get("seed"))
@@ -140,33 +693,21 @@ pub fn parse_comfyui_workflow(json_str: &str) -> anyhow::Result<ComfyUIWorkflow>
                             workflow.seed = Some(seed.to_string());
                         }
                     }
+                }
 
-                    // Extract from EmptyLatentImage
-                    if class_type.contains("Latent") || class_type.contains("Empty") {
-                        // Extract width
-                        if let Some(width) = node
-                            .get("inputs")
-                            .and_then(|i| i.get("width"))
-                            .and_then(|v| v.as_u64())
-                        {
-                            workflow.width = Some(width.to_string());
-                        }
-
-                        // Extract height
-                        if let Some(height) = node
-                            .get("inputs")
-                            .and_then(|i| i.get("height"))
-                            .and_then(|v| v.as_u64())
-                        {
-                            workflow.height = Some(height.to_string());
-                        }
+                // Extract from EmptyLatentImage
+                if (class_type.contains("Latent") || class_type.contains("Empty")) && workflow.width.is_none() {
+                    let inputs = node.get("inputs");
+                    let width = inputs.and_then(|i| i.get("width")).and_then(|v| v.as_u64());
+                    let height = inputs.and_then(|i| i.get("height")).and_then(|v| v.as_u64());
+                    if let (Some(width), Some(height)) = (width, height) {
+                        workflow.width = Some(width.to_string());
+                        workflow.height = Some(height.to_string());
                     }
                 }
             }
         }
     }
-
-    Ok(workflow)
 }
 
 pub fn extract_readable_prompt_from_workflow(json_str: &str) -> Option<String> {
@@ -179,10 +720,14 @@ pub fn extract_readable_prompt_from_workflow(json_str: &str) -> Option<String> {
 pub fn apply_comfyui_to_metadata(json_str: &str, metadata: &mut ExtractedMetadata) {
     match parse_comfyui_workflow(json_str) {
         Ok(workflow) => {
-            // Set readable prompt - ALWAYS override with extracted readable prompt
-            // The JSON workflow should not be stored as the prompt text
-            if let Some(prompt) = workflow.readable_prompt {
-                // Always use the readable prompt, not the JSON
+            if workflow.readable_prompt.is_some() || workflow.model.is_some() || !workflow.stages.is_empty() {
+                metadata.generator.get_or_insert_with(|| "comfyui".to_string());
+            }
+
+            // The readable prompt stands in for the JSON workflow, which is never stored as
+            // the prompt text. Callers run this through `record_source`, so a prompt from an
+            // earlier source still takes precedence.
+            if let Some(prompt) = workflow.readable_prompt.clone() {
                 metadata.prompt = Some(prompt);
             } else {
                 // If we couldn't extract readable prompt, try fallback search
@@ -193,56 +738,7 @@ pub fn apply_comfyui_to_metadata(json_str: &str, metadata: &mut ExtractedMetadat
                 }
             }
 
-            // Set negative prompt
-            if let Some(neg_prompt) = workflow.negative_prompt {
-                if metadata.negative_prompt.is_none() {
-                    metadata.negative_prompt = Some(neg_prompt);
-                }
-            }
-
-            // Set model
-            if let Some(model) = workflow.model {
-                if metadata.model.is_none() {
-                    metadata.model = Some(model);
-                }
-            }
-
-            // Set generation parameters
-            if let Some(seed) = workflow.seed {
-                if metadata.seed.is_none() {
-                    metadata.seed = Some(seed);
-                }
-            }
-
-            if let Some(steps) = workflow.steps {
-                if metadata.steps.is_none() {
-                    metadata.steps = Some(steps);
-                }
-            }
-
-            if let Some(cfg_scale) = workflow.cfg_scale {
-                if metadata.cfg_scale.is_none() {
-                    metadata.cfg_scale = Some(cfg_scale);
-                }
-            }
-
-            if let Some(sampler) = workflow.sampler {
-                if metadata.sampler.is_none() {
-                    metadata.sampler = Some(sampler);
-                }
-            }
-
-            // Set size
-            if let (Some(width), Some(height)) = (workflow.width, workflow.height) {
-                if metadata.size.is_none() {
-                    metadata.size = Some(format!("{}x{}", width, height));
-                }
-            }
-
-            // Store LoRA in other metadata
-            if let Some(lora) = workflow.lora {
-                metadata.other.push(("lora".to_string(), lora));
-            }
+            fill_missing_from_workflow(workflow, metadata);
         }
         Err(_) => {
             // If parsing fails, try to extract prompt from JSON string directly
@@ -258,6 +754,71 @@ pub fn apply_comfyui_to_metadata(json_str: &str, metadata: &mut ExtractedMetadat
     }
 }
 
+/// Copy workflow values into fields that are still empty; the prompt is left untouched
+/// unless it's missing too
+pub(crate) fn fill_missing_from_workflow(workflow: ComfyUIWorkflow, metadata: &mut ExtractedMetadata) {
+    if metadata.prompt.is_none() {
+        metadata.prompt = workflow.readable_prompt;
+    }
+
+    // Set negative prompt
+    if let Some(neg_prompt) = workflow.negative_prompt {
+        if metadata.negative_prompt.is_none() {
+            metadata.negative_prompt = Some(neg_prompt);
+        }
+    }
+
+    // Set model
+    if let Some(model) = workflow.model {
+        if metadata.model.is_none() {
+            metadata.model = Some(model);
+        }
+    }
+
+    // Set generation parameters
+    if let Some(seed) = workflow.seed {
+        if metadata.seed.is_none() {
+            metadata.seed = Some(seed);
+        }
+    }
+
+    if let Some(steps) = workflow.steps {
+        if metadata.steps.is_none() {
+            metadata.steps = Some(steps);
+        }
+    }
+
+    if let Some(cfg_scale) = workflow.cfg_scale {
+        if metadata.cfg_scale.is_none() {
+            metadata.cfg_scale = Some(cfg_scale);
+        }
+    }
+
+    if let Some(sampler) = workflow.sampler {
+        if metadata.sampler.is_none() {
+            metadata.sampler = Some(sampler);
+        }
+    }
+
+    // Set size
+    if let (Some(width), Some(height)) = (workflow.width, workflow.height) {
+        if metadata.size.is_none() {
+            metadata.size = Some(format!("{}x{}", width, height));
+        }
+    }
+
+    if metadata.stages.is_empty() {
+        metadata.stages = workflow.stages;
+    }
+
+    // Store LoRA in other metadata
+    if let Some(lora) = workflow.lora {
+        if !metadata.other.iter().any(|(key, _)| key == "lora") {
+            metadata.other.push(("lora".to_string(), lora));
+        }
+    }
+}
+
 fn find_populated_text_in_json(value: &Value) -> Option<String> {
     match value {
         Value::Object(map) => {
@@ -333,5 +894,187 @@ mod tests {
         assert_eq!(workflow.sampler, Some("dpm_2".to_string()));
         assert_eq!(workflow.seed, Some("12345".to_string()));
     }
-}
 
+    #[test]
+    fn test_parse_comfyui_follows_sampler_links() {
+        // A decoy prompt node with a lower ID must not win over the one wired into the sampler
+        let json = r#"{
+            "1": {
+                "inputs": { "text": "unused draft prompt", "clip": ["4", 1] },
+                "class_type": "CLIPTextEncode"
+            },
+            "3": {
+                "inputs": {
+                    "seed": ["12", 0],
+                    "steps": 30,
+                    "cfg": 6.5,
+                    "sampler_name": "dpmpp_2m",
+                    "scheduler": "karras",
+                    "denoise": 1.0,
+                    "model": ["10", 0],
+                    "positive": ["6", 0],
+                    "negative": ["7", 0],
+                    "latent_image": ["5", 0]
+                },
+                "class_type": "KSampler"
+            },
+            "4": {
+                "inputs": { "ckpt_name": "juggernautXL.safetensors" },
+                "class_type": "CheckpointLoaderSimple"
+            },
+            "5": {
+                "inputs": { "width": 832, "height": 1216, "batch_size": 1 },
+                "class_type": "EmptyLatentImage"
+            },
+            "6": {
+                "inputs": { "text": ["11", 0], "clip": ["10", 1] },
+                "class_type": "CLIPTextEncode"
+            },
+            "7": {
+                "inputs": { "text": "blurry, watermark", "clip": ["10", 1] },
+                "class_type": "CLIPTextEncode"
+            },
+            "10": {
+                "inputs": {
+                    "lora_name": "detail_tweaker.safetensors",
+                    "strength_model": 0.8,
+                    "strength_clip": 0.8,
+                    "model": ["4", 0],
+                    "clip": ["4", 1]
+                },
+                "class_type": "LoraLoader"
+            },
+            "11": {
+                "inputs": { "string_a": "a lighthouse at dusk", "string_b": "volumetric fog", "delimiter": ", " },
+                "class_type": "StringConcatenate"
+            },
+            "12": {
+                "inputs": { "value": 987654321 },
+                "class_type": "PrimitiveInt"
+            }
+        }"#;
+
+        let workflow = parse_comfyui_workflow(json).unwrap();
+        assert_eq!(workflow.readable_prompt, Some("a lighthouse at dusk, volumetric fog".to_string()));
+        assert_eq!(workflow.negative_prompt, Some("blurry, watermark".to_string()));
+        assert_eq!(workflow.model, Some("juggernautXL.safetensors".to_string()));
+        assert_eq!(workflow.lora, Some("detail_tweaker.safetensors".to_string()));
+        assert_eq!(workflow.seed, Some("987654321".to_string()));
+        assert_eq!(workflow.steps, Some("30".to_string()));
+        assert_eq!(workflow.cfg_scale, Some("6.5".to_string()));
+        assert_eq!(workflow.sampler, Some("dpmpp_2m".to_string()));
+        assert_eq!(workflow.width, Some("832".to_string()));
+        assert_eq!(workflow.height, Some("1216".to_string()));
+    }
+
+    #[test]
+    fn test_parse_comfyui_custom_sampler_and_conditioning_concat() {
+        let json = r#"{
+            "1": {
+                "inputs": { "noise": ["2", 0], "guider": ["3", 0], "sampler": ["4", 0], "sigmas": ["5", 0], "latent_image": ["6", 0] },
+                "class_type": "SamplerCustomAdvanced"
+            },
+            "2": { "inputs": { "noise_seed": 42 }, "class_type": "RandomNoise" },
+            "3": {
+                "inputs": { "model": ["7", 0], "positive": ["8", 0], "negative": ["9", 0], "cfg": 4.0 },
+                "class_type": "CFGGuider"
+            },
+            "4": { "inputs": { "sampler_name": "euler" }, "class_type": "KSamplerSelect" },
+            "5": { "inputs": { "scheduler": "simple", "steps": 25, "denoise": 1.0, "model": ["7", 0] }, "class_type": "BasicScheduler" },
+            "6": { "inputs": { "width": 1024, "height": 1024, "batch_size": 1 }, "class_type": "EmptySD3LatentImage" },
+            "7": { "inputs": { "unet_name": "flux1-dev.safetensors", "weight_dtype": "default" }, "class_type": "UNETLoader" },
+            "8": { "inputs": { "conditioning_to": ["10", 0], "conditioning_from": ["11", 0] }, "class_type": "ConditioningConcat" },
+            "9": { "inputs": { "text": "lowres", "clip": ["12", 0] }, "class_type": "CLIPTextEncode" },
+            "10": { "inputs": { "text": "a red fox", "clip": ["12", 0] }, "class_type": "CLIPTextEncode" },
+            "11": { "inputs": { "text": "in the snow", "clip": ["12", 0] }, "class_type": "CLIPTextEncode" }
+        }"#;
+
+        let workflow = parse_comfyui_workflow(json).unwrap();
+        assert_eq!(workflow.readable_prompt, Some("a red fox, in the snow".to_string()));
+        assert_eq!(workflow.negative_prompt, Some("lowres".to_string()));
+        assert_eq!(workflow.model, Some("flux1-dev.safetensors".to_string()));
+        assert_eq!(workflow.seed, Some("42".to_string()));
+        assert_eq!(workflow.steps, Some("25".to_string()));
+        assert_eq!(workflow.sampler, Some("euler".to_string()));
+        assert_eq!(workflow.width, Some("1024".to_string()));
+    }
+
+    #[test]
+    fn test_parse_comfyui_pipeline_stages() {
+        // Base KSamplerAdvanced hands its latent to a refiner, then a model upscale runs on the result
+        let json = r#"{
+            "10": {
+                "inputs": {
+                    "add_noise": "enable", "noise_seed": 5, "steps": 25, "cfg": 7.5,
+                    "sampler_name": "euler", "scheduler": "normal", "start_at_step": 0, "end_at_step": 20,
+                    "return_with_leftover_noise": "enable",
+                    "model": ["4", 0], "positive": ["6", 0], "negative": ["7", 0], "latent_image": ["5", 0]
+                },
+                "class_type": "KSamplerAdvanced"
+            },
+            "11": {
+                "inputs": {
+                    "add_noise": "disable", "noise_seed": 5, "steps": 25, "cfg": 7.5,
+                    "sampler_name": "euler", "scheduler": "normal", "start_at_step": 20, "end_at_step": 10000,
+                    "return_with_leftover_noise": "disable",
+                    "model": ["12", 0], "positive": ["6", 0], "negative": ["7", 0], "latent_image": ["10", 0]
+                },
+                "class_type": "KSamplerAdvanced"
+            },
+            "4": { "inputs": { "ckpt_name": "sd_xl_base_1.0.safetensors" }, "class_type": "CheckpointLoaderSimple" },
+            "12": { "inputs": { "ckpt_name": "sd_xl_refiner_1.0.safetensors" }, "class_type": "CheckpointLoaderSimple" },
+            "5": { "inputs": { "width": 1024, "height": 1024, "batch_size": 1 }, "class_type": "EmptyLatentImage" },
+            "6": { "inputs": { "text": "a misty harbor", "clip": ["4", 1] }, "class_type": "CLIPTextEncode" },
+            "7": { "inputs": { "text": "text", "clip": ["4", 1] }, "class_type": "CLIPTextEncode" },
+            "13": { "inputs": { "samples": ["11", 0], "vae": ["4", 2] }, "class_type": "VAEDecode" },
+            "14": { "inputs": { "model_name": "4x-UltraSharp.pth" }, "class_type": "UpscaleModelLoader" },
+            "15": { "inputs": { "upscale_model": ["14", 0], "image": ["13", 0] }, "class_type": "ImageUpscaleWithModel" }
+        }"#;
+
+        let workflow = parse_comfyui_workflow(json).unwrap();
+        assert_eq!(workflow.model, Some("sd_xl_base_1.0.safetensors".to_string()));
+
+        let kinds: Vec<&str> = workflow.stages.iter().map(|s| s.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["base", "refiner", "upscale"]);
+        assert_eq!(workflow.stages[0].end_step, Some("20".to_string()));
+        assert_eq!(workflow.stages[1].model, Some("sd_xl_refiner_1.0.safetensors".to_string()));
+        assert_eq!(workflow.stages[1].start_step, Some("20".to_string()));
+        assert_eq!(workflow.stages[1].size, Some("1024x1024".to_string()));
+        assert_eq!(workflow.stages[2].upscaler, Some("4x-UltraSharp.pth".to_string()));
+    }
+
+    #[test]
+    fn test_parse_comfyui_hires_sampler_with_lower_node_id() {
+        // The hires pass (node 3) is visited before the base sampler (node 9) but reuses its model
+        let json = r#"{
+            "3": {
+                "inputs": {
+                    "seed": 8, "steps": 12, "cfg": 6, "sampler_name": "euler", "scheduler": "normal", "denoise": 0.5,
+                    "model": ["4", 0], "positive": ["6", 0], "negative": ["7", 0], "latent_image": ["10", 0]
+                },
+                "class_type": "KSampler"
+            },
+            "9": {
+                "inputs": {
+                    "seed": 8, "steps": 25, "cfg": 6, "sampler_name": "euler", "scheduler": "normal", "denoise": 1,
+                    "model": ["4", 0], "positive": ["6", 0], "negative": ["7", 0], "latent_image": ["5", 0]
+                },
+                "class_type": "KSampler"
+            },
+            "10": {
+                "inputs": { "samples": ["9", 0], "upscale_method": "nearest-exact", "scale_by": 1.5 },
+                "class_type": "LatentUpscaleBy"
+            },
+            "4": { "inputs": { "ckpt_name": "dreamshaper_8.safetensors" }, "class_type": "CheckpointLoaderSimple" },
+            "5": { "inputs": { "width": 512, "height": 768, "batch_size": 1 }, "class_type": "EmptyLatentImage" },
+            "6": { "inputs": { "text": "a lighthouse at dusk", "clip": ["4", 1] }, "class_type": "CLIPTextEncode" },
+            "7": { "inputs": { "text": "blurry", "clip": ["4", 1] }, "class_type": "CLIPTextEncode" }
+        }"#;
+
+        let workflow = parse_comfyui_workflow(json).unwrap();
+        let kinds: Vec<&str> = workflow.stages.iter().map(|s| s.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["base", "hires_fix"]);
+        assert_eq!(workflow.stages[0].steps, Some("25".to_string()));
+        assert_eq!(workflow.stages[1].denoise, Some("0.5".to_string()));
+    }
+}
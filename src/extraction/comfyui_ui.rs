@@ -0,0 +1,413 @@
+use crate::extraction::ExtractedMetadata;
+use crate::extraction::comfyui::{fill_missing_from_workflow, parse_comfyui_graph, ComfyUIWorkflow};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// ComfyUI UI-format workflow (the `workflow` PNG chunk): the editor's view of the graph,
+/// with `nodes`/`links` arrays, positional widget values, groups and notes.
+#[derive(Debug, Clone)]
+pub struct ComfyUIUiWorkflow {
+    pub nodes: Vec<UiNode>,
+    /// `[link_id, origin_id, origin_slot, target_id, target_slot, type]`
+    pub links: Vec<UiLink>,
+    pub groups: Vec<String>,
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UiNode {
+    pub id: String,
+    pub node_type: String,
+    /// User-assigned title, if it differs from the node type
+    pub title: Option<String>,
+    /// 0 = always, `MODE_MUTED` or `MODE_BYPASSED`
+    pub mode: u64,
+    pub inputs: Vec<UiInput>,
+    pub widgets_values: Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct UiInput {
+    pub name: String,
+    pub link: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UiLink {
+    pub id: u64,
+    pub origin_id: String,
+    pub origin_slot: u64,
+    /// Data type carried by the link (`MODEL`, `CONDITIONING`, ...)
+    pub link_type: Option<String>,
+}
+
+/// Node mode of muted nodes: they don't run, and nothing downstream of them does either
+pub const MODE_MUTED: u64 = 2;
+/// Node mode of bypassed nodes: each input is passed through to the output of the same type
+pub const MODE_BYPASSED: u64 = 4;
+
+/// Node types whose only widget is free text (notes and primitive string nodes)
+const NOTE_TYPES: &[&str] = &["Note", "MarkdownNote"];
+
+/// Widget names, in `widgets_values` order, for common built-in nodes. Seed widgets are
+/// followed by the UI-only "control_after_generate" value, which is why some entries skip a slot.
+fn widget_names(node_type: &str) -> Option<&'static [&'static str]> {
+    let names: &[&str] = match node_type {
+        "KSampler" => &["seed", "control_after_generate", "steps", "cfg", "sampler_name", "scheduler", "denoise"],
+        "KSamplerAdvanced" => &[
+            "add_noise", "noise_seed", "control_after_generate", "steps", "cfg", "sampler_name",
+            "scheduler", "start_at_step", "end_at_step", "return_with_leftover_noise",
+        ],
+        "CLIPTextEncode" | "CLIPTextEncodeFlux" => &["text"],
+        "CLIPTextEncodeSDXL" => &[
+            "width", "height", "crop_w", "crop_h", "target_width", "target_height", "text_g", "text_l",
+        ],
+        "CLIPTextEncodeSDXLRefiner" => &["ascore", "width", "height", "text"],
+        "CheckpointLoaderSimple" => &["ckpt_name"],
+        "UNETLoader" => &["unet_name", "weight_dtype"],
+        "LoraLoader" => &["lora_name", "strength_model", "strength_clip"],
+        "LoraLoaderModelOnly" => &["lora_name", "strength_model"],
+        "EmptyLatentImage" | "EmptySD3LatentImage" => &["width", "height", "batch_size"],
+        "LatentUpscale" => &["upscale_method", "width", "height", "crop"],
+        "LatentUpscaleBy" => &["upscale_method", "scale_by"],
+        "KSamplerSelect" => &["sampler_name"],
+        "BasicScheduler" => &["scheduler", "steps", "denoise"],
+        "RandomNoise" => &["noise_seed", "control_after_generate"],
+        "CFGGuider" => &["cfg"],
+        "PrimitiveNode" => &["value", "control_after_generate"],
+        "Note" | "MarkdownNote" => &["text"],
+        _ => return None,
+    };
+    Some(names)
+}
+
+pub fn parse_comfyui_ui_workflow(json_str: &str) -> anyhow::Result<ComfyUIUiWorkflow> {
+    let json: Value = serde_json::from_str(json_str)?;
+    let raw_nodes = json
+        .get("nodes")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| anyhow::anyhow!("Not a ComfyUI UI workflow: missing nodes array"))?;
+
+    let mut nodes = Vec::new();
+    let mut notes = Vec::new();
+
+    for raw in raw_nodes {
+        let id = match raw.get("id") {
+            Some(Value::Number(n)) => n.to_string(),
+            Some(Value::String(s)) => s.clone(),
+            _ => continue,
+        };
+        let node_type = raw.get("type").and_then(|v| v.as_str()).unwrap_or("").to_string();
+        let title = raw
+            .get("title")
+            .and_then(|v| v.as_str())
+            .filter(|t| !t.is_empty() && *t != node_type)
+            .map(|t| t.to_string());
+        let inputs = raw
+            .get("inputs")
+            .and_then(|v| v.as_array())
+            .map(|inputs| {
+                inputs
+                    .iter()
+                    .map(|input| UiInput {
+                        name: input.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+                        link: input.get("link").and_then(|v| v.as_u64()),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let widgets_values = raw.get("widgets_values").cloned().unwrap_or(Value::Null);
+
+        if NOTE_TYPES.contains(&node_type.as_str()) {
+            if let Some(text) = widgets_values.get(0).and_then(|v| v.as_str()) {
+                if !text.trim().is_empty() {
+                    notes.push(text.trim().to_string());
+                }
+            }
+        }
+
+        nodes.push(UiNode {
+            id,
+            node_type,
+            title,
+            mode: raw.get("mode").and_then(|v| v.as_u64()).unwrap_or(0),
+            inputs,
+            widgets_values,
+        });
+    }
+
+    let links = json
+        .get("links")
+        .and_then(|v| v.as_array())
+        .map(|links| links.iter().filter_map(parse_link).collect())
+        .unwrap_or_default();
+
+    let groups = json
+        .get("groups")
+        .and_then(|v| v.as_array())
+        .map(|groups| {
+            groups
+                .iter()
+                .filter_map(|g| g.get("title").and_then(|v| v.as_str()))
+                .filter(|t| !t.trim().is_empty())
+                .map(|t| t.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(ComfyUIUiWorkflow { nodes, links, groups, notes })
+}
+
+/// Links are usually arrays, but newer frontends may serialize them as objects
+fn parse_link(value: &Value) -> Option<UiLink> {
+    let id_str = |v: &Value| match v {
+        Value::Number(n) => Some(n.to_string()),
+        Value::String(s) => Some(s.clone()),
+        _ => None,
+    };
+
+    if let Some(arr) = value.as_array() {
+        return Some(UiLink {
+            id: arr.first()?.as_u64()?,
+            origin_id: id_str(arr.get(1)?)?,
+            origin_slot: arr.get(2)?.as_u64()?,
+            link_type: arr.get(5).and_then(|v| v.as_str()).map(String::from),
+        });
+    }
+
+    Some(UiLink {
+        id: value.get("id")?.as_u64()?,
+        origin_id: id_str(value.get("origin_id")?)?,
+        origin_slot: value.get("origin_slot")?.as_u64()?,
+        link_type: value.get("type").and_then(|v| v.as_str()).map(String::from),
+    })
+}
+
+impl ComfyUIUiWorkflow {
+    /// Rebuild an API-format graph (`{ id: { class_type, inputs } }`) from the UI graph, so the
+    /// same link-following parser can be used on both chunks. Reroute and bypassed nodes are
+    /// collapsed, muted nodes are dropped and widget values are named using the known widget layouts.
+    pub fn to_api_graph(&self) -> Value {
+        let links: HashMap<u64, &UiLink> = self.links.iter().map(|l| (l.id, l)).collect();
+        let nodes_by_id: HashMap<&str, &UiNode> = self.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+
+        // Follow a link back through any Reroute or bypassed nodes to the real producer
+        let resolve_link = |link_id: u64| -> Option<Value> {
+            let mut link = *links.get(&link_id)?;
+            for _ in 0..self.links.len() {
+                match nodes_by_id.get(link.origin_id.as_str()) {
+                    Some(origin) if origin.mode == MODE_MUTED => return None,
+                    Some(origin) if origin.node_type == "Reroute" => {
+                        let upstream = origin.inputs.first().and_then(|i| i.link)?;
+                        link = *links.get(&upstream)?;
+                    }
+                    Some(origin) if origin.mode == MODE_BYPASSED => {
+                        link = origin
+                            .inputs
+                            .iter()
+                            .filter_map(|input| links.get(&input.link?).copied())
+                            .find(|upstream| upstream.link_type == link.link_type)?;
+                    }
+                    _ => break,
+                }
+            }
+            Some(json!([link.origin_id, link.origin_slot]))
+        };
+
+        let mut graph = Map::new();
+        for node in &self.nodes {
+            if node.node_type == "Reroute"
+                || NOTE_TYPES.contains(&node.node_type.as_str())
+                || node.mode == MODE_MUTED
+                || node.mode == MODE_BYPASSED
+            {
+                continue;
+            }
+
+            let mut inputs = Map::new();
+            match &node.widgets_values {
+                // Some custom nodes already store widgets by name
+                Value::Object(named) => {
+                    inputs.extend(named.clone());
+                }
+                Value::Array(values) => {
+                    let names = widget_names(&node.node_type);
+                    for (index, value) in values.iter().enumerate() {
+                        let name = match names.and_then(|n| n.get(index)) {
+                            Some(name) => name.to_string(),
+                            // A lone string widget on an unknown node is almost always its text
+                            None if values.len() == 1 && value.is_string() => "text".to_string(),
+                            None => format!("widget_{}", index),
+                        };
+                        inputs.insert(name, value.clone());
+                    }
+                }
+                _ => {}
+            }
+
+            // Linked inputs (including widgets converted to inputs) override widget values
+            for input in &node.inputs {
+                if let Some(link) = input.link.and_then(resolve_link) {
+                    inputs.insert(input.name.clone(), link);
+                }
+            }
+
+            graph.insert(
+                node.id.clone(),
+                json!({ "class_type": node.node_type, "inputs": inputs }),
+            );
+        }
+
+        Value::Object(graph)
+    }
+
+    /// Custom node titles as `id: title` lines
+    pub fn node_titles(&self) -> Vec<String> {
+        self.nodes
+            .iter()
+            .filter_map(|n| n.title.as_ref().map(|t| format!("{}: {}", n.id, t)))
+            .collect()
+    }
+
+    pub fn to_workflow(&self) -> ComfyUIWorkflow {
+        parse_comfyui_graph(&self.to_api_graph())
+    }
+}
+
+/// Use a UI-format workflow to fill whatever the API graph (or other chunks) didn't provide,
+/// and record group names, notes and node titles in `other`
+pub fn apply_comfyui_ui_to_metadata(json_str: &str, metadata: &mut ExtractedMetadata) {
+    let ui = match parse_comfyui_ui_workflow(json_str) {
+        Ok(ui) => ui,
+        Err(_) => return,
+    };
+
+    if !ui.nodes.is_empty() {
+        metadata.generator.get_or_insert_with(|| "comfyui".to_string());
+    }
+    fill_missing_from_workflow(ui.to_workflow(), metadata);
+
+    if !ui.groups.is_empty() {
+        metadata.other.push(("comfyui_groups".to_string(), ui.groups.join("\n")));
+    }
+    if !ui.notes.is_empty() {
+        metadata.other.push(("comfyui_notes".to_string(), ui.notes.join("\n\n")));
+    }
+    let titles = ui.node_titles();
+    if !titles.is_empty() {
+        metadata.other.push(("comfyui_node_titles".to_string(), titles.join("\n")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UI_WORKFLOW: &str = r#"{
+        "last_node_id": 9,
+        "last_link_id": 9,
+        "nodes": [
+            { "id": 3, "type": "KSampler", "title": "Base Pass", "mode": 0,
+              "inputs": [
+                  { "name": "model", "type": "MODEL", "link": 1 },
+                  { "name": "positive", "type": "CONDITIONING", "link": 4 },
+                  { "name": "negative", "type": "CONDITIONING", "link": 6 },
+                  { "name": "latent_image", "type": "LATENT", "link": 2 }
+              ],
+              "widgets_values": [156680208700286, "randomize", 20, 8, "euler", "normal", 1] },
+            { "id": 4, "type": "CheckpointLoaderSimple", "mode": 0, "inputs": [],
+              "widgets_values": ["v1-5-pruned-emaonly.safetensors"] },
+            { "id": 5, "type": "EmptyLatentImage", "mode": 0, "inputs": [],
+              "widgets_values": [512, 768, 1] },
+            { "id": 6, "type": "CLIPTextEncode", "mode": 0,
+              "inputs": [{ "name": "clip", "type": "CLIP", "link": 3 }],
+              "widgets_values": ["a cozy cabin in the woods, snow"] },
+            { "id": 7, "type": "CLIPTextEncode", "mode": 0,
+              "inputs": [{ "name": "clip", "type": "CLIP", "link": 5 }],
+              "widgets_values": ["text, watermark"] },
+            { "id": 8, "type": "Reroute", "mode": 0,
+              "inputs": [{ "name": "", "type": "*", "link": 7 }],
+              "outputs": [{ "name": "", "type": "CONDITIONING", "links": [4] }] },
+            { "id": 9, "type": "Note", "mode": 0, "inputs": [],
+              "widgets_values": ["Upscale with 4x-UltraSharp afterwards"] }
+        ],
+        "links": [
+            [1, 4, 0, 3, 0, "MODEL"],
+            [2, 5, 0, 3, 3, "LATENT"],
+            [3, 4, 1, 6, 0, "CLIP"],
+            [4, 8, 0, 3, 1, "CONDITIONING"],
+            [5, 4, 1, 7, 0, "CLIP"],
+            [6, 7, 0, 3, 2, "CONDITIONING"],
+            [7, 6, 0, 8, 0, "CONDITIONING"]
+        ],
+        "groups": [{ "title": "Base Model", "bounding": [0, 0, 100, 100] }],
+        "version": 0.4
+    }"#;
+
+    #[test]
+    fn test_parse_ui_workflow_annotations() {
+        let ui = parse_comfyui_ui_workflow(UI_WORKFLOW).unwrap();
+        assert_eq!(ui.nodes.len(), 7);
+        assert_eq!(ui.groups, vec!["Base Model".to_string()]);
+        assert_eq!(ui.notes, vec!["Upscale with 4x-UltraSharp afterwards".to_string()]);
+        assert_eq!(ui.node_titles(), vec!["3: Base Pass".to_string()]);
+    }
+
+    #[test]
+    fn test_ui_workflow_as_fallback() {
+        let mut metadata = ExtractedMetadata::empty();
+        apply_comfyui_ui_to_metadata(UI_WORKFLOW, &mut metadata);
+
+        assert_eq!(metadata.prompt, Some("a cozy cabin in the woods, snow".to_string()));
+        assert_eq!(metadata.negative_prompt, Some("text, watermark".to_string()));
+        assert_eq!(metadata.model, Some("v1-5-pruned-emaonly.safetensors".to_string()));
+        assert_eq!(metadata.seed, Some("156680208700286".to_string()));
+        assert_eq!(metadata.steps, Some("20".to_string()));
+        assert_eq!(metadata.sampler, Some("euler".to_string()));
+        assert_eq!(metadata.size, Some("512x768".to_string()));
+        assert!(metadata.other.iter().any(|(k, v)| k == "comfyui_groups" && v == "Base Model"));
+    }
+
+    #[test]
+    fn test_muted_and_bypassed_nodes() {
+        let mut json: Value = serde_json::from_str(UI_WORKFLOW).unwrap();
+        let nodes = json["nodes"].as_array_mut().unwrap();
+        // An abandoned branch, muted, listed before the real sampler
+        nodes.insert(0, json!({ "id": 1, "type": "KSampler", "mode": 2,
+            "inputs": [{ "name": "positive", "type": "CONDITIONING", "link": 20 }],
+            "widgets_values": [1, "fixed", 4, 2, "lcm", "normal", 1] }));
+        nodes.insert(1, json!({ "id": 2, "type": "CLIPTextEncode", "mode": 2,
+            "inputs": [], "widgets_values": ["an abandoned idea"] }));
+        // A bypassed LoRA between the checkpoint and the sampler
+        nodes.push(json!({ "id": 10, "type": "LoraLoader", "mode": 4,
+            "inputs": [{ "name": "model", "type": "MODEL", "link": 21 }, { "name": "clip", "type": "CLIP", "link": 22 }],
+            "widgets_values": ["detail.safetensors", 1, 1] }));
+        nodes[2]["inputs"][0]["link"] = json!(23);
+        let links = json["links"].as_array_mut().unwrap();
+        links.extend([
+            json!([20, 2, 0, 1, 1, "CONDITIONING"]),
+            json!([21, 4, 0, 10, 0, "MODEL"]),
+            json!([22, 4, 1, 10, 1, "CLIP"]),
+            json!([23, 10, 0, 3, 0, "MODEL"]),
+        ]);
+
+        let ui = parse_comfyui_ui_workflow(&json.to_string()).unwrap();
+        let graph = ui.to_api_graph();
+        assert!(graph.get("1").is_none() && graph.get("2").is_none() && graph.get("10").is_none());
+        assert_eq!(graph["3"]["inputs"]["model"], json!(["4", 0]));
+
+        let workflow = ui.to_workflow();
+        assert_eq!(workflow.readable_prompt, Some("a cozy cabin in the woods, snow".to_string()));
+        assert_eq!(workflow.model, Some("v1-5-pruned-emaonly.safetensors".to_string()));
+        assert_eq!(workflow.lora, None);
+    }
+
+    #[test]
+    fn test_ui_workflow_does_not_override_api_values() {
+        let mut metadata = ExtractedMetadata::empty();
+        metadata.prompt = Some("from the API graph".to_string());
+        apply_comfyui_ui_to_metadata(UI_WORKFLOW, &mut metadata);
+
+        assert_eq!(metadata.prompt, Some("from the API graph".to_string()));
+    }
+}
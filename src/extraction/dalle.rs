@@ -0,0 +1,62 @@
+use crate::extraction::generator::{find_field, generator_from_software, software_tags};
+use crate::extraction::ExtractedMetadata;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// Fields that may hold the prompt of a DALL-E / ChatGPT image, in order of preference.
+/// OpenAI itself only signs the image (C2PA); prompts come from tools that re-save it.
+const PROMPT_KEYS: &[&str] = &[
+    "revised_prompt",
+    "prompt",
+    "dc:description",
+    "Description",
+    "ImageDescription",
+    "dc:title",
+    "c2pa.title",
+];
+/// Titles that are just the downloaded file name
+static FILE_NAME_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)^[\w\-. ]+\.(png|jpe?g|webp)$").unwrap());
+
+/// Recognize DALL-E / ChatGPT images from their C2PA and XMP tool hints, and take any
+/// embedded prompt the format parsers didn't already use
+pub fn detect_dalle(metadata: &mut ExtractedMetadata) {
+    let is_dalle = match metadata.generator.as_deref() {
+        Some(generator) => generator == "dalle",
+        None => software_tags(metadata).iter().any(|tag| generator_from_software(tag) == Some("dalle")),
+    };
+    if !is_dalle {
+        return;
+    }
+
+    metadata.generator = Some("dalle".to_string());
+    if metadata.prompt.is_none() {
+        metadata.prompt = PROMPT_KEYS
+            .iter()
+            .filter_map(|key| find_field(metadata, key))
+            .find(|value| !value.trim().is_empty() && !FILE_NAME_RE.is_match(value.trim()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extraction::MetadataEntry;
+
+    #[test]
+    fn test_dalle_from_c2pa_with_prompt() {
+        let mut metadata = ExtractedMetadata::empty();
+        metadata.entries = vec![
+            MetadataEntry::new("c2pa.claim_generator", "ChatGPT", "provenance"),
+            MetadataEntry::new("c2pa.title", "image.png", "provenance"),
+            MetadataEntry::new("dc:description", "an isometric tiny library", "xmp"),
+        ];
+        detect_dalle(&mut metadata);
+        assert_eq!(metadata.generator, Some("dalle".to_string()));
+        assert_eq!(metadata.prompt, Some("an isometric tiny library".to_string()));
+
+        let mut unsigned = ExtractedMetadata::empty();
+        unsigned.other = vec![("Software".to_string(), "GIMP 2.10".to_string())];
+        detect_dalle(&mut unsigned);
+        assert_eq!(unsigned.generator, None);
+    }
+}
@@ -0,0 +1,231 @@
+use crate::extraction::{ExtractedMetadata, SourceLocation};
+use ::exif::{Exif, Reader, Tag, Value};
+use encoding_rs::{ISO_2022_JP, SHIFT_JIS};
+use std::path::Path;
+
+/// Prefix of EXIF data in JPEG APP1 segments; WebP EXIF chunks sometimes carry it too
+pub const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+/// Parse a bare EXIF block (TIFF header onwards), as found in WebP `EXIF` chunks and
+/// ISOBMFF `Exif` items. A leading `Exif\0\0` is skipped.
+pub fn parse_exif_block(data: &[u8]) -> Option<Exif> {
+    let data = data.strip_prefix(EXIF_HEADER).unwrap_or(data);
+    Reader::new().read_raw(data.to_vec()).ok()
+}
+
+/// A TIFF file is an EXIF block in its own right
+pub fn extract_tiff_metadata<P: AsRef<Path>>(path: P) -> anyhow::Result<ExtractedMetadata> {
+    let data = std::fs::read(path.as_ref())?;
+    Ok(extract_tiff_metadata_from_bytes(&data))
+}
+
+pub fn extract_tiff_metadata_from_bytes(data: &[u8]) -> ExtractedMetadata {
+    let mut metadata = ExtractedMetadata::empty();
+    if let Some(exif) = parse_exif_block(data) {
+        apply_exif_to_metadata(&exif, &SourceLocation::new("tiff", "", Some(0), "exif"), &mut metadata);
+    }
+    metadata
+}
+
+/// Map EXIF fields into metadata, the same way for every container format. `origin` is the
+/// EXIF block's location; each field is recorded under its tag name.
+pub fn apply_exif_to_metadata(exif: &Exif, origin: &SourceLocation, metadata: &mut ExtractedMetadata) {
+    // Extract common EXIF fields that might contain prompts
+    for field in exif.fields() {
+        // Display gives the tag name ("UserComment"); Debug would give "Tag(Exif, 37510)"
+        let tag_str = field.tag.to_string();
+        let value_str = match (field.tag, &field.value) {
+            // display_as escapes the raw bytes; decode the charset header instead
+            (Tag::UserComment, Value::Undefined(raw, _)) => {
+                decode_user_comment(raw, !exif.little_endian()).unwrap_or_default()
+            }
+            _ => field.value.display_as(field.tag).to_string(),
+        };
+
+        // Clean up value (remove quotes if present)
+        let value = value_str.strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .unwrap_or(&value_str)
+            .to_string();
+
+        if value.is_empty() {
+            continue;
+        }
+
+        // Match on tag string since Tag enum might not have all variants
+        match tag_str.as_str() {
+            "ImageDescription" => {
+                metadata.record_source(&origin.with_key(&tag_str), |m| {
+                    if m.prompt.is_none() {
+                        m.prompt = Some(value.clone());
+                    }
+                });
+                metadata.other.push(("ImageDescription".to_string(), value));
+            }
+            "UserComment" => {
+                // A1111 and Forge write the full parameters string here
+                metadata.record_source(&origin.with_key(&tag_str).with_parser("a1111"), |m| {
+                    if parse_potential_parameters(&value, m) {
+                        m.parameters = Some(value.clone());
+                    }
+                });
+                metadata.other.push(("UserComment".to_string(), value));
+            }
+            "Software" => {
+                // The tool that wrote the file, not the model; used for generator detection
+                metadata.other.push(("Software".to_string(), value));
+            }
+            _ => {
+                // Artist, DateTime* and everything else are kept as-is
+                metadata.other.push((tag_str, value));
+            }
+        }
+    }
+}
+
+/// Returns whether the text looked like a parameters string and was parsed
+pub(crate) fn parse_potential_parameters(text: &str, metadata: &mut ExtractedMetadata) -> bool {
+    // Check if the text looks like a Stable Diffusion parameters string (with or without
+    // a settings line)
+    let is_parameters = text.contains("Steps:") || text.contains("CFG scale:") || text.contains("Seed:") || text.contains("Negative prompt:");
+    if is_parameters {
+        crate::extraction::png::parse_parameters_string(text, metadata);
+    }
+    is_parameters
+}
+
+/// Decode an EXIF `UserComment` value: an 8-byte character code followed by the text.
+/// `big_endian` is the TIFF byte order, used for UNICODE text without a BOM when the
+/// text itself gives no hint.
+pub fn decode_user_comment(raw: &[u8], big_endian: bool) -> Option<String> {
+    if raw.len() < 8 {
+        return decode_undefined(raw);
+    }
+    let (header, body) = raw.split_at(8);
+
+    let text = match header {
+        b"ASCII\0\0\0" => String::from_utf8_lossy(body).to_string(),
+        b"UNICODE\0" => decode_utf16(body, big_endian),
+        b"JIS\0\0\0\0\0" => {
+            // ISO-2022-JP escapes with ESC; otherwise writers use Shift_JIS
+            let encoding = if body.contains(&0x1b) { ISO_2022_JP } else { SHIFT_JIS };
+            encoding.decode_without_bom_handling(body).0.to_string()
+        }
+        // Undefined (all zeros), or a writer that skipped the header altogether
+        b"\0\0\0\0\0\0\0\0" => decode_undefined(body)?,
+        _ => decode_undefined(raw)?,
+    };
+
+    let text = text.trim_end_matches(['\0', ' ']).to_string();
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// UTF-16 in either byte order. A BOM wins; otherwise the byte order with more
+/// zero high bytes is chosen, since A1111 (piexif) always writes big-endian even
+/// inside little-endian EXIF.
+fn decode_utf16(body: &[u8], big_endian: bool) -> String {
+    let (body, big_endian) = match body {
+        [0xfe, 0xff, rest @ ..] => (rest, true),
+        [0xff, 0xfe, rest @ ..] => (rest, false),
+        _ => {
+            let zeros_at = |parity: usize| body.iter().skip(parity).step_by(2).filter(|b| **b == 0).count();
+            let (even, odd) = (zeros_at(0), zeros_at(1));
+            let big_endian = if even == odd { big_endian } else { even > odd };
+            (body, big_endian)
+        }
+    };
+
+    let units: Vec<u16> = body
+        .chunks_exact(2)
+        .map(|pair| {
+            if big_endian {
+                u16::from_be_bytes([pair[0], pair[1]])
+            } else {
+                u16::from_le_bytes([pair[0], pair[1]])
+            }
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// No declared charset: UTF-8 if valid, otherwise Latin-1
+fn decode_undefined(body: &[u8]) -> Option<String> {
+    let body = match body.iter().rposition(|b| *b != 0) {
+        Some(end) => &body[..=end],
+        None => return None,
+    };
+    Some(match std::str::from_utf8(body) {
+        Ok(text) => text.to_string(),
+        Err(_) => body.iter().map(|&b| b as char).collect(),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Little-endian TIFF data whose only field is a UserComment in the Exif IFD
+    pub(crate) fn tiff_with_user_comment(comment: &[u8]) -> Vec<u8> {
+        let mut tiff = b"II*\0\x08\0\0\0".to_vec();
+        // IFD0: one entry, ExifIFDPointer -> offset 26
+        tiff.extend_from_slice(&1u16.to_le_bytes());
+        tiff.extend_from_slice(&0x8769u16.to_le_bytes());
+        tiff.extend_from_slice(&4u16.to_le_bytes());
+        tiff.extend_from_slice(&1u32.to_le_bytes());
+        tiff.extend_from_slice(&26u32.to_le_bytes());
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        // Exif IFD: one entry, UserComment (UNDEFINED) -> offset 44
+        tiff.extend_from_slice(&1u16.to_le_bytes());
+        tiff.extend_from_slice(&0x9286u16.to_le_bytes());
+        tiff.extend_from_slice(&7u16.to_le_bytes());
+        tiff.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        tiff.extend_from_slice(&44u32.to_le_bytes());
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        tiff.extend_from_slice(comment);
+        tiff
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|u| if big_endian { u.to_be_bytes() } else { u.to_le_bytes() })
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_unicode_user_comment() {
+        let text = "a castle at night\nSteps: 20, Sampler: Euler a, Seed: 42";
+
+        // piexif writes big-endian regardless of the TIFF byte order
+        let mut raw = b"UNICODE\0".to_vec();
+        raw.extend(utf16(text, true));
+        assert_eq!(decode_user_comment(&raw, false), Some(text.to_string()));
+
+        let mut raw = b"UNICODE\0".to_vec();
+        raw.extend(utf16(text, false));
+        assert_eq!(decode_user_comment(&raw, true), Some(text.to_string()));
+
+        // Non-Latin text gives no zero-byte hint, so the TIFF byte order decides
+        let mut raw = b"UNICODE\0".to_vec();
+        raw.extend(utf16("夕焼け", false));
+        assert_eq!(decode_user_comment(&raw, false), Some("夕焼け".to_string()));
+    }
+
+    #[test]
+    fn test_decode_other_charsets() {
+        assert_eq!(decode_user_comment(b"ASCII\0\0\0a red fox\0", false), Some("a red fox".to_string()));
+
+        let mut jis = b"JIS\0\0\0\0\0".to_vec();
+        jis.extend(SHIFT_JIS.encode("猫").0.iter());
+        assert_eq!(decode_user_comment(&jis, false), Some("猫".to_string()));
+
+        let mut undefined = vec![0u8; 8];
+        undefined.extend("café".as_bytes());
+        assert_eq!(decode_user_comment(&undefined, false), Some("café".to_string()));
+
+        assert_eq!(decode_user_comment(&[0u8; 16], false), None);
+    }
+}
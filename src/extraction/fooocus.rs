@@ -0,0 +1,223 @@
+use crate::extraction::{ExtractedMetadata, GenerationStage};
+use crate::extraction::generator::value_to_string;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+/// Quoted items of a Python list repr, e.g. `['Fooocus V2', 'Fooocus Sharp']`
+static PY_LIST_ITEM_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"'([^']*)'|"([^"]*)""#).unwrap());
+static NUMBER_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\d+").unwrap());
+
+/// Whether a JSON `parameters` value was written by Fooocus rather than being a ComfyUI graph.
+/// Newer versions add `metadata_scheme`; older logs use title-cased keys like `Base Model`.
+pub fn is_fooocus_parameters(params: &Value) -> bool {
+    let Some(object) = params.as_object() else {
+        return false;
+    };
+    let version = field(params, &["version", "Version"]).unwrap_or_default();
+    object.contains_key("metadata_scheme")
+        || version.starts_with("Fooocus")
+        || (field(params, &["base_model", "Base Model"]).is_some()
+            && field(params, &["performance", "Performance"]).is_some())
+}
+
+/// Map Fooocus JSON parameters into metadata
+pub fn apply_fooocus_to_metadata(params: &Value, metadata: &mut ExtractedMetadata) {
+    metadata.generator = Some("fooocus".to_string());
+
+    metadata.prompt = field(params, &["prompt", "Prompt"]);
+    metadata.negative_prompt = field(params, &["negative_prompt", "Negative Prompt"]);
+    metadata.model = field(params, &["base_model", "Base Model"]);
+    metadata.sampler = field(params, &["sampler", "Sampler"]);
+    metadata.cfg_scale = field(params, &["guidance_scale", "Guidance Scale"]);
+    metadata.seed = field(params, &["seed", "Seed"]);
+
+    let performance = field(params, &["performance", "Performance"]);
+    // Older versions only record the performance preset, which fixes the step count
+    metadata.steps = field(params, &["steps", "Steps"]).or_else(|| {
+        let steps = match performance.as_deref()? {
+            "Quality" => 60,
+            "Speed" => 30,
+            "Extreme Speed" => 8,
+            "Lightning" | "Hyper-SD" => 4,
+            _ => return None,
+        };
+        Some(steps.to_string())
+    });
+
+    // "(1152, 896)" or "1152×896 ..."
+    if let Some(resolution) = field(params, &["resolution", "Resolution"]) {
+        let dims: Vec<&str> = NUMBER_RE.find_iter(&resolution).map(|m| m.as_str()).collect();
+        if dims.len() >= 2 {
+            metadata.size = Some(format!("{}x{}", dims[0], dims[1]));
+        }
+    }
+
+    if let Some(performance) = performance {
+        metadata.other.push(("performance".to_string(), performance));
+    }
+
+    let styles = fooocus_styles(params);
+    if !styles.is_empty() {
+        metadata.other.push(("styles".to_string(), styles.join(", ")));
+    }
+
+    let loras = fooocus_loras(params);
+    if !loras.is_empty() {
+        metadata.other.push(("lora".to_string(), loras.join(", ")));
+    }
+
+    for (key, name) in [
+        ("prompt_expansion", "prompt_expansion"),
+        ("Fooocus V2 Expansion", "prompt_expansion"),
+        ("sharpness", "sharpness"),
+        ("Sharpness", "sharpness"),
+        ("adm_guidance", "adm_guidance"),
+        ("ADM Guidance", "adm_guidance"),
+        ("vae", "vae"),
+        ("version", "version"),
+        ("Version", "version"),
+    ] {
+        if let Some(value) = field(params, &[key]) {
+            metadata.other.push((name.to_string(), value));
+        }
+    }
+
+    let scheduler = field(params, &["scheduler", "Scheduler"]);
+    let mut base = GenerationStage::new("base");
+    base.model = metadata.model.clone();
+    base.sampler = metadata.sampler.clone();
+    base.scheduler = scheduler.clone();
+    base.steps = metadata.steps.clone();
+    base.cfg_scale = metadata.cfg_scale.clone();
+    base.seed = metadata.seed.clone();
+    base.size = metadata.size.clone();
+    metadata.stages = vec![base.clone()];
+
+    if let Some(refiner) = field(params, &["refiner_model", "Refiner Model"]).filter(|m| m != "None") {
+        let mut stage = GenerationStage::new("refiner");
+        stage.model = Some(refiner);
+        stage.sampler = base.sampler.clone();
+        stage.scheduler = scheduler;
+        stage.steps = base.steps.clone();
+        stage.cfg_scale = base.cfg_scale.clone();
+        stage.seed = base.seed.clone();
+        stage.size = base.size.clone();
+        let total_steps = base.steps.as_deref().and_then(|s| s.parse::<f64>().ok());
+        let switch_at = field(params, &["refiner_switch", "Refiner Switch"]).and_then(|s| s.parse::<f64>().ok());
+        if let (Some(total), Some(switch_at)) = (total_steps, switch_at) {
+            stage.start_step = Some(((total * switch_at) as u64).to_string());
+            stage.end_step = base.steps.clone();
+        }
+        metadata.stages.push(stage);
+    }
+}
+
+/// Styles are a JSON array in some versions and a Python list repr string in others
+fn fooocus_styles(params: &Value) -> Vec<String> {
+    match params.get("styles").or_else(|| params.get("Styles")) {
+        Some(Value::Array(styles)) => styles.iter().filter_map(|s| s.as_str()).map(|s| s.to_string()).collect(),
+        Some(Value::String(styles)) => PY_LIST_ITEM_RE
+            .captures_iter(styles)
+            .filter_map(|c| c.get(1).or_else(|| c.get(2)))
+            .map(|m| m.as_str().to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// `loras: [[name, weight, hash?], ...]`, or `"LoRA 1": "name : weight"` in older logs
+fn fooocus_loras(params: &Value) -> Vec<String> {
+    if let Some(loras) = params.get("loras").and_then(|l| l.as_array()) {
+        return loras
+            .iter()
+            .filter_map(|entry| {
+                let entry = entry.as_array()?;
+                let name = entry.first()?.as_str()?;
+                Some(match entry.get(1) {
+                    Some(weight) => format!("{}:{}", name, value_to_string(weight)),
+                    None => name.to_string(),
+                })
+            })
+            .collect();
+    }
+
+    let mut loras = Vec::new();
+    for index in 1.. {
+        let Some(lora) = field(params, &[&format!("LoRA {}", index)]) else {
+            break;
+        };
+        loras.push(
+            lora.split_once(" : ")
+                .map(|(name, weight)| format!("{}:{}", name.trim(), weight.trim()))
+                .unwrap_or(lora),
+        );
+    }
+    loras
+}
+
+/// First non-empty value among alternative key spellings
+fn field(params: &Value, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .filter_map(|key| params.get(*key))
+        .filter(|v| !v.is_null())
+        .map(value_to_string)
+        .find(|s| !s.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fooocus_scheme_parameters() {
+        let params: Value = serde_json::from_str(r#"{
+            "adm_guidance": "(1.5, 0.8, 0.3)", "base_model": "juggernautXL_v8Rundiffusion.safetensors", "guidance_scale": 4,
+            "loras": [["sd_xl_offset_example-lora_1.0.safetensors", 0.1, "4852686128"]], "metadata_scheme": "fooocus",
+            "negative_prompt": "", "performance": "Speed", "prompt": "a cabin in the woods", "refiner_model": "None",
+            "refiner_switch": 0.5, "resolution": "(1152, 896)", "sampler": "dpmpp_2m_sde_gpu", "scheduler": "karras",
+            "seed": "1234567", "sharpness": 2, "steps": 30, "styles": "['Fooocus V2', 'Fooocus Enhance', 'Fooocus Sharp']",
+            "version": "Fooocus v2.3.1"
+        }"#).unwrap();
+        assert!(is_fooocus_parameters(&params));
+
+        let mut metadata = ExtractedMetadata::empty();
+        apply_fooocus_to_metadata(&params, &mut metadata);
+
+        assert_eq!(metadata.generator, Some("fooocus".to_string()));
+        assert_eq!(metadata.prompt, Some("a cabin in the woods".to_string()));
+        assert_eq!(metadata.negative_prompt, None);
+        assert_eq!(metadata.model, Some("juggernautXL_v8Rundiffusion.safetensors".to_string()));
+        assert_eq!(metadata.cfg_scale, Some("4".to_string()));
+        assert_eq!(metadata.size, Some("1152x896".to_string()));
+        assert!(metadata.other.contains(&("styles".to_string(), "Fooocus V2, Fooocus Enhance, Fooocus Sharp".to_string())));
+        assert!(metadata.other.contains(&("performance".to_string(), "Speed".to_string())));
+        assert!(metadata.other.contains(&("lora".to_string(), "sd_xl_offset_example-lora_1.0.safetensors:0.1".to_string())));
+        assert_eq!(metadata.stages.len(), 1);
+        assert_eq!(metadata.stages[0].scheduler, Some("karras".to_string()));
+    }
+
+    #[test]
+    fn test_fooocus_legacy_log_keys() {
+        let params: Value = serde_json::from_str(r#"{
+            "Prompt": "a fox", "Negative Prompt": "blurry", "Styles": ["Fooocus V2"], "Performance": "Extreme Speed",
+            "Resolution": "(1024, 1024)", "Base Model": "sd_xl_base_1.0.safetensors", "Refiner Model": "sd_xl_refiner_1.0.safetensors",
+            "Refiner Switch": 0.75, "Seed": 42, "LoRA 1": "add-detail.safetensors : 0.5"
+        }"#).unwrap();
+        assert!(is_fooocus_parameters(&params));
+
+        let mut metadata = ExtractedMetadata::empty();
+        apply_fooocus_to_metadata(&params, &mut metadata);
+
+        assert_eq!(metadata.steps, Some("8".to_string()));
+        assert!(metadata.other.contains(&("lora".to_string(), "add-detail.safetensors:0.5".to_string())));
+        assert_eq!(metadata.stages[1].kind, "refiner");
+        assert_eq!(metadata.stages[1].start_step, Some("6".to_string()));
+    }
+
+    #[test]
+    fn test_comfyui_graph_is_not_fooocus() {
+        let graph: Value = serde_json::from_str(r#"{"3": {"class_type": "KSampler", "inputs": {"seed": 1}}}"#).unwrap();
+        assert!(!is_fooocus_parameters(&graph));
+    }
+}
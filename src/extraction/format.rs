@@ -0,0 +1,149 @@
+use std::io::Read;
+use std::path::Path;
+
+/// Bytes read from the start of a file for sniffing; enough for the ISOBMFF brand list
+const SNIFF_LENGTH: usize = 64;
+
+/// ISOBMFF brands of HEIF images (HEVC-coded or generic)
+const HEIF_BRANDS: &[&[u8; 4]] = &[b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"mif1", b"msf1"];
+
+/// Image container format, identified by its magic bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Avif,
+    Heif,
+    Gif,
+    Tiff,
+}
+
+impl FileFormat {
+    /// Canonical name, as stored in `images.format`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileFormat::Png => "png",
+            FileFormat::Jpeg => "jpeg",
+            FileFormat::WebP => "webp",
+            FileFormat::Avif => "avif",
+            FileFormat::Heif => "heif",
+            FileFormat::Gif => "gif",
+            FileFormat::Tiff => "tiff",
+        }
+    }
+
+    /// Format implied by a file extension (case-insensitive)
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        Some(match ext.to_lowercase().as_str() {
+            "png" => FileFormat::Png,
+            "jpg" | "jpeg" | "jpe" | "jfif" => FileFormat::Jpeg,
+            "webp" => FileFormat::WebP,
+            "avif" => FileFormat::Avif,
+            "heic" | "heif" => FileFormat::Heif,
+            "gif" => FileFormat::Gif,
+            "tif" | "tiff" => FileFormat::Tiff,
+            _ => return None,
+        })
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        path.as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(FileFormat::from_extension)
+    }
+}
+
+/// Identify a format from the first bytes of a file
+pub fn sniff_format(header: &[u8]) -> Option<FileFormat> {
+    if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(FileFormat::Png);
+    }
+    if header.starts_with(&[0xff, 0xd8, 0xff]) {
+        return Some(FileFormat::Jpeg);
+    }
+    if header.len() >= 12 && &header[0..4] == b"RIFF" && &header[8..12] == b"WEBP" {
+        return Some(FileFormat::WebP);
+    }
+    if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        return Some(FileFormat::Gif);
+    }
+    if header.starts_with(b"II*\0") || header.starts_with(b"MM\0*") {
+        return Some(FileFormat::Tiff);
+    }
+    if header.len() >= 12 && &header[4..8] == b"ftyp" {
+        return sniff_isobmff_brand(header);
+    }
+    None
+}
+
+/// AVIF or HEIF from the `ftyp` major brand, falling back to the compatible brands
+/// (`mif1` files name their codec there)
+fn sniff_isobmff_brand(header: &[u8]) -> Option<FileFormat> {
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let ftyp = &header[8..size.clamp(12, header.len())];
+    // Major brand, minor version, then compatible brands
+    let brands = std::iter::once(&ftyp[0..4]).chain(ftyp.get(8..).unwrap_or_default().chunks_exact(4));
+
+    let mut heif = false;
+    for brand in brands {
+        if brand == b"avif" || brand == b"avis" {
+            return Some(FileFormat::Avif);
+        }
+        heif |= HEIF_BRANDS.iter().any(|b| &b[..] == brand);
+    }
+    heif.then_some(FileFormat::Heif)
+}
+
+/// Sniff a file's format from its first bytes
+pub fn sniff_file<P: AsRef<Path>>(path: P) -> std::io::Result<Option<FileFormat>> {
+    let mut header = Vec::with_capacity(SNIFF_LENGTH);
+    std::fs::File::open(path)?
+        .take(SNIFF_LENGTH as u64)
+        .read_to_end(&mut header)?;
+    Ok(sniff_format(&header))
+}
+
+/// The format a file really has: sniffed from its content, or from its extension when the
+/// content is not recognized
+pub fn detect_format<P: AsRef<Path>>(path: P) -> Option<FileFormat> {
+    let path = path.as_ref();
+    sniff_file(path).ok().flatten().or_else(|| FileFormat::from_path(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_magic_bytes() {
+        assert_eq!(sniff_format(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some(FileFormat::Png));
+        assert_eq!(sniff_format(&[0xff, 0xd8, 0xff, 0xe0]), Some(FileFormat::Jpeg));
+        assert_eq!(sniff_format(b"RIFF\x10\0\0\0WEBPVP8L"), Some(FileFormat::WebP));
+        assert_eq!(sniff_format(b"RIFF\x10\0\0\0WAVEfmt "), None);
+        assert_eq!(sniff_format(b"GIF89a\x01\0"), Some(FileFormat::Gif));
+        assert_eq!(sniff_format(b"MM\0*\0\0\0\x08"), Some(FileFormat::Tiff));
+        assert_eq!(sniff_format(b"\0\0\0\x1cftypavif\0\0\0\0avifmif1miaf"), Some(FileFormat::Avif));
+        assert_eq!(sniff_format(b"\0\0\0\x18ftypheic\0\0\0\0mif1heic"), Some(FileFormat::Heif));
+        // Generic HEIF major brand with AV1 content
+        assert_eq!(sniff_format(b"\0\0\0\x18ftypmif1\0\0\0\0mif1avif"), Some(FileFormat::Avif));
+        // Video files are ISOBMFF too
+        assert_eq!(sniff_format(b"\0\0\0\x18ftypisom\0\0\x02\0isomiso2"), None);
+        assert_eq!(sniff_format(b"not an image"), None);
+    }
+
+    #[test]
+    fn test_detect_renamed_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let renamed = dir.path().join("download.jpg");
+        std::fs::write(&renamed, b"\x89PNG\r\n\x1a\n").unwrap();
+        assert_eq!(detect_format(&renamed), Some(FileFormat::Png));
+        assert_eq!(FileFormat::from_path(&renamed), Some(FileFormat::Jpeg));
+
+        // Unrecognized content falls back to the extension
+        let unknown = dir.path().join("truncated.webp");
+        std::fs::write(&unknown, b"").unwrap();
+        assert_eq!(detect_format(&unknown), Some(FileFormat::WebP));
+    }
+}
@@ -0,0 +1,184 @@
+use crate::extraction::ExtractedMetadata;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+/// First dotted version number in a string, e.g. "v1.7.0" or "Fooocus v2.3.1"
+static VERSION_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\d+(?:\.\d+)+").unwrap());
+/// Forge versions look like "f2.0.1v1.10.1-previous-313-g8a042934"
+static FORGE_VERSION_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^f(\d+(?:\.\d+)*)v").unwrap());
+
+/// Known values of `software`-style tags, checked in order (Forge before the A1111 name it embeds)
+const SOFTWARE_GENERATORS: &[(&str, &str)] = &[
+    ("novelai", "novelai"),
+    ("midjourney", "midjourney"),
+    ("dall-e", "dalle"),
+    ("dall·e", "dalle"),
+    ("openai", "dalle"),
+    ("chatgpt", "dalle"),
+    ("gpt-image", "dalle"),
+    ("firefly", "firefly"),
+    ("comfyui", "comfyui"),
+    ("invokeai", "invokeai"),
+    ("fooocus", "fooocus"),
+    ("swarmui", "swarmui"),
+    ("forge", "forge"),
+    ("sd.next", "sdnext"),
+    ("automatic1111", "a1111"),
+    ("stable diffusion web", "a1111"),
+    ("draw things", "drawthings"),
+];
+
+/// Fill `generator` and `generator_version` from what the format parsers found.
+/// Parsers with a dedicated format (NovelAI, InvokeAI, ComfyUI, ...) already set the
+/// generator; this covers A1111-style parameters and software tags, and finds versions.
+pub fn detect_generator(metadata: &mut ExtractedMetadata) {
+    // Prefer whichever tool tag names a known generator (Software may just be an editor)
+    let software_tags = software_tags(metadata);
+    let software = software_tags
+        .iter()
+        .find(|tag| generator_from_software(tag).is_some())
+        .or(software_tags.first())
+        .cloned();
+    let a1111_version = find_field(metadata, "Version");
+
+    if metadata.generator.is_none() {
+        metadata.generator = software
+            .as_deref()
+            .and_then(generator_from_software)
+            .or_else(|| {
+                // A1111-style settings line; forks identify themselves through "Version"
+                if metadata.parameters.is_none() || (metadata.steps.is_none() && metadata.sampler.is_none()) {
+                    return None;
+                }
+                let version = a1111_version.as_deref().unwrap_or_default();
+                Some(if FORGE_VERSION_RE.is_match(version) {
+                    "forge"
+                } else if version.contains("Fooocus") {
+                    "fooocus"
+                } else {
+                    "a1111"
+                })
+            })
+            .map(|g| g.to_string());
+    }
+
+    if metadata.generator_version.is_none() {
+        let source = match metadata.generator.as_deref() {
+            Some("forge") => {
+                metadata.generator_version = a1111_version
+                    .as_deref()
+                    .and_then(|v| FORGE_VERSION_RE.captures(v))
+                    .map(|c| c[1].to_string());
+                None
+            }
+            Some("a1111") | Some("sdnext") => a1111_version.or(software),
+            Some("fooocus") => find_field(metadata, "version").or(a1111_version),
+            Some("invokeai") => find_field(metadata, "app_version"),
+            Some("swarmui") => find_field(metadata, "swarm_version"),
+            Some(_) => software,
+            None => None,
+        };
+        if let Some(source) = source {
+            metadata.generator_version = VERSION_RE.find(&source).map(|m| m.as_str().to_string());
+        }
+    }
+}
+
+/// Tags that name the tool that made the file, in order of preference
+const SOFTWARE_TAGS: &[&str] = &[
+    "Software",
+    "App",
+    "xmp:CreatorTool",
+    "OriginatingProgram",
+    "c2pa.claim_generator",
+    "c2pa.software_agent",
+];
+
+/// Values of the software-style tags that are present
+pub(crate) fn software_tags(metadata: &ExtractedMetadata) -> Vec<String> {
+    SOFTWARE_TAGS.iter().filter_map(|key| find_field(metadata, key)).collect()
+}
+
+/// A field collected by the format parsers, from `other` or the descriptive `entries`
+/// (case-insensitive key)
+pub(crate) fn find_field(metadata: &ExtractedMetadata, key: &str) -> Option<String> {
+    metadata
+        .other
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, v)| v.clone())
+        .or_else(|| {
+            metadata
+                .entries
+                .iter()
+                .find(|e| e.key.eq_ignore_ascii_case(key))
+                .map(|e| e.value.clone())
+        })
+}
+
+/// Map a software/creator tag to a generator id
+pub fn generator_from_software(software: &str) -> Option<&'static str> {
+    let software = software.to_lowercase();
+    SOFTWARE_GENERATORS
+        .iter()
+        .find(|(needle, _)| software.contains(needle))
+        .map(|(_, generator)| *generator)
+}
+
+/// A JSON value as a metadata string: strings without their quotes, anything else as JSON.
+/// Shared by the parsers of generators that write JSON metadata.
+pub(crate) fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata_with(parameters: bool, other: &[(&str, &str)]) -> ExtractedMetadata {
+        let mut metadata = ExtractedMetadata::empty();
+        if parameters {
+            metadata.parameters = Some("a cat\nSteps: 20".to_string());
+            metadata.steps = Some("20".to_string());
+        }
+        metadata.other = other.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        metadata
+    }
+
+    #[test]
+    fn test_detect_a1111_and_forge_from_version() {
+        let mut a1111 = metadata_with(true, &[("Version", "v1.7.0")]);
+        detect_generator(&mut a1111);
+        assert_eq!(a1111.generator, Some("a1111".to_string()));
+        assert_eq!(a1111.generator_version, Some("1.7.0".to_string()));
+
+        let mut forge = metadata_with(true, &[("Version", "f2.0.1v1.10.1-previous-313-g8a042934")]);
+        detect_generator(&mut forge);
+        assert_eq!(forge.generator, Some("forge".to_string()));
+        assert_eq!(forge.generator_version, Some("2.0.1".to_string()));
+    }
+
+    #[test]
+    fn test_detect_from_software_tag() {
+        let mut metadata = metadata_with(false, &[("Software", "Adobe Firefly 3.1")]);
+        detect_generator(&mut metadata);
+        assert_eq!(metadata.generator, Some("firefly".to_string()));
+        assert_eq!(metadata.generator_version, Some("3.1".to_string()));
+
+        let mut editor = metadata_with(false, &[("Software", "GIMP 2.10")]);
+        detect_generator(&mut editor);
+        assert_eq!(editor.generator, None);
+    }
+
+    #[test]
+    fn test_version_for_parser_detected_generator() {
+        let mut metadata = metadata_with(false, &[("app_version", "4.2.4")]);
+        metadata.generator = Some("invokeai".to_string());
+        detect_generator(&mut metadata);
+        assert_eq!(metadata.generator_version, Some("4.2.4".to_string()));
+    }
+}
@@ -0,0 +1,495 @@
+use crate::extraction::{ExtractedMetadata, GenerationStage};
+use crate::extraction::generator::value_to_string;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+/// PNG text chunk keys written by InvokeAI, newest first
+pub const INVOKEAI_KEYS: &[&str] = &["invokeai_metadata", "invokeai_graph", "sd-metadata", "Dream"];
+
+/// v2 prompts carry the negative prompt inline, in square brackets: `a cat [blurry, lowres]`
+static BRACKETED_NEGATIVE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[([^\[\]]*)\]").unwrap());
+
+/// Whether a set of text fields was written by InvokeAI
+pub fn is_invokeai(fields: &[(String, String)]) -> bool {
+    fields.iter().any(|(key, _)| INVOKEAI_KEYS.contains(&key.as_str()))
+}
+
+/// Map InvokeAI fields into metadata. `invokeai_metadata` (v3+) is preferred, then the
+/// `invokeai_graph` it was generated from, then `sd-metadata` and `Dream` (v2). Fields that
+/// were not mapped, such as the graph next to `invokeai_metadata`, are kept in `other`.
+pub fn apply_invokeai_to_metadata(fields: &[(String, String)], metadata: &mut ExtractedMetadata) {
+    let get = |key: &str| fields.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+    let json = |key: &str| get(key).and_then(|v| serde_json::from_str::<Value>(v).ok());
+
+    metadata.generator = Some("invokeai".to_string());
+
+    let mapped = if let Some(invoke) = json("invokeai_metadata") {
+        apply_v3_metadata(&invoke, metadata);
+        "invokeai_metadata"
+    } else if let Some(graph) = json("invokeai_graph").as_ref().and_then(InvokeGraph::new) {
+        graph.apply(metadata);
+        "invokeai_graph"
+    } else if let Some(sd_metadata) = json("sd-metadata") {
+        apply_sd_metadata(&sd_metadata, metadata);
+        "sd-metadata"
+    } else if let Some(dream) = get("Dream") {
+        apply_dream_string(dream, metadata);
+        "Dream"
+    } else {
+        ""
+    };
+
+    for (key, value) in fields {
+        if key != mapped && !metadata.other.iter().any(|(k, _)| k == key) {
+            metadata.other.push((key.clone(), value.clone()));
+        }
+    }
+
+    if metadata.stages.is_empty() && (metadata.steps.is_some() || metadata.sampler.is_some()) {
+        let mut stage = GenerationStage::new("base");
+        stage.model = metadata.model.clone();
+        stage.sampler = metadata.sampler.clone();
+        stage.steps = metadata.steps.clone();
+        stage.cfg_scale = metadata.cfg_scale.clone();
+        stage.seed = metadata.seed.clone();
+        stage.size = metadata.size.clone();
+        metadata.stages.push(stage);
+    }
+}
+
+/// v3+ flat JSON: `positive_prompt`, `negative_prompt`, `model`, `scheduler`, `loras`, ...
+fn apply_v3_metadata(invoke: &Value, metadata: &mut ExtractedMetadata) {
+    let str_field = |key: &str| {
+        invoke
+            .get(key)
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| s.to_string())
+    };
+
+    metadata.prompt = str_field("positive_prompt");
+    metadata.negative_prompt = str_field("negative_prompt");
+    metadata.model = invoke.get("model").and_then(model_name);
+    metadata.sampler = str_field("scheduler");
+    metadata.steps = invoke.get("steps").map(value_to_string);
+    metadata.cfg_scale = invoke.get("cfg_scale").map(value_to_string);
+    metadata.seed = invoke.get("seed").map(value_to_string);
+    if let (Some(width), Some(height)) = (
+        invoke.get("width").and_then(|v| v.as_u64()),
+        invoke.get("height").and_then(|v| v.as_u64()),
+    ) {
+        metadata.size = Some(format!("{}x{}", width, height));
+    }
+
+    let loras: Vec<String> = invoke
+        .get("loras")
+        .and_then(|l| l.as_array())
+        .map(|loras| {
+            loras
+                .iter()
+                .filter_map(|entry| {
+                    // v3 nests the model under "lora", v4+ under "model"
+                    let name = entry.get("lora").or_else(|| entry.get("model")).and_then(model_name)?;
+                    Some(match entry.get("weight") {
+                        Some(weight) => format!("{}:{}", name, value_to_string(weight)),
+                        None => name,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if !loras.is_empty() {
+        metadata.other.push(("lora".to_string(), loras.join(", ")));
+    }
+
+    for key in [
+        "app_version",
+        "generation_mode",
+        "positive_style_prompt",
+        "negative_style_prompt",
+        "cfg_rescale_multiplier",
+        "clip_skip",
+        "strength",
+        "seamless_x",
+        "seamless_y",
+    ] {
+        if let Some(value) = invoke.get(key).filter(|v| !v.is_null()) {
+            metadata.other.push((key.to_string(), value_to_string(value)));
+        }
+    }
+    if let Some(vae) = invoke.get("vae").and_then(model_name) {
+        metadata.other.push(("vae".to_string(), vae));
+    }
+
+    let mut base = GenerationStage::new("base");
+    base.model = metadata.model.clone();
+    base.sampler = metadata.sampler.clone();
+    base.steps = metadata.steps.clone();
+    base.cfg_scale = metadata.cfg_scale.clone();
+    base.seed = metadata.seed.clone();
+    base.size = metadata.size.clone();
+    base.denoise = invoke.get("strength").map(value_to_string);
+    metadata.stages.push(base);
+
+    if let Some(refiner_model) = invoke.get("refiner_model").and_then(model_name) {
+        let mut refiner = GenerationStage::new("refiner");
+        refiner.model = Some(refiner_model);
+        refiner.sampler = invoke.get("refiner_scheduler").and_then(|v| v.as_str()).map(|s| s.to_string());
+        refiner.steps = invoke.get("refiner_steps").map(value_to_string);
+        refiner.cfg_scale = invoke.get("refiner_cfg_scale").map(value_to_string);
+        refiner.seed = metadata.seed.clone();
+        // `refiner_start` is the fraction of the refiner schedule where denoising begins
+        let refiner_steps = invoke.get("refiner_steps").and_then(|v| v.as_f64());
+        let refiner_start = invoke.get("refiner_start").and_then(|v| v.as_f64());
+        if let (Some(total), Some(start)) = (refiner_steps, refiner_start) {
+            refiner.start_step = Some(((total * start) as u64).to_string());
+            refiner.end_step = refiner.steps.clone();
+        }
+        metadata.stages.push(refiner);
+    }
+
+    if invoke.get("hrf_enabled").and_then(|v| v.as_bool()).unwrap_or(false) {
+        let mut hires = GenerationStage::new("hires_fix");
+        hires.denoise = invoke.get("hrf_strength").map(value_to_string);
+        hires.upscaler = invoke.get("hrf_method").and_then(|v| v.as_str()).map(|s| s.to_string());
+        metadata.stages.push(hires);
+    }
+}
+
+/// Links followed before giving up, so a cyclic graph can't recurse forever
+const MAX_LINK_DEPTH: usize = 16;
+
+/// `invokeai_graph`: the invocation graph, with nodes keyed by id holding their input values,
+/// and edges `{ source: { node_id, field }, destination: { node_id, field } }` for linked inputs
+struct InvokeGraph<'a> {
+    nodes: &'a Map<String, Value>,
+    edges: &'a [Value],
+}
+
+impl<'a> InvokeGraph<'a> {
+    fn new(graph: &'a Value) -> Option<Self> {
+        Some(InvokeGraph {
+            nodes: graph.get("nodes")?.as_object()?,
+            edges: graph.get("edges").and_then(|e| e.as_array()).map(Vec::as_slice).unwrap_or_default(),
+        })
+    }
+
+    /// Follow the links out of the denoise node, the same way `ComfyGraph` follows the sampler
+    fn apply(&self, metadata: &mut ExtractedMetadata) {
+        let Some(denoise) = self.primary_denoise() else {
+            return;
+        };
+        let text = |value: Option<&Value>| value.and_then(|v| v.as_str()).filter(|s| !s.trim().is_empty()).map(String::from);
+
+        metadata.prompt = ["positive_conditioning", "positive_text_conditioning"]
+            .iter()
+            .find_map(|field| self.source(denoise, field))
+            .and_then(|(encoder, _)| text(self.value(encoder, "prompt", 0)));
+        metadata.negative_prompt = ["negative_conditioning", "negative_text_conditioning"]
+            .iter()
+            .find_map(|field| self.source(denoise, field))
+            .and_then(|(encoder, _)| text(self.value(encoder, "prompt", 0)));
+        metadata.model = self.model(denoise, 0);
+        metadata.sampler = text(self.value(denoise, "scheduler", 0));
+        metadata.steps = self.value(denoise, "steps", 0).map(value_to_string);
+        metadata.cfg_scale = self.value(denoise, "cfg_scale", 0).map(value_to_string);
+
+        // v3 feeds seed and size through a `noise` node, later versions set them on the denoise node
+        let noise = self.source(denoise, "noise").map_or(denoise, |(noise, _)| noise);
+        metadata.seed = self.value(noise, "seed", 0).map(value_to_string);
+        if let (Some(width), Some(height)) = (
+            self.value(noise, "width", 0).and_then(|v| v.as_u64()),
+            self.value(noise, "height", 0).and_then(|v| v.as_u64()),
+        ) {
+            metadata.size = Some(format!("{}x{}", width, height));
+        }
+    }
+
+    fn is_denoise(&self, id: &str) -> bool {
+        self.nodes
+            .get(id)
+            .and_then(|node| node.get("type"))
+            .and_then(|v| v.as_str())
+            .is_some_and(|kind| kind.contains("denoise"))
+    }
+
+    /// The first denoise node whose latents don't come from another denoise pass
+    fn primary_denoise(&self) -> Option<&'a str> {
+        let mut denoise = self.nodes.keys().map(String::as_str).filter(|id| self.is_denoise(id));
+        let first = denoise.clone().next();
+        denoise
+            .find(|id| !self.source(id, "latents").is_some_and(|(source, _)| self.is_denoise(source)))
+            .or(first)
+    }
+
+    /// The node and output field linked into `field` of node `id`
+    fn source(&self, id: &str, field: &str) -> Option<(&'a str, &'a str)> {
+        let edge = self.edges.iter().find(|edge| {
+            edge.pointer("/destination/node_id").and_then(|v| v.as_str()) == Some(id)
+                && edge.pointer("/destination/field").and_then(|v| v.as_str()) == Some(field)
+        })?;
+        Some((edge.pointer("/source/node_id")?.as_str()?, edge.pointer("/source/field")?.as_str()?))
+    }
+
+    /// An input's value, set on the node or linked from a primitive node's output
+    fn value(&self, id: &str, field: &str, depth: usize) -> Option<&'a Value> {
+        if depth > MAX_LINK_DEPTH {
+            return None;
+        }
+        if let Some(value) = self.nodes.get(id)?.get(field).filter(|v| !v.is_null()) {
+            return Some(value);
+        }
+        let (source, output) = self.source(id, field)?;
+        self.value(source, output, depth + 1)
+    }
+
+    /// The model of the loader feeding a node, past any LoRA loaders in between
+    fn model(&self, id: &str, depth: usize) -> Option<String> {
+        if depth > MAX_LINK_DEPTH {
+            return None;
+        }
+        let node = self.nodes.get(id)?;
+        if let Some(model) = node.get("model").and_then(model_name) {
+            return Some(model);
+        }
+        let (source, _) = ["unet", "transformer", "model"].iter().find_map(|field| self.source(id, field))?;
+        self.model(source, depth + 1)
+    }
+}
+
+/// v2 `sd-metadata`: `{ "model_weights", "app_version", "image": { "prompt", "sampler", ... } }`
+fn apply_sd_metadata(sd_metadata: &Value, metadata: &mut ExtractedMetadata) {
+    metadata.model = sd_metadata
+        .get("model_weights")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    if let Some(version) = sd_metadata.get("app_version").and_then(|v| v.as_str()) {
+        metadata.other.push(("app_version".to_string(), version.to_string()));
+    }
+
+    let Some(image) = sd_metadata.get("image") else {
+        return;
+    };
+
+    // The prompt is either a string or a list of weighted `{ "prompt", "weight" }` fragments
+    let prompt = match image.get("prompt") {
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(Value::Array(parts)) => Some(
+            parts
+                .iter()
+                .filter_map(|p| p.get("prompt").and_then(|v| v.as_str()))
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        _ => None,
+    };
+    if let Some(prompt) = prompt {
+        apply_bracketed_prompt(&prompt, metadata);
+    }
+
+    metadata.sampler = image.get("sampler").and_then(|v| v.as_str()).map(|s| s.to_string());
+    metadata.steps = image.get("steps").map(value_to_string);
+    metadata.cfg_scale = image.get("cfg_scale").map(value_to_string);
+    metadata.seed = image.get("seed").map(value_to_string);
+    if let (Some(width), Some(height)) = (
+        image.get("width").and_then(|v| v.as_u64()),
+        image.get("height").and_then(|v| v.as_u64()),
+    ) {
+        metadata.size = Some(format!("{}x{}", width, height));
+    }
+    if let Some(kind) = image.get("type").and_then(|v| v.as_str()) {
+        metadata.other.push(("generation_mode".to_string(), kind.to_string()));
+    }
+}
+
+/// v2 `Dream` command line: `"a cat [blurry]" -s 50 -S 42 -W 512 -H 512 -C 7.5 -A k_lms`
+fn apply_dream_string(dream: &str, metadata: &mut ExtractedMetadata) {
+    let dream = dream.trim();
+    let (prompt, flags) = match dream.strip_prefix('"').and_then(|rest| rest.rsplit_once('"')) {
+        Some((prompt, flags)) => (prompt.to_string(), flags),
+        None => match dream.find(" -") {
+            Some(index) => (dream[..index].to_string(), &dream[index..]),
+            None => (dream.to_string(), ""),
+        },
+    };
+    apply_bracketed_prompt(&prompt, metadata);
+
+    let mut width = None;
+    let mut height = None;
+    let mut tokens = flags.split_whitespace();
+    while let Some(flag) = tokens.next() {
+        let value = match flag {
+            "-s" | "-S" | "-W" | "-H" | "-C" | "-A" | "-m" | "-f" => tokens.next().map(|v| v.to_string()),
+            _ => None,
+        };
+        match flag {
+            "-s" => metadata.steps = value,
+            "-S" => metadata.seed = value,
+            "-W" => width = value,
+            "-H" => height = value,
+            "-C" => metadata.cfg_scale = value,
+            "-A" => metadata.sampler = value,
+            "-m" => metadata.model = value,
+            "-f" => {
+                if let Some(strength) = value {
+                    metadata.other.push(("strength".to_string(), strength));
+                }
+            }
+            _ => {}
+        }
+    }
+    if let (Some(width), Some(height)) = (width, height) {
+        metadata.size = Some(format!("{}x{}", width, height));
+    }
+}
+
+/// Split a v2 prompt into positive text and the bracketed negative fragments
+fn apply_bracketed_prompt(prompt: &str, metadata: &mut ExtractedMetadata) {
+    let negatives: Vec<&str> = BRACKETED_NEGATIVE_RE
+        .captures_iter(prompt)
+        .filter_map(|c| c.get(1).map(|m| m.as_str().trim()))
+        .filter(|s| !s.is_empty())
+        .collect();
+    let positive = BRACKETED_NEGATIVE_RE.replace_all(prompt, "");
+    let positive = positive.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if !positive.is_empty() {
+        metadata.prompt = Some(positive);
+    }
+    if !negatives.is_empty() {
+        metadata.negative_prompt = Some(negatives.join(", "));
+    }
+}
+
+/// Model references are `{ "model_name" }` (v3) or `{ "name" }` (v4+), or a plain string
+fn model_name(model: &Value) -> Option<String> {
+    match model {
+        Value::String(s) => Some(s.clone()),
+        Value::Object(_) => model
+            .get("model_name")
+            .or_else(|| model.get("name"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string()),
+        _ => None,
+    }
+    .filter(|s| !s.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invokeai_v3_metadata() {
+        let invoke = r#"{
+            "generation_mode": "sdxl_txt2img", "positive_prompt": "a lighthouse at dusk", "negative_prompt": "blurry",
+            "width": 1024, "height": 1024, "seed": 2718, "cfg_scale": 6.5, "steps": 30, "scheduler": "dpmpp_2m_k",
+            "model": {"key": "abc", "hash": "blake3:00", "name": "Juggernaut XL v9", "base": "sdxl", "type": "main"},
+            "loras": [{"model": {"key": "def", "name": "detail-tweaker"}, "weight": 0.6}],
+            "refiner_model": {"model_name": "sdxl-refiner-1.0"}, "refiner_steps": 10, "refiner_start": 0.8,
+            "app_version": "4.2.4"
+        }"#;
+        let fields = vec![
+            ("invokeai_metadata".to_string(), invoke.to_string()),
+            ("invokeai_graph".to_string(), r#"{"nodes": {}, "edges": []}"#.to_string()),
+        ];
+        assert!(is_invokeai(&fields));
+
+        let mut metadata = ExtractedMetadata::empty();
+        apply_invokeai_to_metadata(&fields, &mut metadata);
+
+        assert_eq!(metadata.generator, Some("invokeai".to_string()));
+        assert_eq!(metadata.prompt, Some("a lighthouse at dusk".to_string()));
+        assert_eq!(metadata.negative_prompt, Some("blurry".to_string()));
+        assert_eq!(metadata.model, Some("Juggernaut XL v9".to_string()));
+        assert_eq!(metadata.sampler, Some("dpmpp_2m_k".to_string()));
+        assert_eq!(metadata.cfg_scale, Some("6.5".to_string()));
+        assert_eq!(metadata.size, Some("1024x1024".to_string()));
+        assert!(metadata.other.contains(&("lora".to_string(), "detail-tweaker:0.6".to_string())));
+        assert!(metadata.other.iter().any(|(k, _)| k == "invokeai_graph"));
+        assert!(!metadata.other.iter().any(|(k, _)| k == "invokeai_metadata"));
+        assert_eq!(metadata.stages.len(), 2);
+        assert_eq!(metadata.stages[1].model, Some("sdxl-refiner-1.0".to_string()));
+        assert_eq!(metadata.stages[1].start_step, Some("8".to_string()));
+    }
+
+    #[test]
+    fn test_invokeai_graph_only() {
+        // A v3 SDXL graph: the positive prompt comes from a string primitive, the model
+        // reaches the denoise node through a LoRA loader, and a second pass refines the latents
+        let edge = |source: &str, output: &str, destination: &str, field: &str| {
+            serde_json::json!({
+                "source": { "node_id": source, "field": output },
+                "destination": { "node_id": destination, "field": field }
+            })
+        };
+        let graph = serde_json::json!({
+            "id": "sdxl_graph",
+            "nodes": {
+                "denoise_refine": { "id": "denoise_refine", "type": "denoise_latents", "steps": 10, "cfg_scale": 5, "scheduler": "euler" },
+                "model_loader": { "id": "model_loader", "type": "sdxl_model_loader",
+                    "model": { "model_name": "juggernautXL_v9", "base_model": "sdxl" } },
+                "lora": { "id": "lora", "type": "sdxl_lora_loader", "lora": { "model_name": "detail-tweaker" }, "weight": 0.6 },
+                "text": { "id": "text", "type": "string", "value": "a koi pond in autumn" },
+                "positive": { "id": "positive", "type": "sdxl_compel_prompt" },
+                "negative": { "id": "negative", "type": "sdxl_compel_prompt", "prompt": "blurry" },
+                "noise": { "id": "noise", "type": "noise", "seed": 4242, "width": 1024, "height": 768 },
+                "denoise_txt2img": { "id": "denoise_txt2img", "type": "denoise_latents", "steps": 30, "cfg_scale": 7.5, "scheduler": "dpmpp_2m_k" }
+            },
+            "edges": [
+                edge("text", "value", "positive", "prompt"),
+                edge("positive", "conditioning", "denoise_txt2img", "positive_conditioning"),
+                edge("negative", "conditioning", "denoise_txt2img", "negative_conditioning"),
+                edge("model_loader", "unet", "lora", "unet"),
+                edge("lora", "unet", "denoise_txt2img", "unet"),
+                edge("noise", "noise", "denoise_txt2img", "noise"),
+                edge("denoise_txt2img", "latents", "denoise_refine", "latents")
+            ]
+        });
+
+        let mut metadata = ExtractedMetadata::empty();
+        apply_invokeai_to_metadata(&[("invokeai_graph".to_string(), graph.to_string())], &mut metadata);
+
+        assert_eq!(metadata.generator, Some("invokeai".to_string()));
+        assert_eq!(metadata.prompt, Some("a koi pond in autumn".to_string()));
+        assert_eq!(metadata.negative_prompt, Some("blurry".to_string()));
+        assert_eq!(metadata.model, Some("juggernautXL_v9".to_string()));
+        assert_eq!(metadata.sampler, Some("dpmpp_2m_k".to_string()));
+        assert_eq!(metadata.steps, Some("30".to_string()));
+        assert_eq!(metadata.cfg_scale, Some("7.5".to_string()));
+        assert_eq!(metadata.seed, Some("4242".to_string()));
+        assert_eq!(metadata.size, Some("1024x768".to_string()));
+        assert!(!metadata.other.iter().any(|(k, _)| k == "invokeai_graph"));
+    }
+
+    #[test]
+    fn test_invokeai_v2_sd_metadata() {
+        let sd_metadata = r#"{"model": "stable diffusion", "model_weights": "stable-diffusion-1.5", "app_id": "invoke-ai/InvokeAI", "app_version": "2.2.4",
+            "image": {"prompt": [{"prompt": "an old castle [fog, people]", "weight": 1.0}], "steps": 50, "cfg_scale": 7.5, "height": 512, "width": 768, "seed": 42, "type": "txt2img", "sampler": "k_lms"}}"#;
+
+        let mut metadata = ExtractedMetadata::empty();
+        apply_invokeai_to_metadata(&[("sd-metadata".to_string(), sd_metadata.to_string())], &mut metadata);
+
+        assert_eq!(metadata.prompt, Some("an old castle".to_string()));
+        assert_eq!(metadata.negative_prompt, Some("fog, people".to_string()));
+        assert_eq!(metadata.model, Some("stable-diffusion-1.5".to_string()));
+        assert_eq!(metadata.sampler, Some("k_lms".to_string()));
+        assert_eq!(metadata.size, Some("768x512".to_string()));
+    }
+
+    #[test]
+    fn test_invokeai_dream_string() {
+        let mut metadata = ExtractedMetadata::empty();
+        let dream = r#""a red fox in snow [watermark]" -s 40 -S 1234 -W 512 -H 640 -C 7.0 -A k_euler_a"#;
+        apply_invokeai_to_metadata(&[("Dream".to_string(), dream.to_string())], &mut metadata);
+
+        assert_eq!(metadata.prompt, Some("a red fox in snow".to_string()));
+        assert_eq!(metadata.negative_prompt, Some("watermark".to_string()));
+        assert_eq!(metadata.steps, Some("40".to_string()));
+        assert_eq!(metadata.seed, Some("1234".to_string()));
+        assert_eq!(metadata.size, Some("512x640".to_string()));
+        assert_eq!(metadata.cfg_scale, Some("7.0".to_string()));
+        assert_eq!(metadata.sampler, Some("k_euler_a".to_string()));
+    }
+}
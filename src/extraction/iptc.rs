@@ -0,0 +1,214 @@
+use crate::extraction::{ExtractedMetadata, MetadataEntry, SourceLocation};
+
+/// Identifier of the Photoshop image resource block in a JPEG APP13 segment
+pub const PHOTOSHOP_ID: &[u8] = b"Photoshop 3.0\0";
+/// Image resource holding the IPTC-IIM datasets
+const IPTC_RESOURCE_ID: u16 = 0x0404;
+/// ISO 2022 escape for UTF-8, as written into dataset 1:90 (CodedCharacterSet)
+const UTF8_ESCAPE: &[u8] = b"\x1b%G";
+
+/// One IPTC-IIM dataset, e.g. 2:120 Caption-Abstract
+#[derive(Debug, Clone, PartialEq)]
+pub struct IptcDataset {
+    pub record: u8,
+    pub dataset: u8,
+    pub value: String,
+}
+
+impl IptcDataset {
+    /// Standard name for the application record (2:xx) datasets we care about
+    pub fn name(&self) -> Option<&'static str> {
+        if self.record != 2 {
+            return None;
+        }
+        Some(match self.dataset {
+            5 => "ObjectName",
+            25 => "Keywords",
+            40 => "SpecialInstructions",
+            55 => "DateCreated",
+            65 => "OriginatingProgram",
+            70 => "ProgramVersion",
+            80 => "By-line",
+            105 => "Headline",
+            110 => "Credit",
+            115 => "Source",
+            116 => "CopyrightNotice",
+            120 => "Caption-Abstract",
+            _ => return None,
+        })
+    }
+}
+
+/// Read the IPTC datasets from a Photoshop APP13 payload (with or without the `Photoshop 3.0` id)
+pub fn parse_photoshop_resources(data: &[u8]) -> Vec<IptcDataset> {
+    let mut data = data.strip_prefix(PHOTOSHOP_ID).unwrap_or(data);
+    let mut datasets = Vec::new();
+
+    // 8BIM blocks: signature, 2-byte id, even-padded Pascal name, 4-byte size, even-padded data
+    while data.len() >= 12 && &data[..4] == b"8BIM" {
+        let id = u16::from_be_bytes([data[4], data[5]]);
+        let name_length = data[6] as usize;
+        let name_field = (1 + name_length + 1) & !1;
+        let size_offset = 6 + name_field;
+        if data.len() < size_offset + 4 {
+            break;
+        }
+        let size = u32::from_be_bytes([
+            data[size_offset],
+            data[size_offset + 1],
+            data[size_offset + 2],
+            data[size_offset + 3],
+        ]) as usize;
+        let start = size_offset + 4;
+        if data.len() < start + size {
+            break;
+        }
+
+        if id == IPTC_RESOURCE_ID {
+            datasets.extend(parse_iim(&data[start..start + size]));
+        }
+
+        let next = start + ((size + 1) & !1);
+        data = &data[next.min(data.len())..];
+    }
+
+    datasets
+}
+
+/// Parse raw IPTC-IIM: `0x1C record dataset length value` tags
+pub fn parse_iim(data: &[u8]) -> Vec<IptcDataset> {
+    let mut raw: Vec<(u8, u8, &[u8])> = Vec::new();
+    let mut offset = 0;
+
+    while offset + 5 <= data.len() && data[offset] == 0x1c {
+        let record = data[offset + 1];
+        let dataset = data[offset + 2];
+        let mut length = u16::from_be_bytes([data[offset + 3], data[offset + 4]]) as usize;
+        offset += 5;
+
+        // Extended datasets: the low 15 bits give the size of the length field
+        if length & 0x8000 != 0 {
+            let length_size = length & 0x7fff;
+            if length_size > 4 || offset + length_size > data.len() {
+                break;
+            }
+            length = data[offset..offset + length_size]
+                .iter()
+                .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+            offset += length_size;
+        }
+
+        if offset + length > data.len() {
+            break;
+        }
+        raw.push((record, dataset, &data[offset..offset + length]));
+        offset += length;
+    }
+
+    let utf8 = raw
+        .iter()
+        .any(|(record, dataset, value)| *record == 1 && *dataset == 90 && value.starts_with(UTF8_ESCAPE));
+
+    raw.into_iter()
+        .filter(|(record, _, _)| *record == 2)
+        .map(|(record, dataset, value)| IptcDataset {
+            record,
+            dataset,
+            value: decode_text(value, utf8),
+        })
+        .collect()
+}
+
+/// Declared UTF-8, or UTF-8 if it happens to be valid, otherwise Latin-1
+fn decode_text(value: &[u8], utf8: bool) -> String {
+    let text = match std::str::from_utf8(value) {
+        Ok(text) => text.to_string(),
+        Err(_) if utf8 => String::from_utf8_lossy(value).to_string(),
+        Err(_) => value.iter().map(|&b| b as char).collect(),
+    };
+    text.trim_end_matches('\0').trim().to_string()
+}
+
+/// Map IPTC datasets into metadata: the caption is a prompt candidate, keywords are
+/// tag candidates, and every named dataset is kept as an "iptc" entry.
+pub fn apply_iptc_to_metadata(datasets: &[IptcDataset], origin: &SourceLocation, metadata: &mut ExtractedMetadata) {
+    let mut entries: Vec<MetadataEntry> = Vec::new();
+
+    for dataset in datasets {
+        let Some(name) = dataset.name() else {
+            continue;
+        };
+        if dataset.value.is_empty() {
+            continue;
+        }
+
+        match name {
+            "Caption-Abstract" => {
+                metadata.record_source(&origin.with_key(name), |m| {
+                    m.prompt.get_or_insert_with(|| dataset.value.clone());
+                });
+            }
+            "Keywords" if !metadata.keywords.contains(&dataset.value) => {
+                metadata.keywords.push(dataset.value.clone());
+            }
+            _ => {}
+        }
+
+        // Repeatable datasets (Keywords, By-line) are joined into one entry
+        match entries.iter_mut().find(|e| e.key == name) {
+            Some(entry) => {
+                entry.value.push_str(", ");
+                entry.value.push_str(&dataset.value);
+            }
+            None => entries.push(MetadataEntry::new(name, &dataset.value, "iptc")),
+        }
+    }
+
+    metadata.entries.extend(entries);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dataset(record: u8, dataset: u8, value: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x1c, record, dataset];
+        data.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        data.extend_from_slice(value);
+        data
+    }
+
+    #[test]
+    fn test_parse_photoshop_iptc_block() {
+        let mut iim = dataset(1, 90, b"\x1b%G");
+        iim.extend(dataset(2, 120, "a misty forest, golden hour — f/1.8".as_bytes()));
+        iim.extend(dataset(2, 25, b"forest"));
+        iim.extend(dataset(2, 25, b"mist"));
+        iim.extend(dataset(2, 65, b"Midjourney"));
+
+        let mut app13 = PHOTOSHOP_ID.to_vec();
+        // An unrelated resource first, with an odd-length name and odd-sized data
+        app13.extend_from_slice(b"8BIM\x03\xed\x01x\0\0\0\x03abc\0");
+        app13.extend_from_slice(b"8BIM\x04\x04\0\0");
+        app13.extend_from_slice(&(iim.len() as u32).to_be_bytes());
+        app13.extend_from_slice(&iim);
+
+        let datasets = parse_photoshop_resources(&app13);
+        assert_eq!(datasets.len(), 4);
+
+        let mut metadata = ExtractedMetadata::empty();
+        apply_iptc_to_metadata(&datasets, &SourceLocation::new("jpeg:APP13", "IPTC", None, "iptc"), &mut metadata);
+
+        assert_eq!(metadata.prompt, Some("a misty forest, golden hour — f/1.8".to_string()));
+        assert_eq!(metadata.keywords, vec!["forest".to_string(), "mist".to_string()]);
+        assert!(metadata.entries.contains(&MetadataEntry::new("Keywords", "forest, mist", "iptc")));
+        assert!(metadata.entries.contains(&MetadataEntry::new("OriginatingProgram", "Midjourney", "iptc")));
+    }
+
+    #[test]
+    fn test_latin1_without_charset() {
+        let datasets = parse_iim(&dataset(2, 120, b"caf\xe9"));
+        assert_eq!(datasets[0].value, "café");
+        assert_eq!(datasets[0].name(), Some("Caption-Abstract"));
+    }
+}
@@ -0,0 +1,479 @@
+use crate::extraction::{ExtractedMetadata, SourceLocation};
+use crate::extraction::c2pa::{apply_c2pa_to_metadata, c2pa_from_uuid_box, parse_manifest_store};
+use crate::extraction::exif::{apply_exif_to_metadata, parse_exif_block};
+use crate::extraction::xmp::{apply_xmp_to_metadata, XmpPacket};
+use std::path::Path;
+
+/// Content type of `mime` items that hold XMP
+const XMP_CONTENT_TYPE: &str = "application/rdf+xml";
+
+/// Metadata items of an ISOBMFF image (AVIF, HEIC/HEIF)
+#[derive(Debug, Default)]
+pub struct IsobmffItems {
+    /// Exif items, TIFF header onwards
+    pub exif: Vec<Vec<u8>>,
+    /// XMP packets from `mime` items
+    pub xmp: Vec<Vec<u8>>,
+    /// Width and height from the primary item's `ispe` property
+    pub dimensions: Option<(u32, u32)>,
+    /// C2PA manifest store from the top-level `uuid` box
+    pub c2pa: Option<Vec<u8>>,
+}
+
+pub fn extract_isobmff_metadata<P: AsRef<Path>>(path: P) -> anyhow::Result<ExtractedMetadata> {
+    let data = std::fs::read(path.as_ref())?;
+    Ok(extract_isobmff_metadata_from_bytes(&data))
+}
+
+pub fn extract_isobmff_metadata_from_bytes(data: &[u8]) -> ExtractedMetadata {
+    let items = parse_isobmff(data);
+
+    let mut metadata = ExtractedMetadata::empty();
+
+    // Items may be split into extents, so no single offset is recorded for them
+    for exif in items.exif.iter().filter_map(|block| parse_exif_block(block)) {
+        apply_exif_to_metadata(&exif, &SourceLocation::new("isobmff:Exif", "", None, "exif"), &mut metadata);
+    }
+
+    for xmp in &items.xmp {
+        if let Ok(packet) = XmpPacket::parse(&String::from_utf8_lossy(xmp)) {
+            apply_xmp_to_metadata(&packet, &SourceLocation::new("isobmff:mime", "", None, "xmp"), &mut metadata);
+        }
+    }
+
+    if let Some(jumbf) = &items.c2pa {
+        apply_c2pa_to_metadata(&parse_manifest_store(jumbf), &mut metadata);
+    }
+
+    metadata
+}
+
+/// Locate the Exif/XMP items and primary image size through the `meta` box, without
+/// decoding any image data
+pub fn parse_isobmff(data: &[u8]) -> IsobmffItems {
+    let mut items = IsobmffItems::default();
+
+    let top = boxes(data);
+    if top.first().map(|(kind, _)| *kind) != Some(b"ftyp") {
+        return items;
+    }
+    items.c2pa = top
+        .iter()
+        .filter(|(kind, _)| *kind == b"uuid")
+        .find_map(|(_, payload)| c2pa_from_uuid_box(payload))
+        .map(|jumbf| jumbf.to_vec());
+    // `meta` is a full box: version and flags come before its children
+    let Some(meta) = find_box(&top, b"meta").and_then(|meta| meta.get(4..)) else {
+        return items;
+    };
+    let children = boxes(meta);
+
+    let primary = find_box(&children, b"pitm").and_then(|pitm| {
+        let mut reader = ByteReader::new(pitm);
+        let version = reader.full_box_header()?.0;
+        reader.uint(if version == 0 { 2 } else { 4 })
+    });
+    let infos = find_box(&children, b"iinf").map(parse_iinf).unwrap_or_default();
+    let locations = find_box(&children, b"iloc").and_then(parse_iloc).unwrap_or_default();
+    let idat = find_box(&children, b"idat").unwrap_or_default();
+
+    for info in infos {
+        let is_exif = &info.item_type == b"Exif";
+        let is_xmp = &info.item_type == b"mime" && info.content_type.as_deref() == Some(XMP_CONTENT_TYPE);
+        if !is_exif && !is_xmp {
+            continue;
+        }
+        let Some(payload) = locations
+            .iter()
+            .find(|location| location.item_id == info.item_id)
+            .and_then(|location| location.read(data, idat))
+        else {
+            continue;
+        };
+
+        if is_exif {
+            // Exif items start with a 32-bit offset to the TIFF header
+            let tiff_offset = ByteReader::new(&payload).uint(4).map(|offset| offset as usize + 4);
+            if let Some(tiff) = tiff_offset.and_then(|offset| payload.get(offset..)) {
+                items.exif.push(tiff.to_vec());
+            }
+        } else {
+            items.xmp.push(payload);
+        }
+    }
+
+    items.dimensions = find_box(&children, b"iprp").and_then(|iprp| primary_dimensions(iprp, primary));
+
+    items
+}
+
+/// Boxes directly inside `data`, as (type, payload) pairs
+pub(crate) fn boxes(data: &[u8]) -> Vec<(&[u8; 4], &[u8])> {
+    let mut result = Vec::new();
+    let mut offset = 0;
+
+    while offset + 8 <= data.len() {
+        let mut reader = ByteReader::new(&data[offset..]);
+        let Some(size) = reader.uint(4) else { break };
+        let kind: &[u8; 4] = data[offset + 4..offset + 8].try_into().unwrap();
+        let (header, size) = match size {
+            // Box extends to the end of the enclosing data
+            0 => (8u64, (data.len() - offset) as u64),
+            // 64-bit size follows the type
+            1 => {
+                reader.skip(4);
+                match reader.uint(8) {
+                    Some(size) => (16, size),
+                    None => break,
+                }
+            }
+            size => (8, size),
+        };
+        if size < header || size > (data.len() - offset) as u64 {
+            break;
+        }
+        let (header, size) = (header as usize, size as usize);
+        result.push((kind, &data[offset + header..offset + size]));
+        offset += size;
+    }
+
+    result
+}
+
+fn find_box<'a>(boxes: &[(&[u8; 4], &'a [u8])], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes.iter().find(|(k, _)| *k == kind).map(|(_, payload)| *payload)
+}
+
+struct ItemInfo {
+    item_id: u64,
+    item_type: [u8; 4],
+    content_type: Option<String>,
+}
+
+/// Item infos (`infe` version 2 and 3; older versions carry no item type)
+fn parse_iinf(iinf: &[u8]) -> Vec<ItemInfo> {
+    let mut reader = ByteReader::new(iinf);
+    let Some((version, _)) = reader.full_box_header() else {
+        return Vec::new();
+    };
+    reader.skip(if version == 0 { 2 } else { 4 });
+
+    boxes(reader.rest())
+        .into_iter()
+        .filter(|(kind, _)| *kind == b"infe")
+        .filter_map(|(_, infe)| {
+            let mut reader = ByteReader::new(infe);
+            let (version, _) = reader.full_box_header()?;
+            if version < 2 {
+                return None;
+            }
+            let item_id = reader.uint(if version == 2 { 2 } else { 4 })?;
+            reader.skip(2); // item_protection_index
+            let item_type: [u8; 4] = reader.bytes(4)?.try_into().ok()?;
+            reader.c_string()?; // item_name
+            let content_type = if &item_type == b"mime" {
+                reader.c_string().map(|s| s.to_string())
+            } else {
+                None
+            };
+            Some(ItemInfo { item_id, item_type, content_type })
+        })
+        .collect()
+}
+
+struct ItemLocation {
+    item_id: u64,
+    /// 0: offsets into the file, 1: offsets into the `idat` box
+    construction_method: u64,
+    extents: Vec<(u64, u64)>,
+}
+
+impl ItemLocation {
+    /// Concatenate the extents; a zero length means "to the end of the source". An item is
+    /// never larger than its source, so repeated extents can't inflate it past that.
+    fn read(&self, file: &[u8], idat: &[u8]) -> Option<Vec<u8>> {
+        let source = match self.construction_method {
+            0 => file,
+            1 => idat,
+            _ => return None,
+        };
+        let mut data = Vec::new();
+        for &(offset, length) in &self.extents {
+            let start = usize::try_from(offset).ok()?;
+            let end = if length == 0 {
+                source.len()
+            } else {
+                start.checked_add(usize::try_from(length).ok()?)?
+            };
+            let extent = source.get(start..end)?;
+            if data.len() + extent.len() > source.len() {
+                return None;
+            }
+            data.extend_from_slice(extent);
+        }
+        Some(data)
+    }
+}
+
+fn parse_iloc(iloc: &[u8]) -> Option<Vec<ItemLocation>> {
+    let mut reader = ByteReader::new(iloc);
+    let (version, _) = reader.full_box_header()?;
+    let sizes = reader.uint(1)?;
+    let (offset_size, length_size) = ((sizes >> 4) as usize, (sizes & 0xf) as usize);
+    let sizes = reader.uint(1)?;
+    let base_offset_size = (sizes >> 4) as usize;
+    let index_size = if version >= 1 { (sizes & 0xf) as usize } else { 0 };
+    // Extents that take no bytes would let a tiny box declare thousands of them
+    if index_size + offset_size + length_size == 0 {
+        return None;
+    }
+    let item_count = reader.uint(if version < 2 { 2 } else { 4 })?;
+
+    let mut locations = Vec::new();
+    for _ in 0..item_count {
+        let item_id = reader.uint(if version < 2 { 2 } else { 4 })?;
+        let construction_method = if version >= 1 { reader.uint(2)? & 0xf } else { 0 };
+        reader.skip(2); // data_reference_index
+        let base_offset = reader.uint(base_offset_size)?;
+        let extent_count = reader.uint(2)?;
+
+        let mut extents = Vec::new();
+        for _ in 0..extent_count {
+            reader.skip(index_size);
+            let offset = reader.uint(offset_size)?;
+            let length = reader.uint(length_size)?;
+            extents.push((base_offset.checked_add(offset)?, length));
+        }
+        locations.push(ItemLocation { item_id, construction_method, extents });
+    }
+
+    Some(locations)
+}
+
+/// `ispe` of the primary item via `ipma`, or the first `ispe` if there is no association
+fn primary_dimensions(iprp: &[u8], primary: Option<u64>) -> Option<(u32, u32)> {
+    let children = boxes(iprp);
+    let properties = boxes(find_box(&children, b"ipco")?);
+    let ispe = |index: usize| {
+        let (kind, payload) = properties.get(index)?;
+        if *kind != b"ispe" {
+            return None;
+        }
+        let mut reader = ByteReader::new(payload);
+        reader.full_box_header()?;
+        Some((reader.uint(4)? as u32, reader.uint(4)? as u32))
+    };
+
+    let associated = primary.zip(find_box(&children, b"ipma")).and_then(|(primary, ipma)| {
+        let mut reader = ByteReader::new(ipma);
+        let (version, flags) = reader.full_box_header()?;
+        let entry_count = reader.uint(4)?;
+        for _ in 0..entry_count {
+            let item_id = reader.uint(if version < 1 { 2 } else { 4 })?;
+            let association_count = reader.uint(1)?;
+            for _ in 0..association_count {
+                // High bit is "essential"; property indices are 1-based
+                let index = if flags & 1 != 0 { reader.uint(2)? & 0x7fff } else { reader.uint(1)? & 0x7f };
+                if item_id == primary && index > 0 {
+                    if let Some(dimensions) = ispe(index as usize - 1) {
+                        return Some(dimensions);
+                    }
+                }
+            }
+        }
+        None
+    });
+
+    associated.or_else(|| (0..properties.len()).find_map(ispe))
+}
+
+/// Big-endian reads that return `None` past the end of the data
+struct ByteReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, position: 0 }
+    }
+
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.position..self.position.checked_add(count)?)?;
+        self.position += count;
+        Some(bytes)
+    }
+
+    /// Unsigned integer of 0-8 bytes; zero-sized fields read as 0
+    fn uint(&mut self, size: usize) -> Option<u64> {
+        if size > 8 {
+            return None;
+        }
+        Some(self.bytes(size)?.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    fn skip(&mut self, count: usize) {
+        self.position = self.position.saturating_add(count);
+    }
+
+    /// Version and flags of a full box
+    fn full_box_header(&mut self) -> Option<(u8, u32)> {
+        let header = self.uint(4)?;
+        Some(((header >> 24) as u8, (header & 0xff_ffff) as u32))
+    }
+
+    fn c_string(&mut self) -> Option<&'a str> {
+        let rest = self.rest();
+        let end = rest.iter().position(|b| *b == 0)?;
+        self.position += end + 1;
+        std::str::from_utf8(&rest[..end]).ok()
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        self.data.get(self.position..).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extraction::exif::tests::tiff_with_user_comment;
+
+    fn bx(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn full_box(kind: &[u8; 4], version: u8, flags: u32, payload: &[u8]) -> Vec<u8> {
+        let mut body = (((version as u32) << 24) | flags).to_be_bytes().to_vec();
+        body.extend_from_slice(payload);
+        bx(kind, &body)
+    }
+
+    fn infe(item_id: u16, item_type: &[u8; 4], content_type: Option<&str>) -> Vec<u8> {
+        let mut body = item_id.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0, 0]);
+        body.extend_from_slice(item_type);
+        body.push(0);
+        if let Some(content_type) = content_type {
+            body.extend_from_slice(content_type.as_bytes());
+            body.push(0);
+        }
+        full_box(b"infe", 2, 0, &body)
+    }
+
+    /// AVIF-like file: Exif item stored in `idat`, XMP item in `mdat`
+    fn avif(exif_item: &[u8], xmp: &[u8]) -> Vec<u8> {
+        let ftyp = bx(b"ftyp", b"avif\0\0\0\0avifmif1");
+        let build_meta = |xmp_offset: u32| {
+            let mut iinf = 3u16.to_be_bytes().to_vec();
+            iinf.extend(infe(1, b"av01", None));
+            iinf.extend(infe(2, b"Exif", None));
+            iinf.extend(infe(3, b"mime", Some(XMP_CONTENT_TYPE)));
+
+            // Version 1: 4-byte offsets and lengths, no base offset or index
+            let mut iloc = vec![0x44, 0x00];
+            iloc.extend_from_slice(&2u16.to_be_bytes());
+            for (item_id, method, offset, length) in [(2u16, 1u16, 0u32, exif_item.len()), (3, 0, xmp_offset, xmp.len())] {
+                iloc.extend_from_slice(&item_id.to_be_bytes());
+                iloc.extend_from_slice(&method.to_be_bytes());
+                iloc.extend_from_slice(&[0, 0]);
+                iloc.extend_from_slice(&1u16.to_be_bytes());
+                iloc.extend_from_slice(&offset.to_be_bytes());
+                iloc.extend_from_slice(&(length as u32).to_be_bytes());
+            }
+
+            // Property 1 is a thumbnail-sized ispe, property 2 belongs to the primary item
+            let mut ispe_small = 64u32.to_be_bytes().to_vec();
+            ispe_small.extend_from_slice(&64u32.to_be_bytes());
+            let mut ispe = 832u32.to_be_bytes().to_vec();
+            ispe.extend_from_slice(&1216u32.to_be_bytes());
+            let mut ipco = full_box(b"ispe", 0, 0, &ispe_small);
+            ipco.extend(full_box(b"ispe", 0, 0, &ispe));
+            let ipma = [0, 0, 0, 1, 0, 1, 1, 0x82];
+            let mut iprp = bx(b"ipco", &ipco);
+            iprp.extend(full_box(b"ipma", 0, 0, &ipma));
+
+            let mut meta = full_box(b"hdlr", 0, 0, b"\0\0\0\0pict\0\0\0\0\0\0\0\0\0\0\0\0\0");
+            meta.extend(full_box(b"pitm", 0, 0, &1u16.to_be_bytes()));
+            meta.extend(full_box(b"iinf", 0, 0, &iinf));
+            meta.extend(full_box(b"iloc", 1, 0, &iloc));
+            meta.extend(bx(b"iprp", &iprp));
+            meta.extend(bx(b"idat", exif_item));
+            full_box(b"meta", 0, 0, &meta)
+        };
+
+        let xmp_offset = (ftyp.len() + build_meta(0).len() + 8) as u32;
+        let mut file = ftyp;
+        file.extend(build_meta(xmp_offset));
+        file.extend(bx(b"mdat", xmp));
+        file
+    }
+
+    #[test]
+    fn test_avif_exif_xmp_and_dimensions() {
+        let params = "an origami crane\nNegative prompt: blurry\nSteps: 28, Sampler: Euler, CFG scale: 5, Seed: 3, Size: 832x1216";
+        let mut comment = b"UNICODE\0".to_vec();
+        comment.extend(params.encode_utf16().flat_map(|u| u.to_be_bytes()));
+        // Offset 6 skips the `Exif\0\0` some writers keep in front of the TIFF header
+        let mut exif_item = 6u32.to_be_bytes().to_vec();
+        exif_item.extend_from_slice(b"Exif\0\0");
+        exif_item.extend(tiff_with_user_comment(&comment));
+        let xmp = br#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+            <rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/" xmp:CreatorTool="ComfyUI"/>
+        </rdf:RDF>"#;
+
+        let data = avif(&exif_item, xmp);
+        let items = parse_isobmff(&data);
+        assert_eq!(items.dimensions, Some((832, 1216)));
+        assert_eq!(items.exif.len(), 1);
+        assert_eq!(items.xmp, vec![xmp.to_vec()]);
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("crane.avif");
+        std::fs::write(&path, &data).unwrap();
+        let metadata = extract_isobmff_metadata(&path).unwrap();
+        assert_eq!(metadata.prompt, Some("an origami crane".to_string()));
+        assert_eq!(metadata.steps, Some("28".to_string()));
+        assert!(metadata.entries.iter().any(|e| e.key == "xmp:CreatorTool" && e.value == "ComfyUI"));
+    }
+
+    /// `ftyp` and a `meta` box whose only item is an XMP packet located by `iloc`
+    fn xmp_item_file(iloc_sizes: [u8; 2], extents: &[u8], extent_count: u16) -> Vec<u8> {
+        let mut iinf = 1u16.to_be_bytes().to_vec();
+        iinf.extend(infe(1, b"mime", Some(XMP_CONTENT_TYPE)));
+        let mut iloc = iloc_sizes.to_vec();
+        iloc.extend_from_slice(&[0, 1, 0, 1, 0, 0, 0, 0]); // one item, id 1, file offsets
+        iloc.extend_from_slice(&extent_count.to_be_bytes());
+        iloc.extend_from_slice(extents);
+
+        let mut meta = full_box(b"iinf", 0, 0, &iinf);
+        meta.extend(full_box(b"iloc", 1, 0, &iloc));
+        let mut file = bx(b"ftyp", b"avif\0\0\0\0avifmif1");
+        file.extend(full_box(b"meta", 0, 0, &meta));
+        file
+    }
+
+    #[test]
+    fn test_hostile_iloc_is_rejected() {
+        // Zero-sized fields: 65535 extents of "the whole file" in 16 bytes of iloc
+        let data = xmp_item_file([0x00, 0x00], &[], u16::MAX);
+        assert!(parse_isobmff(&data).xmp.is_empty());
+
+        // 4-byte offsets and no lengths: every extent reads from 0 to the end of the file
+        let data = xmp_item_file([0x40, 0x00], &[0; 4 * 1000], 1000);
+        assert!(parse_isobmff(&data).xmp.is_empty());
+
+        // A single whole-file extent is still read
+        let data = xmp_item_file([0x40, 0x00], &[0; 4], 1);
+        assert_eq!(parse_isobmff(&data).xmp, vec![data.clone()]);
+    }
+
+    #[test]
+    fn test_non_isobmff_is_empty() {
+        let items = parse_isobmff(b"\x89PNG\r\n\x1a\n\0\0\0\0");
+        assert!(items.exif.is_empty() && items.xmp.is_empty() && items.dimensions.is_none());
+    }
+}
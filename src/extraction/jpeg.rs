@@ -1,8 +1,13 @@
-use crate::extraction::ExtractedMetadata;
+use crate::extraction::{ExtractedMetadata, MetadataEntry, SourceLocation};
+use crate::extraction::sources::offset_in;
+use crate::extraction::iptc::{apply_iptc_to_metadata, parse_photoshop_resources, IptcDataset, PHOTOSHOP_ID};
 use std::fs::File;
 use std::path::Path;
 use std::io::Read;
 use log::debug;
+use crate::extraction::exif::{apply_exif_to_metadata, parse_potential_parameters, EXIF_HEADER};
+use crate::extraction::xmp::{apply_xmp_to_metadata, xmp_from_jpeg_segments, JPEG_XMP_ID};
+use crate::extraction::c2pa::{apply_c2pa_to_metadata, c2pa_from_jpeg_segments, parse_manifest_store};
 use exif::Reader;
 
 pub fn extract_jpeg_metadata<P: AsRef<Path>>(path: P) -> anyhow::Result<ExtractedMetadata> {
@@ -10,172 +15,147 @@ pub fn extract_jpeg_metadata<P: AsRef<Path>>(path: P) -> anyhow::Result<Extracte
     let mut file = File::open(path)?;
     let mut buf = Vec::new();
     file.read_to_end(&mut buf)?;
-    
+    debug!("Reading JPEG metadata: {}", path.display());
+    extract_jpeg_metadata_from_bytes(&buf)
+}
+
+pub fn extract_jpeg_metadata_from_bytes(buf: &[u8]) -> anyhow::Result<ExtractedMetadata> {
     let mut metadata = ExtractedMetadata::empty();
 
     // Try to parse EXIF data using kamadak-exif
-    let mut cursor = std::io::Cursor::new(&buf);
+    let mut cursor = std::io::Cursor::new(buf);
     match Reader::new().read_from_container(&mut cursor) {
         Ok(exif) => {
-            debug!("Found EXIF data in JPEG: {}", path.display());
-            
-            // Extract common EXIF fields that might contain prompts
-            for field in exif.fields() {
-                let tag_str = format!("{:?}", field.tag);
-                let value_str = field.value.display_as(field.tag).to_string();
-                
-                // Clean up value (remove quotes if present)
-                let value = value_str.strip_prefix('"')
-                    .and_then(|s| s.strip_suffix('"'))
-                    .unwrap_or(&value_str)
-                    .to_string();
-                
-                if value.is_empty() {
-                    continue;
-                }
-                
-                // Match on tag string since Tag enum might not have all variants
-                match tag_str.as_str() {
-                    "ImageDescription" => {
-                        if metadata.prompt.is_none() {
-                            metadata.prompt = Some(value.clone());
-                        }
-                        metadata.other.push(("ImageDescription".to_string(), value));
-                    }
-                    "UserComment" => {
-                        // UserComment often contains prompts or generation info
-                        parse_potential_parameters(&value, &mut metadata);
-                        metadata.other.push(("UserComment".to_string(), value));
-                    }
-                    "Artist" => {
-                        metadata.other.push(("Artist".to_string(), value));
-                    }
-                    "Software" => {
-                        // Software field might contain model name
-                        if metadata.model.is_none() {
-                            metadata.model = Some(value.clone());
-                        }
-                        metadata.other.push(("Software".to_string(), value));
-                    }
-                    "DateTime" | "DateTimeOriginal" | "DateTimeDigitized" => {
-                        metadata.other.push((tag_str.clone(), value));
-                    }
-                    _ => {
-                        // Store other fields
-                        metadata.other.push((tag_str, value));
-                    }
-                }
-            }
+            debug!("Found EXIF data in JPEG");
+            let offset = jpeg_segments(buf)
+                .into_iter()
+                .find(|(marker, payload)| *marker == 0xe1 && payload.starts_with(EXIF_HEADER))
+                .and_then(|(_, payload)| offset_in(buf, payload));
+            apply_exif_to_metadata(&exif, &SourceLocation::new("jpeg:APP1", "", offset, "exif"), &mut metadata);
         }
         Err(e) => {
-            debug!("No EXIF data found in JPEG {}: {}", path.display(), e);
+            debug!("No EXIF data found in JPEG: {}", e);
         }
     }
 
     // Try to extract XMP data if present
     // XMP is often embedded in JPEG APP1 segment
-    extract_xmp_from_jpeg(&buf, &mut metadata)?;
+    extract_xmp_from_jpeg(buf, &mut metadata);
+
+    // Photo managers write captions/keywords to IPTC (APP13) and plain comments (COM)
+    extract_iptc_and_comments(buf, &mut metadata);
+
+    // Content Credentials (C2PA) travel as JUMBF in APP11 segments
+    let app11 = jpeg_segments(buf)
+        .into_iter()
+        .filter(|(marker, _)| *marker == 0xeb)
+        .map(|(_, payload)| payload);
+    if let Some(jumbf) = c2pa_from_jpeg_segments(app11) {
+        apply_c2pa_to_metadata(&parse_manifest_store(&jumbf), &mut metadata);
+    }
 
     Ok(metadata)
 }
 
-/// Extract XMP data from JPEG file
-fn extract_xmp_from_jpeg(data: &[u8], metadata: &mut ExtractedMetadata) -> anyhow::Result<()> {
-    // XMP data in JPEG is typically in APP1 segment with identifier "http://ns.adobe.com/xap/1.0/\0"
-    let xmp_header = b"http://ns.adobe.com/xap/1.0/\0";
-    
-    // Search for XMP header
-    for (i, window) in data.windows(xmp_header.len()).enumerate() {
-        if window == xmp_header {
-            // Found XMP header, try to extract XML data
-            let xmp_start = i + xmp_header.len();
-            if xmp_start < data.len() {
-                // XMP data follows the header
-                // Try to find XML content
-                if let Ok(xml_str) = String::from_utf8(data[xmp_start..].to_vec()) {
-                    parse_xmp_xml(&xml_str, metadata)?;
-                }
-            }
-            break;
-        }
+/// Walk the marker segments of a JPEG file up to the start of scan, returning
+/// (marker, payload) pairs. Payloads exclude the marker and length bytes.
+pub(crate) fn jpeg_segments(data: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut segments = Vec::new();
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return segments;
     }
-    
-    Ok(())
-}
 
-/// Parse XMP XML to extract prompts and metadata
-fn parse_xmp_xml(xml: &str, metadata: &mut ExtractedMetadata) -> anyhow::Result<()> {
-    // Simple XMP parsing - look for common fields
-    // Full XMP parsing would require an XML parser, but we can do basic regex matching
-    
-    // Look for dc:description (Dublin Core description)
-    if let Some(desc_start) = xml.find("<dc:description>") {
-        let desc_end = xml[desc_start..].find("</dc:description>");
-        if let Some(end) = desc_end {
-            let desc = &xml[desc_start + 16..desc_start + end];
-            let desc = desc.trim();
-            if !desc.is_empty() && metadata.prompt.is_none() {
-                metadata.prompt = Some(desc.to_string());
-            }
+    let mut offset = 2;
+    while offset + 4 <= data.len() {
+        if data[offset] != 0xff {
+            break;
         }
-    }
-    
-    // Look for xmp:Description
-    if let Some(desc_start) = xml.find("<xmp:Description>") {
-        let desc_end = xml[desc_start..].find("</xmp:Description>");
-        if let Some(end) = desc_end {
-            let desc = &xml[desc_start + 17..desc_start + end];
-            let desc = desc.trim();
-            if !desc.is_empty() && metadata.prompt.is_none() {
-                metadata.prompt = Some(desc.to_string());
+        let marker = data[offset + 1];
+        match marker {
+            // Fill byte before a marker
+            0xff => {
+                offset += 1;
+                continue;
             }
-        }
-    }
-    
-    // Look for rdf:Description with description attribute
-    if let Some(desc_start) = xml.find("rdf:Description") {
-        if let Some(desc_attr) = xml[desc_start..].find("dc:description=\"") {
-            let attr_start = desc_start + desc_attr + 15;
-            if let Some(attr_end) = xml[attr_start..].find('"') {
-                let desc = &xml[attr_start..attr_start + attr_end];
-                if !desc.is_empty() && metadata.prompt.is_none() {
-                    metadata.prompt = Some(desc.to_string());
-                }
+            // Stand-alone markers carry no length
+            0x01 | 0xd0..=0xd7 => {
+                offset += 2;
+                continue;
             }
+            // End of image, or start of scan (entropy-coded data follows)
+            0xd9 | 0xda => break,
+            _ => {}
+        }
+
+        let length = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        if length < 2 || offset + 2 + length > data.len() {
+            break;
         }
+        segments.push((marker, &data[offset + 4..offset + 2 + length]));
+        offset += 2 + length;
     }
-    
-    Ok(())
+
+    segments
 }
 
-#[allow(dead_code)]
-fn parse_potential_parameters(text: &str, metadata: &mut ExtractedMetadata) {
-    // Check if the text looks like a Stable Diffusion parameters string
-    if text.contains("Steps:") || text.contains("CFG scale:") || text.contains("Seed:") {
-        // Try to parse as parameters string
-        crate::extraction::png::parse_parameters_string(text, metadata);
-    } else {
-        // Might be a simple prompt, check for negative prompt marker
-        if text.contains("Negative prompt:") {
-            let lines: Vec<&str> = text.lines().collect();
-            if !lines.is_empty() {
-                metadata.prompt = Some(lines[0].trim().to_string());
-            }
-            for line in &lines {
-                if line.trim().starts_with("Negative prompt:") {
-                    let neg_prompt = line.trim().strip_prefix("Negative prompt:").unwrap_or("").trim();
-                    if !neg_prompt.is_empty() {
-                        metadata.negative_prompt = Some(neg_prompt.to_string());
-                    }
-                }
-            }
+/// Extract XMP (including Extended XMP) from the JPEG APP1 segments
+fn extract_xmp_from_jpeg(data: &[u8], metadata: &mut ExtractedMetadata) {
+    let app1: Vec<&[u8]> = jpeg_segments(data)
+        .into_iter()
+        .filter(|(marker, _)| *marker == 0xe1)
+        .map(|(_, payload)| payload)
+        .collect();
+
+    if let Some(packet) = xmp_from_jpeg_segments(app1.iter().copied()) {
+        // Point at the main packet's segment; Extended XMP continues in later ones
+        let offset = app1
+            .iter()
+            .find(|payload| payload.starts_with(JPEG_XMP_ID))
+            .and_then(|payload| offset_in(data, payload));
+        apply_xmp_to_metadata(&packet, &SourceLocation::new("jpeg:APP1", "XMP", offset, "xmp"), metadata);
+    }
+}
+
+/// Read IPTC-IIM datasets from Photoshop APP13 segments and text from COM segments
+fn extract_iptc_and_comments(data: &[u8], metadata: &mut ExtractedMetadata) {
+    let segments = jpeg_segments(data);
+
+    let app13: Vec<&[u8]> = segments
+        .iter()
+        .filter(|(marker, payload)| *marker == 0xed && payload.starts_with(PHOTOSHOP_ID))
+        .map(|(_, payload)| *payload)
+        .collect();
+    let datasets: Vec<IptcDataset> = app13.iter().flat_map(|payload| parse_photoshop_resources(payload)).collect();
+    let offset = app13.first().and_then(|payload| offset_in(data, payload));
+    apply_iptc_to_metadata(&datasets, &SourceLocation::new("jpeg:APP13", "", offset, "iptc"), metadata);
+
+    for (_, payload) in segments.iter().filter(|(marker, _)| *marker == 0xfe) {
+        let comment = match std::str::from_utf8(payload) {
+            Ok(text) => text.to_string(),
+            Err(_) => payload.iter().map(|&b| b as char).collect(),
+        };
+        let comment = comment.trim_end_matches('\0').trim();
+        if comment.is_empty() {
+            continue;
         }
+
+        // Some tools write the full parameters string into a COM segment
+        let location = SourceLocation::new("jpeg:COM", "Comment", offset_in(data, payload), "comment");
+        metadata.record_source(&location, |m| {
+            if parse_potential_parameters(comment, m) {
+                m.parameters = Some(comment.to_string());
+            } else {
+                m.prompt = Some(comment.to_string());
+            }
+        });
+        metadata.entries.push(MetadataEntry::new("Comment", comment, "comment"));
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::extraction::exif::tests::tiff_with_user_comment;
 
     #[test]
     fn test_parse_potential_parameters() {
@@ -189,5 +169,52 @@ Steps: 20, Seed: 12345";
         assert_eq!(metadata.prompt, Some("beautiful landscape".to_string()));
         assert_eq!(metadata.negative_prompt, Some("blurry".to_string()));
     }
-}
 
+    /// Minimal JPEG with a little-endian EXIF block holding only a UserComment
+    fn jpeg_with_user_comment(comment: &[u8]) -> Vec<u8> {
+        let tiff = tiff_with_user_comment(comment);
+
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend(tiff);
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe1];
+        jpeg.extend_from_slice(&((app1.len() + 2) as u16).to_be_bytes());
+        jpeg.extend(app1);
+        jpeg.extend_from_slice(&[0xff, 0xd9]);
+        jpeg
+    }
+
+    #[test]
+    fn test_a1111_jpeg_user_comment() {
+        let params = "a lighthouse in a storm\nNegative prompt: blurry\nSteps: 25, Sampler: DPM++ 2M, CFG scale: 7, Seed: 99, Size: 512x768, Version: v1.9.4";
+        let mut comment = b"UNICODE\0".to_vec();
+        comment.extend(params.encode_utf16().flat_map(|u| u.to_be_bytes()));
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("a1111.jpg");
+        std::fs::write(&path, jpeg_with_user_comment(&comment)).unwrap();
+
+        let metadata = extract_jpeg_metadata(&path).unwrap();
+        assert_eq!(metadata.prompt, Some("a lighthouse in a storm".to_string()));
+        assert_eq!(metadata.negative_prompt, Some("blurry".to_string()));
+        assert_eq!(metadata.seed, Some("99".to_string()));
+        assert_eq!(metadata.parameters, Some(params.to_string()));
+    }
+
+    #[test]
+    fn test_jpeg_com_segment_and_segment_walk() {
+        let comment = b"a watercolor heron";
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xfe];
+        jpeg.extend_from_slice(&((comment.len() + 2) as u16).to_be_bytes());
+        jpeg.extend_from_slice(comment);
+        // Entropy-coded data after SOS must not be mistaken for segments
+        jpeg.extend_from_slice(&[0xff, 0xda, 0x00, 0x02, 0xff, 0xfe, 0x00, 0x04, 0x41, 0x42, 0xff, 0xd9]);
+
+        let segments = jpeg_segments(&jpeg);
+        assert_eq!(segments, vec![(0xfe, &comment[..])]);
+
+        let mut metadata = ExtractedMetadata::empty();
+        extract_iptc_and_comments(&jpeg, &mut metadata);
+        assert_eq!(metadata.prompt, Some("a watercolor heron".to_string()));
+        assert_eq!(metadata.entries, vec![MetadataEntry::new("Comment", "a watercolor heron", "comment")]);
+    }
+}
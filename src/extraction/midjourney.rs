@@ -0,0 +1,200 @@
+use crate::extraction::generator::{find_field, generator_from_software, software_tags};
+use crate::extraction::ExtractedMetadata;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// A `--flag` at the start of the text or after whitespace
+static FLAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|\s)--([a-zA-Z]+)").unwrap());
+/// Phones autocorrect "--" into an em dash
+static EM_DASH_FLAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(^|\s)\x{2014}([a-zA-Z])").unwrap());
+/// Midjourney appends the job id to the description
+static JOB_ID_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s*Job ID:\s*(\S+)\s*$").unwrap());
+
+/// Fields that may hold the Midjourney description, in order of preference
+const DESCRIPTION_KEYS: &[&str] = &["Description", "ImageDescription", "dc:description"];
+/// Flags that only Midjourney uses (`--ar`/`--seed` alone are too generic)
+const MIDJOURNEY_FLAGS: &[&str] = &["v", "niji", "stylize", "chaos", "sref", "cref", "weird", "personalize", "iw", "style"];
+
+/// A Midjourney description: the prompt, its parameter flags and the job id
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MidjourneyPrompt {
+    pub prompt: String,
+    /// Image URLs placed in front of the text prompt
+    pub image_prompts: Vec<String>,
+    /// Flags with canonical names (`ar`, `v`, `stylize`, `chaos`, ...) and their values
+    pub flags: Vec<(String, String)>,
+    pub job_id: Option<String>,
+}
+
+impl MidjourneyPrompt {
+    pub fn flag(&self, name: &str) -> Option<&str> {
+        self.flags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+/// Split a description like `a cat --ar 16:9 --v 6.1 Job ID: ...` into its parts
+pub fn parse_midjourney_description(text: &str) -> MidjourneyPrompt {
+    let text = EM_DASH_FLAG_RE.replace_all(text, "$1--$2").to_string();
+    let mut result = MidjourneyPrompt::default();
+
+    let text = match JOB_ID_RE.captures(&text) {
+        Some(captures) => {
+            result.job_id = Some(captures[1].to_string());
+            text[..captures.get(0).unwrap().start()].to_string()
+        }
+        None => text,
+    };
+
+    let flags: Vec<_> = FLAG_RE.captures_iter(&text).collect();
+    let prompt_end = flags.first().map_or(text.len(), |c| c.get(0).unwrap().start());
+    for (index, captures) in flags.iter().enumerate() {
+        let value_end = flags.get(index + 1).map_or(text.len(), |next| next.get(0).unwrap().start());
+        let value = text[captures.get(0).unwrap().end()..value_end].trim();
+        result.flags.push((canonical_flag(&captures[1]), value.to_string()));
+    }
+
+    let mut words = text[..prompt_end].split_whitespace().peekable();
+    while let Some(url) = words.next_if(|w| w.starts_with("http://") || w.starts_with("https://")) {
+        result.image_prompts.push(url.to_string());
+    }
+    result.prompt = words.collect::<Vec<_>>().join(" ");
+
+    result
+}
+
+fn canonical_flag(flag: &str) -> String {
+    let flag = flag.to_lowercase();
+    match flag.as_str() {
+        "aspect" => "ar",
+        "s" => "stylize",
+        "c" => "chaos",
+        "q" => "quality",
+        "w" => "weird",
+        "version" => "v",
+        "p" => "personalize",
+        other => other,
+    }
+    .to_string()
+}
+
+/// Look for a Midjourney description among the fields the format parsers collected
+/// (PNG `Description`, EXIF `ImageDescription`, XMP `dc:description`) and apply it.
+pub fn detect_midjourney(metadata: &mut ExtractedMetadata) {
+    if metadata.generator.as_deref().is_some_and(|g| g != "midjourney") {
+        return;
+    }
+    let tagged = metadata.generator.is_some()
+        || software_tags(metadata).iter().any(|tag| generator_from_software(tag) == Some("midjourney"));
+
+    let description = DESCRIPTION_KEYS
+        .iter()
+        .filter_map(|key| find_field(metadata, key))
+        .chain(metadata.prompt.clone())
+        .find(|text| {
+            let parsed = parse_midjourney_description(text);
+            let known_flags = parsed.flags.iter().filter(|(f, _)| MIDJOURNEY_FLAGS.contains(&f.as_str())).count();
+            parsed.job_id.is_some() || (tagged && !parsed.flags.is_empty()) || known_flags >= 2
+        });
+
+    if let Some(description) = description {
+        apply_midjourney_to_metadata(&parse_midjourney_description(&description), metadata);
+    }
+}
+
+/// Map a parsed description into metadata; flags without a dedicated field go to `other`
+pub fn apply_midjourney_to_metadata(parsed: &MidjourneyPrompt, metadata: &mut ExtractedMetadata) {
+    metadata.generator = Some("midjourney".to_string());
+    if !parsed.prompt.is_empty() {
+        metadata.prompt = Some(parsed.prompt.clone());
+    }
+
+    let mut remaining = Vec::new();
+    for (flag, value) in &parsed.flags {
+        let key = match flag.as_str() {
+            "v" => {
+                metadata.generator_version = Some(value.clone());
+                continue;
+            }
+            "niji" => {
+                metadata.generator_version = Some(format!("niji {}", value).trim().to_string());
+                continue;
+            }
+            "no" => {
+                metadata.negative_prompt.get_or_insert_with(|| value.clone());
+                continue;
+            }
+            "seed" => {
+                metadata.seed = Some(value.clone());
+                continue;
+            }
+            "ar" => "aspect_ratio",
+            "stylize" | "chaos" | "quality" | "weird" | "sref" | "cref" => flag.as_str(),
+            _ => {
+                remaining.push(format!("--{} {}", flag, value).trim().to_string());
+                continue;
+            }
+        };
+        metadata.other.push((key.to_string(), value.clone()));
+    }
+    if !remaining.is_empty() {
+        metadata.other.push(("flags".to_string(), remaining.join(" ")));
+    }
+    if !parsed.image_prompts.is_empty() {
+        metadata.other.push(("image_prompts".to_string(), parsed.image_prompts.join(" ")));
+    }
+
+    // XMP carries the job id as the image GUID when the description doesn't
+    let job_id = parsed.job_id.clone().or_else(|| find_field(metadata, "Iptc4xmpExt:DigImageGUID"));
+    if let Some(job_id) = job_id {
+        metadata.other.push(("job_id".to_string(), job_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_description_flags() {
+        let parsed = parse_midjourney_description(
+            "https://s.mj.run/abc a red fox in snow, ukiyo-e --ar 16:9 --v 6.1 --s 250 --chaos 10 --seed 42 \
+             --sref https://s.mj.run/one https://s.mj.run/two --cref https://s.mj.run/me \u{2014}no text --style raw \
+             Job ID: 0c5e3f2a-1111-4c3e-9b2d-8f9e1a2b3c4d",
+        );
+        assert_eq!(parsed.prompt, "a red fox in snow, ukiyo-e");
+        assert_eq!(parsed.image_prompts, vec!["https://s.mj.run/abc".to_string()]);
+        assert_eq!(parsed.flag("ar"), Some("16:9"));
+        assert_eq!(parsed.flag("stylize"), Some("250"));
+        assert_eq!(parsed.flag("sref"), Some("https://s.mj.run/one https://s.mj.run/two"));
+        assert_eq!(parsed.flag("no"), Some("text"));
+        assert_eq!(parsed.job_id, Some("0c5e3f2a-1111-4c3e-9b2d-8f9e1a2b3c4d".to_string()));
+
+        let mut metadata = ExtractedMetadata::empty();
+        apply_midjourney_to_metadata(&parsed, &mut metadata);
+        assert_eq!(metadata.generator_version, Some("6.1".to_string()));
+        assert_eq!(metadata.seed, Some("42".to_string()));
+        assert_eq!(metadata.negative_prompt, Some("text".to_string()));
+        assert!(metadata.other.contains(&("aspect_ratio".to_string(), "16:9".to_string())));
+        assert!(metadata.other.contains(&("flags".to_string(), "--style raw".to_string())));
+    }
+
+    #[test]
+    fn test_detect_from_png_description() {
+        let mut metadata = ExtractedMetadata::empty();
+        metadata.other = vec![
+            ("Description".to_string(), "lighthouse at dusk --ar 2:3 --niji 6 Job ID: 1234".to_string()),
+            ("Author".to_string(), "someone".to_string()),
+        ];
+        detect_midjourney(&mut metadata);
+        assert_eq!(metadata.generator, Some("midjourney".to_string()));
+        assert_eq!(metadata.generator_version, Some("niji 6".to_string()));
+        assert_eq!(metadata.prompt, Some("lighthouse at dusk".to_string()));
+        assert!(metadata.other.contains(&("job_id".to_string(), "1234".to_string())));
+
+        // A prompt that merely mentions a flag is left alone
+        let mut other = ExtractedMetadata::empty();
+        other.prompt = Some("a poster that says --ar".to_string());
+        detect_midjourney(&mut other);
+        assert_eq!(other.generator, None);
+    }
+}
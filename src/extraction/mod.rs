@@ -1,13 +1,38 @@
 pub mod png;
 pub mod jpeg;
 pub mod webp;
+pub mod isobmff;
+pub mod format;
+pub mod exif;
+pub mod xmp;
+pub mod iptc;
+pub mod cbor;
+pub mod c2pa;
 pub mod parser;
+pub mod registry;
+pub mod sources;
+pub mod params;
+pub mod a1111;
+pub mod novelai;
+pub mod invokeai;
+pub mod fooocus;
+pub mod swarmui;
+pub mod midjourney;
+pub mod dalle;
+pub mod generator;
 pub mod normalizer;
 pub mod tag_extractor;
 pub mod comfyui;
+pub mod comfyui_ui;
+pub mod stealth;
+pub mod watermark;
 
-pub use parser::{ExtractedMetadata, MetadataExtractor};
+pub use parser::{ExtractedMetadata, GenerationStage, MetadataEntry, MetadataExtractor};
+pub use params::GenerationParams;
+pub use registry::{ContainerView, MetadataParser, ParserRegistry, TextField};
+pub use sources::{FieldSource, SourceLocation};
 pub use normalizer::PromptNormalizer;
 pub use tag_extractor::TagExtractor;
 pub use comfyui::{parse_comfyui_workflow, apply_comfyui_to_metadata, ComfyUIWorkflow};
+pub use comfyui_ui::{parse_comfyui_ui_workflow, apply_comfyui_ui_to_metadata, ComfyUIUiWorkflow};
 
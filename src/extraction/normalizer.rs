@@ -56,7 +56,7 @@ impl PromptNormalizer {
     pub fn extract_segments(prompt: &str) -> Vec<String> {
         prompt
             .split(',')
-            .map(|s| Self::normalize(s))
+            .map(Self::normalize)
             .filter(|s| !s.is_empty())
             .collect()
     }
@@ -0,0 +1,272 @@
+use crate::extraction::{ExtractedMetadata, GenerationStage};
+use crate::extraction::generator::value_to_string;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Keys NovelAI writes as PNG text chunks (and into the stealth pnginfo JSON)
+pub const NOVELAI_KEYS: &[&str] = &["Title", "Description", "Software", "Source", "Generation time", "Comment"];
+
+/// A V4 character prompt and where it was placed on the canvas
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NovelAICharacter {
+    pub prompt: String,
+    pub negative_prompt: Option<String>,
+    /// Normalized (0.0-1.0) canvas positions
+    pub centers: Vec<(f64, f64)>,
+}
+
+/// Whether a set of text fields was written by NovelAI
+pub fn is_novelai(fields: &[(String, String)]) -> bool {
+    fields
+        .iter()
+        .any(|(key, value)| key == "Software" && value.trim().starts_with("NovelAI"))
+}
+
+/// Map NovelAI fields (`Description`, `Source`, `Comment` JSON, ...) into metadata.
+/// Keys that were mapped are not duplicated into `other`.
+pub fn apply_novelai_to_metadata(fields: &[(String, String)], metadata: &mut ExtractedMetadata) {
+    let get = |key: &str| fields.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+
+    metadata.generator = Some("novelai".to_string());
+
+    let comment: Option<Value> = get("Comment").and_then(|c| serde_json::from_str(c).ok());
+    if let Some(comment) = &comment {
+        apply_comment(comment, metadata);
+    }
+
+    if metadata.prompt.is_none() {
+        metadata.prompt = get("Description").map(|d| d.to_string()).filter(|d| !d.trim().is_empty());
+    }
+    if metadata.model.is_none() {
+        // e.g. "NovelAI Diffusion V4.5 4BDE2A90" or "Stable Diffusion XL C1E1DE52"
+        metadata.model = get("Source").map(|s| s.to_string());
+    }
+
+    for (key, value) in fields {
+        let mapped = match key.as_str() {
+            "Description" => metadata.prompt.is_some(),
+            "Comment" => comment.is_some(),
+            _ => false,
+        };
+        if !mapped && !metadata.other.iter().any(|(k, _)| k == key) {
+            metadata.other.push((key.clone(), value.clone()));
+        }
+    }
+
+    if metadata.stages.is_empty() && (metadata.steps.is_some() || metadata.sampler.is_some()) {
+        let mut stage = GenerationStage::new("base");
+        stage.model = metadata.model.clone();
+        stage.sampler = metadata.sampler.clone();
+        stage.scheduler = comment
+            .as_ref()
+            .and_then(|c| c.get("noise_schedule"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+        stage.steps = metadata.steps.clone();
+        stage.cfg_scale = metadata.cfg_scale.clone();
+        stage.seed = metadata.seed.clone();
+        stage.size = metadata.size.clone();
+        stage.denoise = comment
+            .as_ref()
+            .and_then(|c| c.get("strength"))
+            .map(value_to_string);
+        metadata.stages.push(stage);
+    }
+}
+
+fn apply_comment(comment: &Value, metadata: &mut ExtractedMetadata) {
+    let str_field = |key: &str| {
+        comment
+            .get(key)
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| s.to_string())
+    };
+
+    // Keys missing from the Comment keep what the Description chunk or other sources gave.
+    // V4 moves prompts into structured captions; fall back to them when the flat field is empty
+    if let Some(prompt) = str_field("prompt").or_else(|| v4_base_caption(comment.get("v4_prompt"))) {
+        metadata.prompt = Some(prompt);
+    }
+    if let Some(negative) = str_field("uc").or_else(|| v4_base_caption(comment.get("v4_negative_prompt"))) {
+        metadata.negative_prompt = Some(negative);
+    }
+
+    if let Some(steps) = comment.get("steps").filter(|v| !v.is_null()) {
+        metadata.steps = Some(value_to_string(steps));
+    }
+    if let Some(scale) = comment.get("scale").filter(|v| !v.is_null()) {
+        metadata.cfg_scale = Some(value_to_string(scale));
+    }
+    if let Some(sampler) = str_field("sampler") {
+        metadata.sampler = Some(sampler);
+    }
+    if let Some(seed) = comment.get("seed").filter(|v| !v.is_null()) {
+        metadata.seed = Some(value_to_string(seed));
+    }
+    if let (Some(width), Some(height)) = (
+        comment.get("width").and_then(|v| v.as_u64()),
+        comment.get("height").and_then(|v| v.as_u64()),
+    ) {
+        metadata.size = Some(format!("{}x{}", width, height));
+    }
+
+    for key in ["noise_schedule", "cfg_rescale", "uncond_scale", "sm", "sm_dyn", "request_type"] {
+        if let Some(value) = comment.get(key).filter(|v| !v.is_null()) {
+            metadata.other.push((key.to_string(), value_to_string(value)));
+        }
+    }
+
+    let characters = v4_characters(comment);
+    if !characters.is_empty() {
+        if let Ok(json) = serde_json::to_string(&characters) {
+            metadata.other.push(("novelai_characters".to_string(), json));
+        }
+    }
+}
+
+fn v4_base_caption(prompt: Option<&Value>) -> Option<String> {
+    prompt?
+        .get("caption")?
+        .get("base_caption")?
+        .as_str()
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| s.to_string())
+}
+
+/// Pair up V4 character captions with their negative captions (same index) and positions
+fn v4_characters(comment: &Value) -> Vec<NovelAICharacter> {
+    let captions = |key: &str| -> Vec<Value> {
+        comment
+            .get(key)
+            .and_then(|p| p.get("caption"))
+            .and_then(|c| c.get("char_captions"))
+            .and_then(|c| c.as_array())
+            .cloned()
+            .unwrap_or_default()
+    };
+    let positives = captions("v4_prompt");
+    let negatives = captions("v4_negative_prompt");
+
+    positives
+        .iter()
+        .enumerate()
+        .filter_map(|(index, character)| {
+            let prompt = character.get("char_caption")?.as_str()?.to_string();
+            let negative_prompt = negatives
+                .get(index)
+                .and_then(|n| n.get("char_caption"))
+                .and_then(|v| v.as_str())
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| s.to_string());
+            let centers = character
+                .get("centers")
+                .and_then(|c| c.as_array())
+                .map(|centers| {
+                    centers
+                        .iter()
+                        .filter_map(|c| Some((c.get("x")?.as_f64()?, c.get("y")?.as_f64()?)))
+                        .collect()
+                })
+                .unwrap_or_default();
+            Some(NovelAICharacter { prompt, negative_prompt, centers })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(comment: &str) -> Vec<(String, String)> {
+        vec![
+            ("Title".to_string(), "NovelAI generated image".to_string()),
+            ("Description".to_string(), "2girls, cafe".to_string()),
+            ("Software".to_string(), "NovelAI".to_string()),
+            ("Source".to_string(), "NovelAI Diffusion V4 F6302A9D".to_string()),
+            ("Comment".to_string(), comment.to_string()),
+        ]
+    }
+
+    #[test]
+    fn test_novelai_v3_comment() {
+        let comment = r#"{"prompt": "1girl, silver hair, night sky", "steps": 28, "height": 1216, "width": 832, "scale": 5.0, "uncond_scale": 1.0, "cfg_rescale": 0.0, "seed": 3213123, "n_samples": 1, "noise_schedule": "native", "sampler": "k_euler_ancestral", "sm": false, "sm_dyn": false, "uc": "lowres, bad anatomy"}"#;
+
+        let fields = fields(comment);
+        assert!(is_novelai(&fields));
+
+        let mut metadata = ExtractedMetadata::empty();
+        apply_novelai_to_metadata(&fields, &mut metadata);
+
+        assert_eq!(metadata.generator, Some("novelai".to_string()));
+        assert_eq!(metadata.prompt, Some("1girl, silver hair, night sky".to_string()));
+        assert_eq!(metadata.negative_prompt, Some("lowres, bad anatomy".to_string()));
+        assert_eq!(metadata.steps, Some("28".to_string()));
+        assert_eq!(metadata.cfg_scale, Some("5.0".to_string()));
+        assert_eq!(metadata.sampler, Some("k_euler_ancestral".to_string()));
+        assert_eq!(metadata.seed, Some("3213123".to_string()));
+        assert_eq!(metadata.size, Some("832x1216".to_string()));
+        assert_eq!(metadata.model, Some("NovelAI Diffusion V4 F6302A9D".to_string()));
+        assert_eq!(metadata.stages[0].scheduler, Some("native".to_string()));
+        assert!(!metadata.other.iter().any(|(k, _)| k == "Comment"));
+    }
+
+    #[test]
+    fn test_partial_comment_keeps_earlier_values() {
+        let mut metadata = ExtractedMetadata::empty();
+        metadata.seed = Some("77".to_string());
+        metadata.sampler = Some("k_dpmpp_2m".to_string());
+        apply_novelai_to_metadata(&fields(r#"{"steps": 28}"#), &mut metadata);
+
+        assert_eq!(metadata.prompt, Some("2girls, cafe".to_string()));
+        assert_eq!(metadata.steps, Some("28".to_string()));
+        assert_eq!(metadata.seed, Some("77".to_string()));
+        assert_eq!(metadata.sampler, Some("k_dpmpp_2m".to_string()));
+    }
+
+    #[test]
+    fn test_novelai_v4_characters() {
+        let comment = r#"{
+            "prompt": "", "steps": 23, "width": 1024, "height": 1024, "scale": 5, "seed": 1, "sampler": "k_euler_ancestral",
+            "v4_prompt": {
+                "caption": {
+                    "base_caption": "2girls, cafe, window light",
+                    "char_captions": [
+                        { "char_caption": "girl, red hair", "centers": [{ "x": 0.3, "y": 0.5 }] },
+                        { "char_caption": "girl, blue hair", "centers": [{ "x": 0.7, "y": 0.5 }] }
+                    ]
+                },
+                "use_coords": true, "use_order": true
+            },
+            "v4_negative_prompt": {
+                "caption": {
+                    "base_caption": "blurry",
+                    "char_captions": [
+                        { "char_caption": "hat", "centers": [{ "x": 0.3, "y": 0.5 }] },
+                        { "char_caption": "", "centers": [{ "x": 0.7, "y": 0.5 }] }
+                    ]
+                }
+            },
+            "uc": ""
+        }"#;
+
+        let mut metadata = ExtractedMetadata::empty();
+        apply_novelai_to_metadata(&fields(comment), &mut metadata);
+
+        assert_eq!(metadata.prompt, Some("2girls, cafe, window light".to_string()));
+        assert_eq!(metadata.negative_prompt, Some("blurry".to_string()));
+
+        let characters_json = metadata
+            .other
+            .iter()
+            .find(|(k, _)| k == "novelai_characters")
+            .map(|(_, v)| v.clone())
+            .unwrap();
+        let characters: Vec<NovelAICharacter> = serde_json::from_str(&characters_json).unwrap();
+        assert_eq!(characters.len(), 2);
+        assert_eq!(characters[0].prompt, "girl, red hair");
+        assert_eq!(characters[0].negative_prompt, Some("hat".to_string()));
+        assert_eq!(characters[0].centers, vec![(0.3, 0.5)]);
+        assert_eq!(characters[1].negative_prompt, None);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::extraction::{ExtractedMetadata, GenerationStage};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Largest side any generator produces; anything bigger is a misparse
+const MAX_DIMENSION: u32 = 65536;
+const MAX_STEPS: u32 = 10000;
+const MAX_CFG: f32 = 100.0;
+/// Deepest CLIP layer skip any model supports
+const MAX_CLIP_SKIP: u32 = 24;
+
+/// `other` keys carrying clip skip, compared after lowercasing and dropping ` ` and `_`
+/// (A1111 `Clip skip`, InvokeAI/Fooocus `clip_skip`, SwarmUI `clipstopatlayer`)
+const CLIP_SKIP_KEYS: &[&str] = &["clipskip", "clipstopatlayer"];
+const DENOISE_KEYS: &[&str] = &["denoisingstrength", "denoise", "strength"];
+const SCHEDULER_KEYS: &[&str] = &["scheduletype", "scheduler"];
+
+/// Generation settings as numbers, so they can be compared and range-queried.
+///
+/// Built from the string fields every parser fills in; values that don't parse or are out
+/// of range are left out and explained in `warnings`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationParams {
+    pub seed: Option<u64>,
+    pub steps: Option<u32>,
+    pub cfg_scale: Option<f32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub denoise: Option<f32>,
+    pub clip_skip: Option<u32>,
+    pub sampler: Option<String>,
+    pub scheduler: Option<String>,
+    pub warnings: Vec<String>,
+}
+
+impl GenerationParams {
+    /// Parse and validate the generation settings of extracted metadata. Top-level fields win;
+    /// the base stage fills in what they lack.
+    pub fn from_metadata(metadata: &ExtractedMetadata) -> Self {
+        let base = metadata.stages.first();
+        let from_base = |field: fn(&GenerationStage) -> &Option<String>| base.and_then(|stage| field(stage).clone());
+        let mut params = GenerationParams::default();
+
+        if let Some(seed) = metadata.seed.clone().or_else(|| from_base(|s| &s.seed)) {
+            params.seed = params.parse_seed(&seed);
+        }
+        if let Some(steps) = metadata.steps.clone().or_else(|| from_base(|s| &s.steps)) {
+            params.steps = params.parse_in_range("steps", &steps, 1, MAX_STEPS);
+        }
+        if let Some(cfg) = metadata.cfg_scale.clone().or_else(|| from_base(|s| &s.cfg_scale)) {
+            params.cfg_scale = params.parse_in_range("cfg_scale", &cfg, 0.0, MAX_CFG);
+        }
+        if let Some(size) = metadata.size.clone().or_else(|| from_base(|s| &s.size)) {
+            match parse_size(&size) {
+                Some((width, height)) if (1..=MAX_DIMENSION).contains(&width) && (1..=MAX_DIMENSION).contains(&height) => {
+                    params.width = Some(width);
+                    params.height = Some(height);
+                }
+                _ => params.warn("size", &size),
+            }
+        }
+
+        // Denoise usually only appears on a stage (img2img base or hires pass)
+        let denoise = base
+            .and_then(|stage| stage.denoise.clone())
+            .or_else(|| metadata.stages.iter().find_map(|stage| stage.denoise.clone()))
+            .or_else(|| other_field(metadata, DENOISE_KEYS));
+        if let Some(denoise) = denoise {
+            params.denoise = params.parse_in_range("denoise", &denoise, 0.0, 1.0);
+        }
+        if let Some(clip_skip) = other_field(metadata, CLIP_SKIP_KEYS) {
+            // SwarmUI and ComfyUI count layers from the end: -2 is clip skip 2
+            let value = clip_skip.trim().trim_start_matches('-');
+            params.clip_skip = params.parse_in_range("clip_skip", value, 1, MAX_CLIP_SKIP);
+        }
+
+        params.sampler = metadata.sampler.clone().or_else(|| from_base(|s| &s.sampler));
+        params.scheduler = from_base(|s| &s.scheduler).or_else(|| other_field(metadata, SCHEDULER_KEYS));
+        params
+    }
+
+    /// Whether nothing could be parsed
+    pub fn is_empty(&self) -> bool {
+        self.seed.is_none()
+            && self.steps.is_none()
+            && self.cfg_scale.is_none()
+            && self.width.is_none()
+            && self.height.is_none()
+            && self.denoise.is_none()
+            && self.clip_skip.is_none()
+            && self.sampler.is_none()
+            && self.scheduler.is_none()
+    }
+
+    fn warn(&mut self, field: &str, value: &str) {
+        self.warnings.push(format!("{}: invalid value {:?}", field, value));
+    }
+
+    /// Seeds are unsigned 64-bit; A1111 writes `-1` for "random" when the real seed is lost
+    fn parse_seed(&mut self, value: &str) -> Option<u64> {
+        let seed = parse_number::<u64>(value);
+        if seed.is_none() {
+            self.warn("seed", value);
+        }
+        seed
+    }
+
+    fn parse_in_range<T: FromStr + PartialOrd>(&mut self, field: &str, value: &str, min: T, max: T) -> Option<T> {
+        let parsed = parse_number::<T>(value).filter(|v| *v >= min && *v <= max);
+        if parsed.is_none() {
+            self.warn(field, value);
+        }
+        parsed
+    }
+}
+
+/// Parse a number, accepting integral floats (`30.0`) where an integer is expected
+fn parse_number<T: FromStr>(value: &str) -> Option<T> {
+    let value = value.trim();
+    value.parse::<T>().ok().or_else(|| {
+        let float = value.parse::<f64>().ok().filter(|f| f.fract() == 0.0)?;
+        format!("{:.0}", float).parse::<T>().ok()
+    })
+}
+
+/// `832x1216`, `832 x 1216` or `832×1216`
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let (width, height) = size.split_once(['x', 'X', '×'])?;
+    Some((parse_number(width)?, parse_number(height)?))
+}
+
+fn other_field(metadata: &ExtractedMetadata, keys: &[&str]) -> Option<String> {
+    metadata
+        .other
+        .iter()
+        .find(|(key, _)| {
+            let key: String = key.to_lowercase().chars().filter(|c| *c != ' ' && *c != '_').collect();
+            keys.contains(&key.as_str())
+        })
+        .map(|(_, value)| value.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_params_from_a1111_fields() {
+        let mut metadata = ExtractedMetadata::empty();
+        metadata.seed = Some("3957893241".to_string());
+        metadata.steps = Some("30".to_string());
+        metadata.cfg_scale = Some("6.5".to_string());
+        metadata.size = Some("832x1216".to_string());
+        metadata.sampler = Some("DPM++ 2M".to_string());
+        metadata.other = vec![
+            ("Clip skip".to_string(), "2".to_string()),
+            ("Schedule type".to_string(), "Karras".to_string()),
+        ];
+        let mut hires = GenerationStage::new("hires_fix");
+        hires.denoise = Some("0.45".to_string());
+        metadata.stages = vec![GenerationStage::new("base"), hires];
+
+        let params = GenerationParams::from_metadata(&metadata);
+        assert_eq!(params.seed, Some(3957893241));
+        assert_eq!(params.steps, Some(30));
+        assert_eq!(params.cfg_scale, Some(6.5));
+        assert_eq!((params.width, params.height), (Some(832), Some(1216)));
+        assert_eq!(params.denoise, Some(0.45));
+        assert_eq!(params.clip_skip, Some(2));
+        assert_eq!(params.scheduler, Some("Karras".to_string()));
+        assert!(params.warnings.is_empty());
+
+        assert!(GenerationParams::default().is_empty());
+        assert!(!GenerationParams { height: Some(1216), ..Default::default() }.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_values_become_warnings() {
+        let mut metadata = ExtractedMetadata::empty();
+        metadata.seed = Some("-1".to_string());
+        metadata.steps = Some("0".to_string());
+        metadata.cfg_scale = Some("abc".to_string());
+        metadata.size = Some("1024".to_string());
+        let mut base = GenerationStage::new("base");
+        base.steps = Some("25.0".to_string());
+        base.denoise = Some("1.0".to_string());
+        metadata.stages = vec![base];
+        metadata.other = vec![("clipstopatlayer".to_string(), "-2".to_string())];
+
+        let params = GenerationParams::from_metadata(&metadata);
+        assert_eq!(params.seed, None);
+        // The top-level value wins even when it's invalid
+        assert_eq!(params.steps, None);
+        assert_eq!(params.cfg_scale, None);
+        assert_eq!(params.width, None);
+        assert_eq!(params.denoise, Some(1.0));
+        assert_eq!(params.clip_skip, Some(2));
+        assert_eq!(
+            params.warnings,
+            vec![
+                "seed: invalid value \"-1\"",
+                "steps: invalid value \"0\"",
+                "cfg_scale: invalid value \"abc\"",
+                "size: invalid value \"1024\"",
+            ]
+        );
+    }
+}
@@ -1,7 +1,12 @@
-use crate::extraction::png::extract_png_metadata;
-use crate::extraction::jpeg::extract_jpeg_metadata;
-use crate::extraction::webp::extract_webp_metadata;
+use crate::extraction::format::{sniff_format, FileFormat};
+use crate::extraction::registry::{ContainerView, ParserRegistry};
 use crate::extraction::normalizer::PromptNormalizer;
+use crate::extraction::generator::detect_generator;
+use crate::extraction::midjourney::detect_midjourney;
+use crate::extraction::dalle::detect_dalle;
+use crate::extraction::params::GenerationParams;
+use crate::extraction::sources::{FieldSource, SourceLocation};
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
 use serde::{Deserialize, Serialize};
 
@@ -16,27 +21,107 @@ pub struct ExtractedMetadata {
     pub cfg_scale: Option<String>,
     pub sampler: Option<String>,
     pub size: Option<String>,
+    pub generator: Option<String>, // tool that produced the image, e.g. "a1111", "comfyui", "novelai"
+    pub generator_version: Option<String>,
+    pub stages: Vec<GenerationStage>, // ordered passes: base, hires fix, refiner, upscale
     pub other: Vec<(String, String)>, // key-value pairs for other metadata
+    pub entries: Vec<MetadataEntry>, // descriptive metadata from IPTC, XMP, COM, ... with its source
+    pub keywords: Vec<String>, // tag candidates from IPTC Keywords / XMP dc:subject
+    pub params: GenerationParams, // typed seed/steps/cfg/size/..., filled in once parsing is done
+    pub sources: Vec<FieldSource>, // where prompt, seed, generator, ... were read from, and the values that lost
+}
+
+/// A key-value pair from a descriptive container, stored with its own `metadata_type`
+/// ("iptc", "xmp", "comment") instead of "generation"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetadataEntry {
+    pub key: String,
+    pub value: String,
+    pub metadata_type: String,
+}
+
+impl MetadataEntry {
+    pub fn new(key: &str, value: &str, metadata_type: &str) -> Self {
+        MetadataEntry {
+            key: key.to_string(),
+            value: value.to_string(),
+            metadata_type: metadata_type.to_string(),
+        }
+    }
+}
+
+/// One sampling (or upscaling) pass in a generation pipeline
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationStage {
+    pub kind: String, // "base", "hires_fix", "refiner", "upscale"
+    pub model: Option<String>,
+    pub sampler: Option<String>,
+    pub scheduler: Option<String>,
+    pub steps: Option<String>,
+    pub cfg_scale: Option<String>,
+    pub seed: Option<String>,
+    pub denoise: Option<String>,
+    pub size: Option<String>,
+    pub upscaler: Option<String>,
+    pub start_step: Option<String>,
+    pub end_step: Option<String>,
+}
+
+impl GenerationStage {
+    pub fn new(kind: &str) -> Self {
+        GenerationStage {
+            kind: kind.to_string(),
+            ..Default::default()
+        }
+    }
 }
 
 pub struct MetadataExtractor;
 
 impl MetadataExtractor {
     pub fn extract<P: AsRef<Path>>(path: P) -> anyhow::Result<ExtractedMetadata> {
+        Self::extract_with(path, &ParserRegistry::default())
+    }
+
+    /// Extract with the parsers of a registry, e.g. the defaults plus in-house ones
+    pub fn extract_with<P: AsRef<Path>>(path: P, registry: &ParserRegistry) -> anyhow::Result<ExtractedMetadata> {
         let path = path.as_ref();
-        
-        // Detect format by extension
-        let ext = path.extension()
-            .and_then(|e| e.to_str())
-            .map(|s| s.to_lowercase())
-            .unwrap_or_default();
-
-        let mut metadata = match ext.as_str() {
-            "png" => extract_png_metadata(path)?,
-            "jpg" | "jpeg" => extract_jpeg_metadata(path)?,
-            "webp" => extract_webp_metadata(path)?,
-            _ => ExtractedMetadata::empty(),
-        };
+        let data = std::fs::read(path)?;
+        // The extension only helps when the content is not recognized
+        let format = sniff_format(&data).or_else(|| FileFormat::from_path(path));
+        Ok(Self::extract_data(&data, format, registry))
+    }
+
+    /// Extract from an image held in memory (an upload, an archive entry, an object
+    /// storage download); the format is detected from the content
+    pub fn extract_from_bytes(data: &[u8]) -> anyhow::Result<ExtractedMetadata> {
+        Self::extract_from_bytes_with(data, &ParserRegistry::default())
+    }
+
+    pub fn extract_from_bytes_with(data: &[u8], registry: &ParserRegistry) -> anyhow::Result<ExtractedMetadata> {
+        Ok(Self::extract_data(data, sniff_format(data), registry))
+    }
+
+    /// Extract from a reader, from its current position to the end
+    pub fn extract_from_reader<R: Read + Seek>(mut reader: R) -> anyhow::Result<ExtractedMetadata> {
+        let start = reader.stream_position()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(start))?;
+
+        let mut data = Vec::with_capacity(end.saturating_sub(start) as usize);
+        reader.read_to_end(&mut data)?;
+        Self::extract_from_bytes(&data)
+    }
+
+    /// Extract from bytes whose format the caller already determined
+    pub(crate) fn extract_data(data: &[u8], format: Option<FileFormat>, registry: &ParserRegistry) -> ExtractedMetadata {
+        let mut metadata = registry.parse(&ContainerView::new(data, format));
+
+        // Tools without a dedicated container format are recognized from the collected fields
+        let fields = SourceLocation::new("fields", "", None, "");
+        metadata.record_changes(&fields.with_parser("midjourney"), detect_midjourney);
+        metadata.record_changes(&fields.with_parser("dalle"), detect_dalle);
+        metadata.record_changes(&fields.with_parser("generator"), detect_generator);
 
         // Normalize prompts
         if let Some(ref mut prompt) = metadata.prompt {
@@ -47,7 +132,9 @@ impl MetadataExtractor {
             *neg_prompt = PromptNormalizer::normalize(neg_prompt);
         }
 
-        Ok(metadata)
+        metadata.params = GenerationParams::from_metadata(&metadata);
+
+        metadata
     }
 }
 
@@ -63,8 +150,58 @@ impl ExtractedMetadata {
             cfg_scale: None,
             sampler: None,
             size: None,
+            generator: None,
+            generator_version: None,
+            stages: Vec::new(),
             other: Vec::new(),
+            entries: Vec::new(),
+            keywords: Vec::new(),
+            params: GenerationParams::default(),
+            sources: Vec::new(),
         }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_extract_from_bytes_and_reader() {
+        let text = b"parameters\0a paper crane\nSteps: 20, Sampler: Euler a, Seed: 5";
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend_from_slice(&(text.len() as u32).to_be_bytes());
+        png.extend_from_slice(b"tEXt");
+        png.extend_from_slice(text);
+        png.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        png.extend_from_slice(b"IEND");
+        let metadata = MetadataExtractor::extract_from_bytes(&png).unwrap();
+        assert_eq!(metadata.prompt.as_deref(), Some("a paper crane"));
+        assert_eq!(metadata.params.steps, Some(20));
+
+        let comment = b"a watercolor heron";
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xfe];
+        jpeg.extend_from_slice(&((comment.len() + 2) as u16).to_be_bytes());
+        jpeg.extend_from_slice(comment);
+        jpeg.extend_from_slice(&[0xff, 0xd9]);
+
+        // The reader is read from its current position, e.g. an entry inside a bundle
+        let mut bundle = b"header".to_vec();
+        bundle.extend_from_slice(&jpeg);
+        let mut reader = Cursor::new(bundle);
+        reader.set_position(6);
+        let metadata = MetadataExtractor::extract_from_reader(reader).unwrap();
+        assert_eq!(metadata.prompt.as_deref(), Some("a watercolor heron"));
+
+        let xmp = r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+            <rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/">
+            <dc:description>a lantern festival</dc:description></rdf:Description></rdf:RDF>"#;
+        let mut webp = b"RIFF\0\0\0\0WEBPXMP ".to_vec();
+        webp.extend_from_slice(&(xmp.len() as u32).to_le_bytes());
+        webp.extend_from_slice(xmp.as_bytes());
+        let metadata = MetadataExtractor::extract_from_reader(Cursor::new(webp)).unwrap();
+        assert_eq!(metadata.prompt.as_deref(), Some("a lantern festival"));
+    }
+}
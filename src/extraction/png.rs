@@ -1,73 +1,145 @@
-use crate::extraction::{ExtractedMetadata, apply_comfyui_to_metadata};
+use crate::extraction::{ExtractedMetadata, SourceLocation, apply_comfyui_to_metadata, apply_comfyui_ui_to_metadata};
+use crate::extraction::a1111::{apply_a1111_to_metadata, parse_a1111_parameters};
+use crate::extraction::c2pa::{apply_c2pa_to_metadata, parse_manifest_store};
+use crate::extraction::fooocus::{apply_fooocus_to_metadata, is_fooocus_parameters};
+use crate::extraction::invokeai::{apply_invokeai_to_metadata, is_invokeai, INVOKEAI_KEYS};
+use crate::extraction::novelai::{apply_novelai_to_metadata, is_novelai, NOVELAI_KEYS};
+use crate::extraction::stealth::apply_stealth_pnginfo;
+use crate::extraction::swarmui::{apply_swarmui_to_metadata, is_swarmui_parameters};
+use crate::extraction::xmp::{apply_xmp_to_metadata, XmpPacket};
+use serde_json::Value;
+use std::io::Read;
 use std::path::Path;
 
 pub fn extract_png_metadata<P: AsRef<Path>>(path: P) -> anyhow::Result<ExtractedMetadata> {
-    let path = path.as_ref();
-    
-    // Read the PNG file and parse text chunks manually
+    let file_data = std::fs::read(path.as_ref())?;
+    extract_png_metadata_from_bytes(&file_data)
+}
+
+pub fn extract_png_metadata_from_bytes(file_data: &[u8]) -> anyhow::Result<ExtractedMetadata> {
     // The png crate doesn't expose text chunks directly, so we parse the file manually
-    let file_data = std::fs::read(path)?;
-    let text_chunks = parse_png_text_chunks(&file_data)?;
+    let text_chunks = parse_png_text_chunks(file_data)?;
 
     let mut metadata = ExtractedMetadata::empty();
+    let location = |chunk: &PngTextChunk, parser: &str| {
+        SourceLocation::new(&format!("png:{}", chunk.chunk_type), &chunk.keyword, Some(chunk.offset), parser)
+    };
+
+    // NovelAI stores everything in Description/Source/Comment chunks, tagged by Software
+    let fields: Vec<(String, String)> = text_chunks
+        .iter()
+        .map(|c| (c.keyword.clone(), c.text.clone()))
+        .collect();
+    let novelai = is_novelai(&fields);
+    if novelai {
+        let novelai_fields: Vec<(String, String)> = fields
+            .iter()
+            .filter(|(key, _)| NOVELAI_KEYS.contains(&key.as_str()))
+            .cloned()
+            .collect();
+        metadata.record_source(&group_location(&text_chunks, NOVELAI_KEYS, "novelai"), |m| {
+            apply_novelai_to_metadata(&novelai_fields, m)
+        });
+    }
+
+    // InvokeAI uses its own chunk keys (invokeai_metadata, sd-metadata, Dream)
+    let invokeai = !novelai && is_invokeai(&fields);
+    if invokeai {
+        let invokeai_fields: Vec<(String, String)> = fields
+            .iter()
+            .filter(|(key, _)| INVOKEAI_KEYS.contains(&key.as_str()))
+            .cloned()
+            .collect();
+        metadata.record_source(&group_location(&text_chunks, INVOKEAI_KEYS, "invokeai"), |m| {
+            apply_invokeai_to_metadata(&invokeai_fields, m)
+        });
+    }
 
     // Parse parameters field (most common in Stable Diffusion)
-    // Process parameters first to extract ComfyUI prompts
-    let mut has_comfyui_workflow = false;
-    for (key, value) in &text_chunks {
-        if key == "parameters" && value.trim_start().starts_with('{') {
-            has_comfyui_workflow = true;
+    // A JSON value may be a ComfyUI graph, Fooocus parameters or SwarmUI metadata;
+    // process it first so the generator-specific parser sets the readable prompt
+    let fooocus_scheme = fields.iter().any(|(key, _)| key == "fooocus_scheme");
+    let mut has_json_parameters = false;
+    for chunk in &text_chunks {
+        let value = &chunk.text;
+        if chunk.keyword == "parameters" && value.trim_start().starts_with('{') {
+            has_json_parameters = true;
             metadata.parameters = Some(value.clone());
-            // Try to parse as ComfyUI workflow - this will set metadata.prompt to readable text
-            apply_comfyui_to_metadata(value, &mut metadata);
+            let json = serde_json::from_str::<Value>(value).ok();
+            let parser = match &json {
+                Some(json) if is_swarmui_parameters(json) => "swarmui",
+                Some(json) if fooocus_scheme || is_fooocus_parameters(json) => "fooocus",
+                // Try to parse as ComfyUI workflow - this will set metadata.prompt to readable text
+                _ => "comfyui",
+            };
+            metadata.record_source(&location(chunk, parser), |m| match (parser, &json) {
+                ("swarmui", Some(json)) => apply_swarmui_to_metadata(json, m),
+                ("fooocus", Some(json)) => apply_fooocus_to_metadata(json, m),
+                _ => apply_comfyui_to_metadata(value, m),
+            });
             break; // Process parameters first, then continue with other chunks
         }
     }
     
     // Now process all chunks
-    for (key, value) in &text_chunks {
+    let mut ui_workflow = None;
+    let mut xmp_packet = None;
+    for chunk in &text_chunks {
+        let (key, value) = (&chunk.keyword, &chunk.text);
+        if novelai && NOVELAI_KEYS.contains(&key.as_str()) {
+            continue; // Already handled by the NovelAI parser
+        }
+        if invokeai && INVOKEAI_KEYS.contains(&key.as_str()) {
+            continue; // Already handled by the InvokeAI parser
+        }
         match key.as_str() {
             "parameters" => {
                 // Already processed above if it's ComfyUI
-                if !has_comfyui_workflow {
+                if !has_json_parameters {
                     metadata.parameters = Some(value.clone());
                     // Try to parse as standard Stable Diffusion parameters string
-                    parse_parameters_string(value, &mut metadata);
+                    metadata.record_source(&location(chunk, "a1111"), |m| parse_parameters_string(value, m));
                 }
             }
             "prompt" => {
-                // Only set if we don't already have a prompt from ComfyUI
-                // Also check if this "prompt" field is actually JSON (ComfyUI workflow)
-                // If it is, don't use it as the prompt - we've already extracted the readable prompt
-                if metadata.prompt.is_none() {
-                    // If it's JSON, try to extract readable prompt from it
-                    if value.trim_start().starts_with('{') {
-                        apply_comfyui_to_metadata(value, &mut metadata);
+                // A JSON "prompt" is a ComfyUI API graph: read the readable prompt out of it
+                // rather than storing the JSON. A prompt from an earlier chunk still wins.
+                let is_json = value.trim_start().starts_with('{');
+                metadata.record_source(&location(chunk, if is_json { "comfyui" } else { "text" }), |m| {
+                    if is_json {
+                        apply_comfyui_to_metadata(value, m);
                     } else {
-                        metadata.prompt = Some(value.clone());
+                        m.prompt = Some(value.clone());
                     }
-                }
-            }
-            "negative_prompt" => {
-                metadata.negative_prompt = Some(value.clone());
+                });
             }
-            "model" => {
-                metadata.model = Some(value.clone());
+            key if TEXT_FIELD_KEYS.contains(&key) => {
+                metadata.record_source(&location(chunk, "text"), |m| {
+                    set_text_field(key, value, m);
+                });
             }
-            "seed" => {
-                metadata.seed = Some(value.clone());
-            }
-            "steps" => {
-                metadata.steps = Some(value.clone());
+            "sui_image_params" => {
+                // Some SwarmUI versions write the params under their own key
+                match serde_json::from_str::<Value>(value) {
+                    Ok(json) => metadata.record_source(&location(chunk, "swarmui"), |m| apply_swarmui_to_metadata(&json, m)),
+                    Err(_) => metadata.other.push((key.clone(), value.clone())),
+                }
             }
-            "cfg_scale" | "CFG scale" => {
-                metadata.cfg_scale = Some(value.clone());
+            "fooocus_scheme" => {
+                // With the "a1111" scheme, Fooocus writes an A1111-style parameters string
+                metadata.record_source(&location(chunk, "fooocus"), |m| {
+                    m.generator.get_or_insert_with(|| "fooocus".to_string());
+                });
+                metadata.other.push((key.clone(), value.clone()));
             }
-            "sampler" => {
-                metadata.sampler = Some(value.clone());
+            "XML:com.adobe.xmp" => {
+                xmp_packet = XmpPacket::parse(value).ok().map(|packet| (packet, chunk));
             }
-            "size" => {
-                metadata.size = Some(value.clone());
+            "workflow" => {
+                metadata.other.push((key.clone(), value.clone()));
+                if value.trim_start().starts_with('{') {
+                    ui_workflow = Some(chunk);
+                }
             }
             _ => {
                 metadata.other.push((key.clone(), value.clone()));
@@ -75,14 +147,88 @@ pub fn extract_png_metadata<P: AsRef<Path>>(path: P) -> anyhow::Result<Extracted
         }
     }
 
+    // The UI-format workflow only fills gaps left by the API graph and text chunks,
+    // e.g. images written by custom save nodes that omit the "prompt" chunk
+    if let Some(chunk) = ui_workflow {
+        metadata.record_source(&location(chunk, "comfyui_ui"), |m| apply_comfyui_ui_to_metadata(&chunk.text, m));
+    }
+
+    // XMP (iTXt "XML:com.adobe.xmp") only fills what the generator's own chunks left empty
+    if let Some((packet, chunk)) = xmp_packet {
+        apply_xmp_to_metadata(&packet, &location(chunk, "xmp"), &mut metadata);
+    }
+
+    // Content Credentials (C2PA) manifest store
+    if let Some(jumbf) = find_png_chunk(file_data, b"caBX") {
+        apply_c2pa_to_metadata(&parse_manifest_store(jumbf), &mut metadata);
+    }
+
+    // Some tools (NovelAI, the stealth-pnginfo extension) hide metadata in pixel LSBs,
+    // which survives sites that strip text chunks. Decoding is costly, so only look there
+    // when no prompt or parameters were found; unrelated chunks (Software, Title) don't count.
+    if metadata.prompt.is_none() && metadata.parameters.is_none() {
+        metadata.record_source(&SourceLocation::new("png:IDAT", "stealth_pnginfo", None, "stealth"), |m| {
+            apply_stealth_pnginfo(file_data, m);
+        });
+    }
+
     Ok(metadata)
 }
 
-fn parse_png_text_chunks(data: &[u8]) -> anyhow::Result<Vec<(String, String)>> {
+/// Keys under which some tools write single generation fields as their own text chunks
+pub(crate) const TEXT_FIELD_KEYS: &[&str] =
+    &["prompt", "negative_prompt", "model", "seed", "steps", "cfg_scale", "CFG scale", "sampler", "size"];
+
+/// Set the generation field named by one of `TEXT_FIELD_KEYS`
+pub(crate) fn set_text_field(key: &str, value: &str, metadata: &mut ExtractedMetadata) {
+    let field = match key {
+        "prompt" => &mut metadata.prompt,
+        "negative_prompt" => &mut metadata.negative_prompt,
+        "model" => &mut metadata.model,
+        "seed" => &mut metadata.seed,
+        "steps" => &mut metadata.steps,
+        "cfg_scale" | "CFG scale" => &mut metadata.cfg_scale,
+        "sampler" => &mut metadata.sampler,
+        "size" => &mut metadata.size,
+        _ => return,
+    };
+    *field = Some(value.to_string());
+}
+
+/// Location of a parser that reads several chunks: their keywords, at the first one's offset
+fn group_location(chunks: &[PngTextChunk], keys: &[&str], parser: &str) -> SourceLocation {
+    let group: Vec<&PngTextChunk> = chunks.iter().filter(|c| keys.contains(&c.keyword.as_str())).collect();
+    let keywords: Vec<&str> = group.iter().map(|c| c.keyword.as_str()).collect();
+    let container = group.first().map_or("png".to_string(), |c| format!("png:{}", c.chunk_type));
+    SourceLocation::new(&container, &keywords.join(", "), group.first().map(|c| c.offset), parser)
+}
+
+/// A decoded PNG text chunk (`tEXt`, `zTXt` or `iTXt`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PngTextChunk {
+    /// `tEXt`, `zTXt` or `iTXt`
+    pub chunk_type: &'static str,
+    /// Byte offset of the chunk (its length field) in the file
+    pub offset: u64,
+    pub keyword: String,
+    pub text: String,
+    /// Whether the text was stored zlib-compressed (always true for `zTXt`)
+    pub compressed: bool,
+    /// RFC 3066 language tag, only present on `iTXt` chunks
+    pub language_tag: Option<String>,
+    /// Keyword translated into the language of `language_tag`, only present on `iTXt` chunks
+    pub translated_keyword: Option<String>,
+}
+
+/// Upper bound for a single decompressed text chunk, to guard against zlib bombs
+const MAX_DECOMPRESSED_TEXT_SIZE: u64 = 16 * 1024 * 1024;
+
+pub(crate) fn parse_png_text_chunks(data: &[u8]) -> anyhow::Result<Vec<PngTextChunk>> {
     let mut chunks = Vec::new();
     let mut offset = 8; // Skip PNG signature
 
     while offset < data.len() {
+        let chunk_start = offset as u64;
         if offset + 8 > data.len() {
             break;
         }
@@ -114,16 +260,16 @@ fn parse_png_text_chunks(data: &[u8]) -> anyhow::Result<Vec<(String, String)>> {
         }
 
         // Read chunk data
-        if chunk_type == "tEXt" && length > 0 {
+        if length > 0 {
             let chunk_data = &data[offset..offset + length];
-            
-            // tEXt format: keyword (null-terminated) + text (null-terminated)
-            if let Some(null_pos) = chunk_data.iter().position(|&b| b == 0) {
-                let keyword = String::from_utf8_lossy(&chunk_data[..null_pos]).to_string();
-                if null_pos + 1 < chunk_data.len() {
-                    let text = String::from_utf8_lossy(&chunk_data[null_pos + 1..]).to_string();
-                    chunks.push((keyword, text));
-                }
+            let parsed = match chunk_type.as_str() {
+                "tEXt" => parse_text_chunk(chunk_data, chunk_start),
+                "zTXt" => parse_ztxt_chunk(chunk_data, chunk_start),
+                "iTXt" => parse_itxt_chunk(chunk_data, chunk_start),
+                _ => None,
+            };
+            if let Some(chunk) = parsed {
+                chunks.push(chunk);
             }
         }
 
@@ -134,58 +280,138 @@ fn parse_png_text_chunks(data: &[u8]) -> anyhow::Result<Vec<(String, String)>> {
     Ok(chunks)
 }
 
-pub(crate) fn parse_parameters_string(params: &str, metadata: &mut ExtractedMetadata) {
-    // Parameters string format:
-    // "prompt text
-    // Negative prompt: negative prompt text
-    // Steps: 20, Sampler: Euler a, CFG scale: 7, Seed: 12345, Size: 512x512, Model: ..."
-    
-    let lines: Vec<&str> = params.lines().collect();
-    
-    if lines.is_empty() {
-        return;
+/// Data of the first chunk of the given type, for binary chunks the text parser skips
+pub(crate) fn find_png_chunk<'a>(data: &'a [u8], chunk_type: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut offset = 8; // Skip PNG signature
+    while offset + 8 <= data.len() {
+        let length = u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]) as usize;
+        let kind = &data[offset + 4..offset + 8];
+        let start = offset + 8;
+        if kind == b"IEND" || start + length > data.len() {
+            break;
+        }
+        if kind == chunk_type {
+            return Some(&data[start..start + length]);
+        }
+        offset = start + length + 4; // Skip CRC
     }
+    None
+}
 
-    // First line is usually the positive prompt
-    if metadata.prompt.is_none() {
-        metadata.prompt = Some(lines[0].trim().to_string());
+/// tEXt format: keyword (null-terminated) + Latin-1 text
+fn parse_text_chunk(chunk_data: &[u8], offset: u64) -> Option<PngTextChunk> {
+    let null_pos = chunk_data.iter().position(|&b| b == 0)?;
+    let keyword = decode_latin1(&chunk_data[..null_pos]);
+    let text = &chunk_data[null_pos + 1..];
+    if text.is_empty() {
+        return None;
     }
 
-    // Look for negative prompt
-    for line in &lines {
-        if line.trim().starts_with("Negative prompt:") {
-            let neg_prompt = line.trim().strip_prefix("Negative prompt:").unwrap_or("").trim();
-            if !neg_prompt.is_empty() {
-                metadata.negative_prompt = Some(neg_prompt.to_string());
-            }
-        }
+    Some(PngTextChunk {
+        chunk_type: "tEXt",
+        offset,
+        keyword,
+        text: decode_latin1(text),
+        compressed: false,
+        language_tag: None,
+        translated_keyword: None,
+    })
+}
+
+/// zTXt format: keyword (null-terminated) + compression method (1 byte) + zlib-compressed Latin-1 text
+fn parse_ztxt_chunk(chunk_data: &[u8], offset: u64) -> Option<PngTextChunk> {
+    let null_pos = chunk_data.iter().position(|&b| b == 0)?;
+    let keyword = decode_latin1(&chunk_data[..null_pos]);
+    let compression_method = *chunk_data.get(null_pos + 1)?;
+    if compression_method != 0 {
+        // 0 (zlib deflate) is the only method defined by the PNG spec
+        return None;
     }
 
-    // Parse parameters from the last line or throughout
-    let params_line = lines.last().unwrap_or(&"");
-    
-    // Extract common parameters using regex-like parsing
-    for part in params_line.split(',') {
-        let part = part.trim();
-        
-        if part.starts_with("Steps:") {
-            metadata.steps = part.strip_prefix("Steps:").map(|s| s.trim().to_string());
-        } else if part.starts_with("Sampler:") {
-            metadata.sampler = part.strip_prefix("Sampler:").map(|s| s.trim().to_string());
-        } else if part.starts_with("CFG scale:") {
-            metadata.cfg_scale = part.strip_prefix("CFG scale:").map(|s| s.trim().to_string());
-        } else if part.starts_with("Seed:") {
-            metadata.seed = part.strip_prefix("Seed:").map(|s| s.trim().to_string());
-        } else if part.starts_with("Size:") {
-            metadata.size = part.strip_prefix("Size:").map(|s| s.trim().to_string());
-        } else if part.starts_with("Model:") {
-            metadata.model = part.strip_prefix("Model:").map(|s| s.trim().to_string());
-        } else if part.contains("Model hash:") {
-            // Skip model hash, but could store it in other
+    let text = inflate_text(&chunk_data[null_pos + 2..])?;
+    if text.is_empty() {
+        return None;
+    }
+
+    Some(PngTextChunk {
+        chunk_type: "zTXt",
+        offset,
+        keyword,
+        text: decode_latin1(&text),
+        compressed: true,
+        language_tag: None,
+        translated_keyword: None,
+    })
+}
+
+/// iTXt format: keyword (null-terminated) + compression flag (1 byte) + compression method (1 byte)
+/// + language tag (null-terminated) + translated keyword (null-terminated, UTF-8) + UTF-8 text
+fn parse_itxt_chunk(chunk_data: &[u8], offset: u64) -> Option<PngTextChunk> {
+    let null_pos = chunk_data.iter().position(|&b| b == 0)?;
+    let keyword = decode_latin1(&chunk_data[..null_pos]);
+    let compressed = *chunk_data.get(null_pos + 1)? != 0;
+    let compression_method = *chunk_data.get(null_pos + 2)?;
+
+    let rest = chunk_data.get(null_pos + 3..)?;
+    let lang_end = rest.iter().position(|&b| b == 0)?;
+    let language_tag = String::from_utf8_lossy(&rest[..lang_end]).to_string();
+
+    let rest = &rest[lang_end + 1..];
+    let translated_end = rest.iter().position(|&b| b == 0)?;
+    let translated_keyword = String::from_utf8_lossy(&rest[..translated_end]).to_string();
+
+    let text_data = &rest[translated_end + 1..];
+    let text = if compressed {
+        if compression_method != 0 {
+            return None;
         }
+        String::from_utf8_lossy(&inflate_text(text_data)?).to_string()
+    } else {
+        String::from_utf8_lossy(text_data).to_string()
+    };
+
+    if text.is_empty() {
+        return None;
+    }
+
+    Some(PngTextChunk {
+        chunk_type: "iTXt",
+        offset,
+        keyword,
+        text,
+        compressed,
+        language_tag: (!language_tag.is_empty()).then_some(language_tag),
+        translated_keyword: (!translated_keyword.is_empty()).then_some(translated_keyword),
+    })
+}
+
+fn inflate_text(compressed: &[u8]) -> Option<Vec<u8>> {
+    let decoder = flate2::read::ZlibDecoder::new(compressed);
+    let mut out = Vec::new();
+    decoder
+        .take(MAX_DECOMPRESSED_TEXT_SIZE)
+        .read_to_end(&mut out)
+        .ok()?;
+    Some(out)
+}
+
+/// Decode Latin-1 text, accepting UTF-8 since many generators write it into tEXt regardless of the spec
+fn decode_latin1(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_string(),
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
     }
 }
 
+pub(crate) fn parse_parameters_string(params: &str, metadata: &mut ExtractedMetadata) {
+    // Parameters string format:
+    // "prompt text (possibly several lines)
+    // Negative prompt: negative prompt text (possibly several lines)
+    // Steps: 20, Sampler: Euler a, CFG scale: 7, Seed: 12345, Size: 512x512, Model: ..."
+    let parsed = parse_a1111_parameters(params);
+    apply_a1111_to_metadata(&parsed, metadata);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -208,5 +434,157 @@ Steps: 20, Sampler: Euler a, CFG scale: 7, Seed: 12345, Size: 512x512, Model: st
         assert_eq!(metadata.size, Some("512x512".to_string()));
         assert_eq!(metadata.model, Some("stable-diffusion-v1-5".to_string()));
     }
-}
 
+    fn png_with_chunk(chunk_type: &[u8; 4], chunk_data: &[u8]) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        data.extend_from_slice(&(chunk_data.len() as u32).to_be_bytes());
+        data.extend_from_slice(chunk_type);
+        data.extend_from_slice(chunk_data);
+        data.extend_from_slice(&[0, 0, 0, 0]); // CRC is not verified
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(b"IEND");
+        data
+    }
+
+    fn zlib(text: &[u8]) -> Vec<u8> {
+        use flate2::write::ZlibEncoder;
+        use std::io::Write;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(text).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_parse_ztxt_chunk() {
+        let mut chunk = b"parameters\0\0".to_vec();
+        chunk.extend(zlib(b"a castle\nSteps: 20, Seed: 42"));
+
+        let chunks = parse_png_text_chunks(&png_with_chunk(b"zTXt", &chunk)).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].keyword, "parameters");
+        assert_eq!(chunks[0].text, "a castle\nSteps: 20, Seed: 42");
+        assert!(chunks[0].compressed);
+    }
+
+    #[test]
+    fn test_parse_itxt_chunk() {
+        let text = "富士山, 夕焼け";
+
+        let mut chunk = b"prompt\0\0\0ja\0\xe3\x83\x97\xe3\x83\xad\xe3\x83\xb3\xe3\x83\x97\xe3\x83\x88\0".to_vec();
+        chunk.extend_from_slice(text.as_bytes());
+        let chunks = parse_png_text_chunks(&png_with_chunk(b"iTXt", &chunk)).unwrap();
+        assert_eq!(chunks[0].keyword, "prompt");
+        assert_eq!(chunks[0].text, text);
+        assert!(!chunks[0].compressed);
+        assert_eq!(chunks[0].language_tag.as_deref(), Some("ja"));
+        assert_eq!(chunks[0].translated_keyword.as_deref(), Some("プロンプト"));
+
+        let mut chunk = b"prompt\0\x01\0\0\0".to_vec();
+        chunk.extend(zlib(text.as_bytes()));
+        let chunks = parse_png_text_chunks(&png_with_chunk(b"iTXt", &chunk)).unwrap();
+        assert_eq!(chunks[0].text, text);
+        assert!(chunks[0].compressed);
+        assert_eq!(chunks[0].language_tag, None);
+    }
+
+    #[test]
+    fn test_parse_text_chunk_latin1() {
+        let chunks = parse_png_text_chunks(&png_with_chunk(b"tEXt", b"prompt\0caf\xe9")).unwrap();
+        assert_eq!(chunks[0].text, "café");
+    }
+
+    #[test]
+    fn test_parse_parameters_hires_stages() {
+        let params = "a knight in ornate armor
+Negative prompt: lowres
+Steps: 28, Sampler: DPM++ 2M, Schedule type: Karras, CFG scale: 6, Seed: 7, Size: 512x768, Model: dreamshaper_8, Denoising strength: 0.45, Hires upscale: 2, Hires steps: 12, Hires upscaler: 4x-UltraSharp";
+
+        let mut metadata = ExtractedMetadata::empty();
+        parse_parameters_string(params, &mut metadata);
+
+        assert_eq!(metadata.stages.len(), 2);
+        let base = &metadata.stages[0];
+        assert_eq!(base.kind, "base");
+        assert_eq!(base.steps, Some("28".to_string()));
+        assert_eq!(base.scheduler, Some("Karras".to_string()));
+        assert_eq!(base.denoise, None);
+
+        let hires = &metadata.stages[1];
+        assert_eq!(hires.kind, "hires_fix");
+        assert_eq!(hires.steps, Some("12".to_string()));
+        assert_eq!(hires.denoise, Some("0.45".to_string()));
+        assert_eq!(hires.upscaler, Some("4x-UltraSharp".to_string()));
+        assert_eq!(hires.size, Some("1024x1536".to_string()));
+        assert_eq!(hires.sampler, Some("DPM++ 2M".to_string()));
+    }
+
+    #[test]
+    fn test_c2pa_manifest_in_cabx_chunk() {
+        let png = png_with_chunk(b"caBX", &crate::extraction::c2pa::tests::manifest_store());
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("chatgpt.png");
+        std::fs::write(&path, png).unwrap();
+
+        let metadata = crate::extraction::MetadataExtractor::extract(&path).unwrap();
+        let provenance = |key: &str| {
+            metadata.entries.iter().find(|e| e.key == key && e.metadata_type == "provenance").map(|e| e.value.as_str())
+        };
+        assert_eq!(provenance("c2pa.claim_generator"), Some("ChatGPT 1.0"));
+        assert_eq!(provenance("c2pa.actions"), Some("c2pa.created"));
+        assert_eq!(
+            provenance("c2pa.digital_source_type"),
+            Some("http://cv.iptc.org/newscodes/digitalsourcetype/trainedAlgorithmicMedia")
+        );
+        assert_eq!(metadata.generator, Some("dalle".to_string()));
+    }
+
+    #[test]
+    fn test_sui_image_params_fill_gaps_after_a_prompt_chunk() {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let params = r#"{"prompt": "from swarmui", "model": "sdxl", "loras": ["detail"], "loraweights": [0.8]}"#;
+        for text in [b"prompt\0a lantern".to_vec(), [b"sui_image_params\0".as_slice(), params.as_bytes()].concat()] {
+            png.extend_from_slice(&(text.len() as u32).to_be_bytes());
+            png.extend_from_slice(b"tEXt");
+            png.extend_from_slice(&text);
+            png.extend_from_slice(&[0, 0, 0, 0]);
+        }
+        png.extend_from_slice(&[0, 0, 0, 0]);
+        png.extend_from_slice(b"IEND");
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("swarm.png");
+        std::fs::write(&path, png).unwrap();
+
+        let metadata = extract_png_metadata(&path).unwrap();
+        assert_eq!(metadata.prompt.as_deref(), Some("a lantern"));
+        assert_eq!(metadata.model.as_deref(), Some("sdxl"));
+        assert_eq!(metadata.source_of("model").unwrap().location.parser, "swarmui");
+        assert!(metadata.other.iter().any(|(key, value)| key == "lora" && value == "detail:0.8"));
+        assert!(!metadata.other.iter().any(|(key, _)| key == "sui_image_params"));
+    }
+
+    #[test]
+    fn test_json_parameters_are_routed_by_shape() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cases = [
+            (r#"{"metadata_scheme": "fooocus", "prompt": "a lantern", "base_model": "juggernautXL.safetensors", "steps": 30}"#, "fooocus"),
+            (r#"{"sui_image_params": {"prompt": "a lantern", "model": "sdxl", "steps": 20}}"#, "swarmui"),
+        ];
+
+        for (index, (params, generator)) in cases.iter().enumerate() {
+            let mut chunk = b"parameters\0".to_vec();
+            chunk.extend_from_slice(params.as_bytes());
+            let path = dir.path().join(format!("{}.png", index));
+            std::fs::write(&path, png_with_chunk(b"tEXt", &chunk)).unwrap();
+
+            let metadata = extract_png_metadata(&path).unwrap();
+            assert_eq!(metadata.generator.as_deref(), Some(*generator));
+            assert_eq!(metadata.prompt, Some("a lantern".to_string()));
+
+            // The offset is where the chunk starts, right after the signature here
+            let source = metadata.source_of("prompt").unwrap();
+            assert_eq!(source.location, SourceLocation::new("png:tEXt", "parameters", Some(8), generator));
+        }
+    }
+}
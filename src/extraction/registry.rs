@@ -0,0 +1,292 @@
+use crate::extraction::exif::extract_tiff_metadata_from_bytes;
+use crate::extraction::format::FileFormat;
+use crate::extraction::isobmff::extract_isobmff_metadata_from_bytes;
+use crate::extraction::jpeg::extract_jpeg_metadata_from_bytes;
+use crate::extraction::png::{extract_png_metadata_from_bytes, parse_png_text_chunks};
+use crate::extraction::webp::{extract_webp_metadata_from_bytes, parse_webp_chunks};
+use crate::extraction::{ExtractedMetadata, SourceLocation};
+use log::warn;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+
+/// Priority of the built-in container parsers; parsers registered with a higher one run
+/// first and win conflicts, lower or equal ones only fill gaps
+pub const BUILTIN_PRIORITY: i32 = 0;
+
+/// A text entry of the container: a PNG `tEXt`/`zTXt`/`iTXt` chunk or a WebP text chunk
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TextField {
+    pub key: String,
+    pub value: String,
+    pub location: SourceLocation,
+}
+
+/// An image file as parsers see it: its bytes, its sniffed format and its decoded text
+/// fields. Text fields are decoded on first use and shared by all parsers.
+pub struct ContainerView<'a> {
+    pub data: &'a [u8],
+    pub format: Option<FileFormat>,
+    text: OnceCell<Vec<TextField>>,
+}
+
+impl<'a> ContainerView<'a> {
+    pub fn new(data: &'a [u8], format: Option<FileFormat>) -> Self {
+        ContainerView { data, format, text: OnceCell::new() }
+    }
+
+    pub fn text_fields(&self) -> &[TextField] {
+        self.text.get_or_init(|| match self.format {
+            Some(FileFormat::Png) => parse_png_text_chunks(self.data)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|chunk| TextField {
+                    location: SourceLocation::new(&format!("png:{}", chunk.chunk_type), &chunk.keyword, Some(chunk.offset), ""),
+                    key: chunk.keyword,
+                    value: chunk.text,
+                })
+                .collect(),
+            Some(FileFormat::WebP) => parse_webp_chunks(self.data)
+                .map(|chunks| chunks.text)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(key, value, offset)| TextField {
+                    location: SourceLocation::new("webp:chunk", &key, Some(offset), ""),
+                    key,
+                    value,
+                })
+                .collect(),
+            _ => Vec::new(),
+        })
+    }
+
+    /// The first text field with the given key
+    pub fn text_field(&self, key: &str) -> Option<&TextField> {
+        self.text_fields().iter().find(|field| field.key == key)
+    }
+}
+
+/// Reads one kind of metadata out of a container.
+///
+/// Register implementations on a `ParserRegistry` to support in-house formats; `parse`
+/// returns its own `ExtractedMetadata`, which the registry merges with the other parsers'.
+pub trait MetadataParser: Send + Sync {
+    /// Short name, recorded as the parser of the fields it provides
+    fn name(&self) -> &str;
+
+    /// Parsers run from the highest priority down; see `BUILTIN_PRIORITY`
+    fn priority(&self) -> i32 {
+        BUILTIN_PRIORITY
+    }
+
+    /// Whether the container holds metadata this parser understands
+    fn detect(&self, view: &ContainerView) -> bool;
+
+    fn parse(&self, view: &ContainerView) -> anyhow::Result<ExtractedMetadata>;
+}
+
+/// Parsers in priority order. The default registry holds the built-in container parsers.
+pub struct ParserRegistry {
+    parsers: Vec<Box<dyn MetadataParser>>,
+}
+
+impl ParserRegistry {
+    /// A registry without any parser
+    pub fn empty() -> Self {
+        ParserRegistry { parsers: Vec::new() }
+    }
+
+    /// Add a parser after those with a higher or equal priority
+    pub fn register<T: MetadataParser + 'static>(&mut self, parser: T) -> &mut Self {
+        let index = self
+            .parsers
+            .iter()
+            .position(|p| p.priority() < parser.priority())
+            .unwrap_or(self.parsers.len());
+        self.parsers.insert(index, Box::new(parser));
+        self
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.parsers.iter().map(|p| p.name()).collect()
+    }
+
+    /// Run every parser that detects its metadata and merge the results: values from
+    /// earlier parsers win, later ones fill the gaps. A failing parser is skipped.
+    pub fn parse(&self, view: &ContainerView) -> ExtractedMetadata {
+        let mut metadata = ExtractedMetadata::empty();
+        let container = view.format.map_or("file", |format| format.as_str());
+
+        for parser in self.parsers.iter().filter(|p| p.detect(view)) {
+            let parsed = match parser.parse(view) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    warn!("Metadata parser {} failed: {}", parser.name(), e);
+                    continue;
+                }
+            };
+            // Parsers that don't track sources are credited for what they contributed
+            metadata.merge_from(&SourceLocation::new(container, "", None, parser.name()), parsed);
+        }
+
+        metadata
+    }
+}
+
+impl Default for ParserRegistry {
+    fn default() -> Self {
+        let mut registry = ParserRegistry::empty();
+        registry
+            .register(ContainerParser { name: "png", formats: &[FileFormat::Png] })
+            .register(ContainerParser { name: "jpeg", formats: &[FileFormat::Jpeg] })
+            .register(ContainerParser { name: "webp", formats: &[FileFormat::WebP] })
+            .register(ContainerParser { name: "isobmff", formats: &[FileFormat::Avif, FileFormat::Heif] })
+            .register(ContainerParser { name: "tiff", formats: &[FileFormat::Tiff] });
+        registry
+    }
+}
+
+/// A built-in parser: everything the format module reads from its container
+struct ContainerParser {
+    name: &'static str,
+    formats: &'static [FileFormat],
+}
+
+impl MetadataParser for ContainerParser {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn detect(&self, view: &ContainerView) -> bool {
+        view.format.is_some_and(|format| self.formats.contains(&format))
+    }
+
+    fn parse(&self, view: &ContainerView) -> anyhow::Result<ExtractedMetadata> {
+        match view.format {
+            Some(FileFormat::Png) => extract_png_metadata_from_bytes(view.data),
+            Some(FileFormat::Jpeg) => extract_jpeg_metadata_from_bytes(view.data),
+            Some(FileFormat::WebP) => extract_webp_metadata_from_bytes(view.data),
+            Some(FileFormat::Avif) | Some(FileFormat::Heif) => Ok(extract_isobmff_metadata_from_bytes(view.data)),
+            Some(FileFormat::Tiff) => Ok(extract_tiff_metadata_from_bytes(view.data)),
+            Some(FileFormat::Gif) | None => Ok(ExtractedMetadata::empty()),
+        }
+    }
+}
+
+impl ExtractedMetadata {
+    /// Merge the result of a lower-priority parser: it only fills fields that are still
+    /// empty, and its lists are appended. Its sources stay selected only for those fields.
+    pub fn merge(&mut self, other: ExtractedMetadata) {
+        let mut filled = Vec::new();
+        let mut fill = |field: &mut Option<String>, value: Option<String>, name: &'static str| {
+            if field.is_none() && value.is_some() {
+                *field = value;
+                filled.push(name);
+            }
+        };
+        fill(&mut self.prompt, other.prompt, "prompt");
+        fill(&mut self.negative_prompt, other.negative_prompt, "negative_prompt");
+        fill(&mut self.parameters, other.parameters, "parameters");
+        fill(&mut self.model, other.model, "model");
+        fill(&mut self.seed, other.seed, "seed");
+        fill(&mut self.steps, other.steps, "steps");
+        fill(&mut self.cfg_scale, other.cfg_scale, "cfg_scale");
+        fill(&mut self.sampler, other.sampler, "sampler");
+        fill(&mut self.size, other.size, "size");
+        fill(&mut self.generator, other.generator, "generator");
+        fill(&mut self.generator_version, other.generator_version, "generator_version");
+
+        if self.stages.is_empty() {
+            self.stages = other.stages;
+        }
+        self.other.extend(other.other);
+        self.entries.extend(other.entries);
+        for keyword in other.keywords {
+            if !self.keywords.contains(&keyword) {
+                self.keywords.push(keyword);
+            }
+        }
+        for mut source in other.sources {
+            source.selected &= filled.contains(&source.field.as_str());
+            // A candidate repeating the value already chosen adds nothing
+            if source.selected || self.tracked_value(&source.field).as_ref() != Some(&source.value) {
+                self.sources.push(source);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An in-house pipeline that writes its own `studio` PNG chunk
+    struct StudioParser {
+        priority: i32,
+    }
+
+    impl MetadataParser for StudioParser {
+        fn name(&self) -> &str {
+            "studio"
+        }
+
+        fn priority(&self) -> i32 {
+            self.priority
+        }
+
+        fn detect(&self, view: &ContainerView) -> bool {
+            view.text_field("studio").is_some()
+        }
+
+        fn parse(&self, view: &ContainerView) -> anyhow::Result<ExtractedMetadata> {
+            let json: serde_json::Value = serde_json::from_str(&view.text_field("studio").unwrap().value)?;
+            let mut metadata = ExtractedMetadata::empty();
+            metadata.prompt = json["text"].as_str().map(String::from);
+            metadata.model = json["checkpoint"].as_str().map(String::from);
+            metadata.generator = Some("studio".to_string());
+            Ok(metadata)
+        }
+    }
+
+    fn png_with_text(chunks: &[(&str, &str)]) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (key, value) in chunks {
+            let body = [key.as_bytes(), b"\0", value.as_bytes()].concat();
+            data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            data.extend_from_slice(b"tEXt");
+            data.extend_from_slice(&body);
+            data.extend_from_slice(&[0, 0, 0, 0]); // CRC is not verified
+        }
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(b"IEND");
+        data
+    }
+
+    #[test]
+    fn test_registered_parser_merges_by_priority() {
+        let data = png_with_text(&[
+            ("parameters", "a lighthouse\nSteps: 20, Sampler: Euler a, Seed: 7"),
+            ("studio", r#"{"text": "a lighthouse at dusk", "checkpoint": "house-v3"}"#),
+        ]);
+        let view = ContainerView::new(&data, Some(FileFormat::Png));
+
+        // Higher priority: the in-house prompt wins, A1111 fills in steps and seed
+        let mut registry = ParserRegistry::default();
+        registry.register(StudioParser { priority: 10 });
+        assert_eq!(registry.names(), vec!["studio", "png", "jpeg", "webp", "isobmff", "tiff"]);
+        let metadata = registry.parse(&view);
+        assert_eq!(metadata.prompt.as_deref(), Some("a lighthouse at dusk"));
+        assert_eq!(metadata.model.as_deref(), Some("house-v3"));
+        assert_eq!(metadata.seed.as_deref(), Some("7"));
+        assert_eq!(metadata.source_of("prompt").unwrap().location.parser, "studio");
+        assert_eq!(metadata.source_of("seed").unwrap().location.parser, "a1111");
+        assert!(metadata.sources.iter().any(|s| s.field == "prompt" && s.value == "a lighthouse" && !s.selected));
+
+        // Default priority: the built-in parser wins, the in-house one fills the model
+        let mut registry = ParserRegistry::default();
+        registry.register(StudioParser { priority: BUILTIN_PRIORITY });
+        let metadata = registry.parse(&view);
+        assert_eq!(metadata.prompt.as_deref(), Some("a lighthouse"));
+        assert_eq!(metadata.model.as_deref(), Some("house-v3"));
+        assert_eq!(metadata.source_of("model").unwrap().location.container, "png");
+    }
+}
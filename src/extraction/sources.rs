@@ -0,0 +1,195 @@
+use crate::extraction::ExtractedMetadata;
+use serde::{Deserialize, Serialize};
+
+/// Fields whose origin is recorded
+const TRACKED_FIELDS: [&str; 10] = [
+    "prompt",
+    "negative_prompt",
+    "model",
+    "seed",
+    "steps",
+    "cfg_scale",
+    "sampler",
+    "size",
+    "generator",
+    "generator_version",
+];
+
+/// Where a value was read from: the container structure (`png:tEXt`, `jpeg:APP1`, ...), the
+/// key inside it, the structure's byte offset in the file and the parser that interpreted it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub container: String,
+    pub key: String,
+    pub offset: Option<u64>,
+    pub parser: String,
+}
+
+impl SourceLocation {
+    pub fn new(container: &str, key: &str, offset: Option<u64>, parser: &str) -> Self {
+        SourceLocation {
+            container: container.to_string(),
+            key: key.to_string(),
+            offset,
+            parser: parser.to_string(),
+        }
+    }
+
+    /// The same structure, under another key (an EXIF tag, an XMP property)
+    pub fn with_key(&self, key: &str) -> Self {
+        SourceLocation { key: key.to_string(), ..self.clone() }
+    }
+
+    pub fn with_parser(&self, parser: &str) -> Self {
+        SourceLocation { parser: parser.to_string(), ..self.clone() }
+    }
+}
+
+/// A value one source gave for a field; `selected` is false for candidates that lost to
+/// another source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldSource {
+    pub field: String,
+    /// The value as the parser produced it, before prompt normalization
+    pub value: String,
+    #[serde(flatten)]
+    pub location: SourceLocation,
+    pub selected: bool,
+}
+
+fn tracked_values(metadata: &ExtractedMetadata) -> [Option<String>; 10] {
+    [
+        metadata.prompt.clone(),
+        metadata.negative_prompt.clone(),
+        metadata.model.clone(),
+        metadata.seed.clone(),
+        metadata.steps.clone(),
+        metadata.cfg_scale.clone(),
+        metadata.sampler.clone(),
+        metadata.size.clone(),
+        metadata.generator.clone(),
+        metadata.generator_version.clone(),
+    ]
+}
+
+/// Byte offset of `part` within `data`, when it is a subslice of it
+pub fn offset_in(data: &[u8], part: &[u8]) -> Option<u64> {
+    let start = (part.as_ptr() as usize).checked_sub(data.as_ptr() as usize)?;
+    (start + part.len() <= data.len()).then_some(start as u64)
+}
+
+impl ExtractedMetadata {
+    /// Run a parser over one source and merge what it found. The parser runs once, on empty
+    /// metadata; its values only fill fields that are still empty, and those that lose to
+    /// earlier sources are kept as candidates.
+    pub fn record_source(&mut self, location: &SourceLocation, parse: impl FnOnce(&mut ExtractedMetadata)) {
+        let mut parsed = ExtractedMetadata::empty();
+        parse(&mut parsed);
+        self.merge_from(location, parsed);
+    }
+
+    /// Merge a parser's standalone result, crediting `location` for every tracked field it
+    /// gives that the parser did not record a source for itself
+    pub fn merge_from(&mut self, location: &SourceLocation, mut parsed: ExtractedMetadata) {
+        for (field, value) in TRACKED_FIELDS.iter().zip(tracked_values(&parsed)) {
+            let Some(value) = value else { continue };
+            if !parsed.sources.iter().any(|s| s.field == *field) {
+                parsed.sources.push(FieldSource {
+                    field: field.to_string(),
+                    value,
+                    location: location.clone(),
+                    selected: true,
+                });
+            }
+        }
+        self.merge(parsed);
+    }
+
+    /// Run a detector over the metadata collected so far and record `location` for every
+    /// tracked field it changed. Unlike `record_source`, the detector may replace values.
+    pub fn record_changes(&mut self, location: &SourceLocation, detect: impl FnOnce(&mut ExtractedMetadata)) {
+        let before = tracked_values(self);
+        detect(self);
+        let after = tracked_values(self);
+
+        for ((field, before), after) in TRACKED_FIELDS.iter().zip(before).zip(after) {
+            let Some(value) = after.filter(|value| before.as_ref() != Some(value)) else { continue };
+            // The new value wins over whatever was recorded for the field so far
+            self.sources.iter_mut().filter(|s| s.field == *field).for_each(|s| s.selected = false);
+            self.sources.push(FieldSource {
+                field: field.to_string(),
+                value,
+                location: location.clone(),
+                selected: true,
+            });
+        }
+    }
+
+    /// The current value of a tracked field
+    pub(crate) fn tracked_value(&self, field: &str) -> Option<String> {
+        let index = TRACKED_FIELDS.iter().position(|f| *f == field)?;
+        tracked_values(self)[index].clone()
+    }
+
+    /// The source of a field's current value
+    pub fn source_of(&self, field: &str) -> Option<&FieldSource> {
+        self.sources.iter().find(|s| s.field == field && s.selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_source_wins_and_losers_are_kept() {
+        let mut metadata = ExtractedMetadata::empty();
+        let chunk = SourceLocation::new("png:tEXt", "parameters", Some(33), "a1111");
+        metadata.record_source(&chunk, |m| {
+            m.prompt = Some("from parameters".to_string());
+            m.seed = Some("1".to_string());
+        });
+
+        // Fills gaps only: its prompt loses, its model is taken, a repeated seed adds nothing
+        let xmp = SourceLocation::new("png:iTXt", "dc:description", Some(120), "xmp");
+        metadata.record_source(&xmp, |m| {
+            m.prompt = Some("from xmp".to_string());
+            m.model = Some("sdxl".to_string());
+            m.seed = Some("1".to_string());
+        });
+
+        // A detector runs on the collected fields and overrides the seed
+        metadata.record_changes(&SourceLocation::new("fields", "", None, "midjourney"), |m| {
+            assert_eq!(m.model.as_deref(), Some("sdxl"));
+            m.seed = Some("2".to_string())
+        });
+
+        assert_eq!(metadata.source_of("prompt").unwrap().location, chunk);
+        assert_eq!(metadata.source_of("model").unwrap().location.parser, "xmp");
+        assert_eq!(metadata.source_of("seed").unwrap().value, "2");
+
+        let losers: Vec<_> = metadata.sources.iter().filter(|s| !s.selected).map(|s| (s.field.as_str(), s.value.as_str())).collect();
+        assert_eq!(losers, vec![("seed", "1"), ("prompt", "from xmp")]);
+    }
+
+    #[test]
+    fn test_parser_runs_once() {
+        let mut metadata = ExtractedMetadata::empty();
+        metadata.prompt = Some("already set".to_string());
+        let mut runs = 0;
+        metadata.record_source(&SourceLocation::new("png:IDAT", "", None, "stealth"), |m| {
+            runs += 1;
+            m.prompt = Some("hidden".to_string());
+        });
+        assert_eq!(runs, 1);
+        assert_eq!(metadata.prompt.as_deref(), Some("already set"));
+        assert!(!metadata.sources[0].selected);
+    }
+
+    #[test]
+    fn test_offset_in() {
+        let data = b"0123456789";
+        assert_eq!(offset_in(data, &data[4..7]), Some(4));
+        assert_eq!(offset_in(&data[4..], &data[..2]), None);
+    }
+}
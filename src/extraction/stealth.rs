@@ -0,0 +1,247 @@
+use crate::extraction::ExtractedMetadata;
+use crate::extraction::generator::value_to_string;
+use crate::extraction::novelai::{apply_novelai_to_metadata, is_novelai};
+use flate2::read::GzDecoder;
+use image::RgbaImage;
+use serde_json::Value;
+use std::io::Read;
+
+/// Signatures written before the payload by the A1111 stealth-pnginfo extension and NovelAI.
+/// "info" payloads are raw UTF-8, "comp" payloads are gzip-compressed.
+const SIGNATURE_ALPHA: &[u8] = b"stealth_pnginfo";
+const SIGNATURE_ALPHA_COMPRESSED: &[u8] = b"stealth_pngcomp";
+const SIGNATURE_RGB: &[u8] = b"stealth_rgbinfo";
+const SIGNATURE_RGB_COMPRESSED: &[u8] = b"stealth_rgbcomp";
+
+/// Upper bound for a decompressed payload, to guard against gzip bombs
+const MAX_PAYLOAD_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StealthMode {
+    Alpha,
+    Rgb,
+}
+
+impl StealthMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StealthMode::Alpha => "alpha",
+            StealthMode::Rgb => "rgb",
+        }
+    }
+}
+
+/// Metadata recovered from pixel least-significant bits
+#[derive(Debug, Clone, PartialEq)]
+pub struct StealthPayload {
+    pub mode: StealthMode,
+    pub compressed: bool,
+    pub text: String,
+}
+
+/// Decode stealth pnginfo from an encoded image (PNG, or lossless WebP)
+pub fn decode_stealth_pnginfo(data: &[u8]) -> Option<StealthPayload> {
+    let image = image::load_from_memory(data).ok()?;
+    let has_alpha = image.color().has_alpha();
+    let rgba = image.to_rgba8();
+
+    if has_alpha {
+        if let Some(payload) = read_payload(&rgba, StealthMode::Alpha) {
+            return Some(payload);
+        }
+    }
+    read_payload(&rgba, StealthMode::Rgb)
+}
+
+/// Pixels are visited column by column (x outer, y inner), matching the writer
+fn lsb_bits(image: &RgbaImage, mode: StealthMode) -> impl Iterator<Item = u8> + '_ {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    // Channels read from each RGBA pixel: alpha alone, or red, green and blue
+    let channels = match mode {
+        StealthMode::Alpha => 3..4,
+        StealthMode::Rgb => 0..3,
+    };
+    let pixels = image.as_raw();
+    (0..width).flat_map(move |x| {
+        let channels = channels.clone();
+        (0..height).flat_map(move |y| {
+            let pixel = (y * width + x) * 4;
+            pixels[pixel + channels.start..pixel + channels.end].iter().map(|channel| channel & 1)
+        })
+    })
+}
+
+/// Read `count` bits, most significant bit first
+fn read_bits(bits: &mut impl Iterator<Item = u8>, count: usize) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(count / 8);
+    for _ in 0..count / 8 {
+        let mut byte = 0u8;
+        for _ in 0..8 {
+            byte = (byte << 1) | bits.next()?;
+        }
+        bytes.push(byte);
+    }
+    Some(bytes)
+}
+
+fn read_payload(image: &RgbaImage, mode: StealthMode) -> Option<StealthPayload> {
+    let (plain, compressed) = match mode {
+        StealthMode::Alpha => (SIGNATURE_ALPHA, SIGNATURE_ALPHA_COMPRESSED),
+        StealthMode::Rgb => (SIGNATURE_RGB, SIGNATURE_RGB_COMPRESSED),
+    };
+
+    let mut bits = lsb_bits(image, mode);
+    let signature = read_bits(&mut bits, plain.len() * 8)?;
+    let is_compressed = if signature == plain {
+        false
+    } else if signature == compressed {
+        true
+    } else {
+        return None;
+    };
+
+    // 32-bit big-endian payload length, in bits
+    let length = read_bits(&mut bits, 32)?;
+    let length_bits = u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize;
+    let bits_per_pixel = if mode == StealthMode::Alpha { 1 } else { 3 };
+    let capacity_bits = image.width() as usize * image.height() as usize * bits_per_pixel;
+    if length_bits == 0 || length_bits > capacity_bits {
+        return None;
+    }
+
+    let payload = read_bits(&mut bits, length_bits)?;
+    let text = if is_compressed {
+        let mut out = Vec::new();
+        GzDecoder::new(payload.as_slice())
+            .take(MAX_PAYLOAD_SIZE)
+            .read_to_end(&mut out)
+            .ok()?;
+        String::from_utf8_lossy(&out).to_string()
+    } else {
+        String::from_utf8_lossy(&payload).to_string()
+    };
+
+    Some(StealthPayload { mode, compressed: is_compressed, text })
+}
+
+/// Recover stealth pnginfo and feed it through the NovelAI or A1111 parameters parser.
+/// Callers should only use this when no prompt or parameters were found elsewhere.
+pub fn apply_stealth_pnginfo(data: &[u8], metadata: &mut ExtractedMetadata) -> bool {
+    let Some(payload) = decode_stealth_pnginfo(data) else {
+        return false;
+    };
+
+    // NovelAI embeds a JSON object of its usual text chunks; A1111 embeds the parameters string
+    let fields: Option<Vec<(String, String)>> = serde_json::from_str::<Value>(&payload.text)
+        .ok()
+        .and_then(|json| json.as_object().cloned())
+        .map(|object| {
+            object
+                .into_iter()
+                .map(|(key, value)| (key, value_to_string(&value)))
+                .collect()
+        });
+
+    match fields {
+        Some(fields) if is_novelai(&fields) => apply_novelai_to_metadata(&fields, metadata),
+        Some(fields) => metadata.other.extend(fields),
+        None => {
+            metadata.parameters = Some(payload.text.clone());
+            crate::extraction::png::parse_parameters_string(&payload.text, metadata);
+        }
+    }
+
+    metadata.other.push(("stealth_pnginfo".to_string(), payload.mode.as_str().to_string()));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use image::{ImageOutputFormat, Rgba};
+    use std::io::{Cursor, Write};
+
+    /// Write signature + length + payload into the alpha LSBs, column by column
+    fn embed(signature: &[u8], payload: &[u8], width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = signature.to_vec();
+        bytes.extend_from_slice(&((payload.len() * 8) as u32).to_be_bytes());
+        bytes.extend_from_slice(payload);
+        let bits: Vec<u8> = bytes
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1))
+            .collect();
+
+        let mut image = RgbaImage::from_pixel(width, height, Rgba([120, 80, 200, 254]));
+        let mut index = 0;
+        for x in 0..width {
+            for y in 0..height {
+                if let Some(bit) = bits.get(index) {
+                    image.get_pixel_mut(x, y).0[3] = 254 | bit;
+                }
+                index += 1;
+            }
+        }
+
+        let mut encoded = Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgba8(image)
+            .write_to(&mut encoded, ImageOutputFormat::Png)
+            .unwrap();
+        encoded.into_inner()
+    }
+
+    #[test]
+    fn test_decode_alpha_stealth_parameters() {
+        let params = "a paper boat on a pond\nNegative prompt: blurry\nSteps: 20, Sampler: Euler a, CFG scale: 7, Seed: 5, Size: 64x64";
+        let png = embed(SIGNATURE_ALPHA, params.as_bytes(), 64, 64);
+
+        let mut metadata = ExtractedMetadata::empty();
+        assert!(apply_stealth_pnginfo(&png, &mut metadata));
+        assert_eq!(metadata.prompt, Some("a paper boat on a pond".to_string()));
+        assert_eq!(metadata.negative_prompt, Some("blurry".to_string()));
+        assert_eq!(metadata.seed, Some("5".to_string()));
+    }
+
+    #[test]
+    fn test_decode_compressed_novelai_stealth() {
+        let json = r#"{"Description": "1girl, rain", "Software": "NovelAI", "Source": "NovelAI Diffusion V4 F6302A9D", "Comment": "{\"prompt\": \"1girl, rain\", \"uc\": \"lowres\", \"steps\": 28, \"scale\": 5.0, \"seed\": 9, \"sampler\": \"k_euler\"}"}"#;
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(json.as_bytes()).unwrap();
+        let png = embed(SIGNATURE_ALPHA_COMPRESSED, &encoder.finish().unwrap(), 96, 96);
+
+        let payload = decode_stealth_pnginfo(&png).unwrap();
+        assert!(payload.compressed);
+        assert_eq!(payload.mode, StealthMode::Alpha);
+
+        let mut metadata = ExtractedMetadata::empty();
+        assert!(apply_stealth_pnginfo(&png, &mut metadata));
+        assert_eq!(metadata.generator, Some("novelai".to_string()));
+        assert_eq!(metadata.prompt, Some("1girl, rain".to_string()));
+        assert_eq!(metadata.negative_prompt, Some("lowres".to_string()));
+    }
+
+    #[test]
+    fn test_stealth_is_read_next_to_unrelated_text_chunks() {
+        let params = "a paper boat on a pond\nSteps: 20, Sampler: Euler a, Seed: 5";
+        let mut png = embed(SIGNATURE_ALPHA, params.as_bytes(), 64, 64);
+
+        // NovelAI and most editors write a Software chunk; insert one after IHDR
+        let body = b"tEXtSoftware\0NovelAI";
+        let mut crc = flate2::Crc::new();
+        crc.update(body);
+        let mut chunk = ((body.len() - 4) as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(body);
+        chunk.extend_from_slice(&crc.sum().to_be_bytes());
+        png.splice(33..33, chunk);
+
+        let metadata = crate::extraction::png::extract_png_metadata_from_bytes(&png).unwrap();
+        assert_eq!(metadata.prompt, Some("a paper boat on a pond".to_string()));
+        assert_eq!(metadata.source_of("seed").unwrap().location.parser, "stealth");
+    }
+
+    #[test]
+    fn test_plain_image_has_no_stealth_data() {
+        let png = embed(b"not_a_signature", b"", 32, 32);
+        assert!(decode_stealth_pnginfo(&png).is_none());
+    }
+}
@@ -0,0 +1,153 @@
+use crate::extraction::{ExtractedMetadata, GenerationStage};
+use crate::extraction::generator::value_to_string;
+use serde_json::Value;
+
+/// Keys mapped onto typed fields or stages; everything else is kept in `other`
+const MAPPED_KEYS: &[&str] = &[
+    "prompt",
+    "negativeprompt",
+    "model",
+    "seed",
+    "steps",
+    "cfgscale",
+    "width",
+    "height",
+    "sampler",
+    "scheduler",
+    "loras",
+    "loraweights",
+];
+
+/// Whether a JSON value is SwarmUI metadata: `{ "sui_image_params": { ... } }`
+pub fn is_swarmui_parameters(params: &Value) -> bool {
+    params.get("sui_image_params").is_some_and(|p| p.is_object())
+}
+
+/// Map SwarmUI metadata into metadata. Accepts either the full document
+/// (with `sui_image_params`, `sui_extra_data`, `sui_models`) or the inner params object.
+pub fn apply_swarmui_to_metadata(params: &Value, metadata: &mut ExtractedMetadata) {
+    let image_params = params.get("sui_image_params").unwrap_or(params);
+    let Some(object) = image_params.as_object() else {
+        return;
+    };
+    let str_field = |key: &str| {
+        image_params
+            .get(key)
+            .filter(|v| !v.is_null())
+            .map(value_to_string)
+            .filter(|s| !s.trim().is_empty())
+    };
+
+    metadata.generator = Some("swarmui".to_string());
+    metadata.prompt = str_field("prompt");
+    metadata.negative_prompt = str_field("negativeprompt");
+    metadata.model = str_field("model");
+    metadata.seed = str_field("seed");
+    metadata.steps = str_field("steps");
+    metadata.cfg_scale = str_field("cfgscale");
+    metadata.sampler = str_field("sampler");
+    if let (Some(width), Some(height)) = (str_field("width"), str_field("height")) {
+        metadata.size = Some(format!("{}x{}", width, height));
+    }
+
+    // LoRA names and weights are parallel arrays
+    let loras = image_params.get("loras").and_then(|l| l.as_array());
+    let weights = image_params.get("loraweights").and_then(|w| w.as_array());
+    if let Some(loras) = loras {
+        let loras: Vec<String> = loras
+            .iter()
+            .enumerate()
+            .filter_map(|(index, lora)| {
+                let name = lora.as_str()?;
+                Some(match weights.and_then(|w| w.get(index)) {
+                    Some(weight) => format!("{}:{}", name, value_to_string(weight)),
+                    None => name.to_string(),
+                })
+            })
+            .collect();
+        if !loras.is_empty() {
+            metadata.other.push(("lora".to_string(), loras.join(", ")));
+        }
+    }
+
+    for (key, value) in object {
+        if !MAPPED_KEYS.contains(&key.as_str()) && !value.is_null() {
+            metadata.other.push((key.clone(), value_to_string(value)));
+        }
+    }
+    for key in ["sui_extra_data", "sui_models"] {
+        if let Some(value) = params.get(key) {
+            metadata.other.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    let mut base = GenerationStage::new("base");
+    base.model = metadata.model.clone();
+    base.sampler = metadata.sampler.clone();
+    base.scheduler = str_field("scheduler");
+    base.steps = metadata.steps.clone();
+    base.cfg_scale = metadata.cfg_scale.clone();
+    base.seed = metadata.seed.clone();
+    base.size = metadata.size.clone();
+    metadata.stages = vec![base.clone()];
+
+    // The refiner pass runs when a control percentage is set; with an upscale it acts as a hires fix
+    let control = str_field("refinercontrolpercentage").or_else(|| str_field("refinercontrol"));
+    if control.as_deref().and_then(|c| c.parse::<f64>().ok()).is_some_and(|c| c > 0.0) {
+        let upscale = str_field("refinerupscale").and_then(|u| u.parse::<f64>().ok()).filter(|u| *u > 1.0);
+        let mut stage = GenerationStage::new(if upscale.is_some() { "hires_fix" } else { "refiner" });
+        stage.model = str_field("refinermodel").or_else(|| base.model.clone());
+        stage.sampler = str_field("refinersampler").or_else(|| base.sampler.clone());
+        stage.scheduler = str_field("refinerscheduler").or_else(|| base.scheduler.clone());
+        stage.steps = str_field("refinersteps").or_else(|| base.steps.clone());
+        stage.cfg_scale = str_field("refinercfgscale").or_else(|| base.cfg_scale.clone());
+        stage.seed = base.seed.clone();
+        stage.denoise = control;
+        stage.upscaler = str_field("refinerupscalemethod");
+        stage.size = match (upscale, image_params.get("width").and_then(|v| v.as_f64()), image_params.get("height").and_then(|v| v.as_f64())) {
+            (Some(upscale), Some(width), Some(height)) => Some(format!("{}x{}", (width * upscale) as u64, (height * upscale) as u64)),
+            _ => base.size.clone(),
+        };
+        metadata.stages.push(stage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swarmui_image_params() {
+        let params: Value = serde_json::from_str(r#"{
+            "sui_image_params": {
+                "prompt": "a koi pond, ink painting", "negativeprompt": "text", "model": "OfficialStableDiffusion/sd_xl_base_1.0",
+                "seed": 1029384756, "steps": 25, "cfgscale": 6.0, "aspectratio": "1:1", "width": 1024, "height": 1024,
+                "sampler": "dpmpp_2m", "scheduler": "karras", "loras": ["ink_style", "detail"], "loraweights": ["0.8", "0.4"],
+                "refinermodel": "OfficialStableDiffusion/sd_xl_refiner_1.0", "refinercontrolpercentage": 0.2, "refinerupscale": 1.5,
+                "refinerupscalemethod": "latent-bicubic", "swarm_version": "0.9.2.1"
+            },
+            "sui_extra_data": {"date": "2024-08-01", "generation_time": "3.1 seconds"}
+        }"#).unwrap();
+        assert!(is_swarmui_parameters(&params));
+
+        let mut metadata = ExtractedMetadata::empty();
+        apply_swarmui_to_metadata(&params, &mut metadata);
+
+        assert_eq!(metadata.generator, Some("swarmui".to_string()));
+        assert_eq!(metadata.prompt, Some("a koi pond, ink painting".to_string()));
+        assert_eq!(metadata.negative_prompt, Some("text".to_string()));
+        assert_eq!(metadata.model, Some("OfficialStableDiffusion/sd_xl_base_1.0".to_string()));
+        assert_eq!(metadata.cfg_scale, Some("6.0".to_string()));
+        assert_eq!(metadata.size, Some("1024x1024".to_string()));
+        assert!(metadata.other.contains(&("lora".to_string(), "ink_style:0.8, detail:0.4".to_string())));
+        assert!(metadata.other.contains(&("swarm_version".to_string(), "0.9.2.1".to_string())));
+        assert!(metadata.other.iter().any(|(k, _)| k == "sui_extra_data"));
+
+        assert_eq!(metadata.stages.len(), 2);
+        let hires = &metadata.stages[1];
+        assert_eq!(hires.kind, "hires_fix");
+        assert_eq!(hires.model, Some("OfficialStableDiffusion/sd_xl_refiner_1.0".to_string()));
+        assert_eq!(hires.denoise, Some("0.2".to_string()));
+        assert_eq!(hires.size, Some("1536x1536".to_string()));
+    }
+}
@@ -0,0 +1,272 @@
+use crate::extraction::{ExtractedMetadata, MetadataEntry};
+use image::RgbImage;
+use std::path::Path;
+
+/// Side of the DCT blocks the watermark bits are spread over
+const BLOCK: usize = 4;
+/// Quantization step used on the U channel by `imwatermark`'s `dwtDct` method
+const SCALE: f64 = 36.0;
+/// The encoder refuses smaller images, so nothing can be decoded from them
+const MIN_SIZE: u32 = 256;
+/// Share of bits that must match for a watermark to count as decoded
+const MIN_BIT_ACCURACY: f64 = 0.9;
+
+/// `0b101100111110110010010000011110111011000110011110`, embedded by the diffusers SDXL pipeline
+const SDXL_MESSAGE: u64 = 0xb3ec907bb19e;
+const SDXL_BITS: usize = 48;
+/// Embedded as UTF-8 bytes by the reference Stable Diffusion scripts
+const SD_V1_MESSAGE: &[u8] = b"StableDiffusionV1";
+
+/// A known invisible watermark that was decoded from an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatermarkMatch {
+    pub name: &'static str,
+    /// Share of the decoded bits that agree with the known pattern
+    pub bit_accuracy: f64,
+}
+
+/// Known watermarks as `(name, bits)`, bits most significant first
+fn known_watermarks() -> Vec<(&'static str, Vec<bool>)> {
+    let sdxl = (0..SDXL_BITS).rev().map(|i| SDXL_MESSAGE >> i & 1 == 1).collect();
+    let sd_v1 = SD_V1_MESSAGE
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1))
+        .collect();
+    vec![("SDXL", sdxl), ("StableDiffusionV1", sd_v1)]
+}
+
+/// Look for a known DWT-DCT watermark (as embedded by `imwatermark`) in decoded pixels.
+///
+/// Both channel orders are tried: the reference scripts hand the encoder BGR pixels, while
+/// the diffusers pipelines pass RGB where BGR is expected.
+pub fn detect_watermark(image: &RgbImage) -> Option<WatermarkMatch> {
+    if image.width() < MIN_SIZE || image.height() < MIN_SIZE {
+        return None;
+    }
+    let watermarks = known_watermarks();
+
+    [false, true]
+        .into_iter()
+        .flat_map(|swapped| {
+            let scores = block_scores(image, swapped);
+            watermarks
+                .iter()
+                .map(|(name, bits)| WatermarkMatch { name, bit_accuracy: bit_accuracy(&scores, bits) })
+                .collect::<Vec<_>>()
+        })
+        .filter(|m| m.bit_accuracy >= MIN_BIT_ACCURACY)
+        .max_by(|a, b| a.bit_accuracy.total_cmp(&b.bit_accuracy))
+}
+
+/// Decode an image file and look for a known watermark
+pub fn detect_watermark_in_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Option<WatermarkMatch>> {
+    let image = image::io::Reader::open(path)?.with_guessed_format()?.decode()?;
+    Ok(detect_watermark(&image.to_rgb8()))
+}
+
+/// Record the detection result as provenance: the watermark name (or `none`) and its bit accuracy
+pub fn apply_watermark_to_metadata(found: Option<&WatermarkMatch>, metadata: &mut ExtractedMetadata) {
+    let name = found.map_or("none", |m| m.name);
+    metadata.entries.push(MetadataEntry::new("watermark", name, "provenance"));
+    if let Some(found) = found {
+        metadata.entries.push(MetadataEntry::new(
+            "watermark.bit_accuracy",
+            &format!("{:.3}", found.bit_accuracy),
+            "provenance",
+        ));
+    }
+}
+
+/// Share of bits the per-block scores agree with, where block `n` carries bit `n % bits.len()`
+fn bit_accuracy(scores: &[bool], bits: &[bool]) -> f64 {
+    let mut ones = vec![0usize; bits.len()];
+    let mut counts = vec![0usize; bits.len()];
+    for (n, score) in scores.iter().enumerate() {
+        counts[n % bits.len()] += 1;
+        ones[n % bits.len()] += *score as usize;
+    }
+    // A bit is set when more than half of its blocks vote for it (`mean * 255 > 127`)
+    let matching = bits
+        .iter()
+        .enumerate()
+        .filter(|(i, bit)| counts[*i] > 0 && (ones[*i] as f64 / counts[*i] as f64 * 255.0 > 127.0) == **bit)
+        .count();
+    matching as f64 / bits.len() as f64
+}
+
+/// Chroma (U) plane as OpenCV's `BGR2YUV` computes it, cropped to a multiple of 4
+fn u_channel(image: &RgbImage, swapped: bool) -> (Vec<f64>, usize, usize) {
+    let width = image.width() as usize / 4 * 4;
+    let height = image.height() as usize / 4 * 4;
+    let mut plane = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let [r, g, b] = image.get_pixel(x as u32, y as u32).0;
+            let (r, b) = if swapped { (b, r) } else { (r, b) };
+            let (r, g, b) = (r as f64, g as f64, b as f64);
+            let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+            plane.push(((b - luma) * 0.492 + 128.0).round().clamp(0.0, 255.0));
+        }
+    }
+    (plane, width, height)
+}
+
+/// Per-block bit votes: Haar approximation band, 4x4 DCT blocks in row-major order, and the
+/// quantization of each block's largest AC coefficient
+fn block_scores(image: &RgbImage, swapped: bool) -> Vec<bool> {
+    let (plane, width, height) = u_channel(image, swapped);
+    let (band_width, band_height) = (width / 2, height / 2);
+    let band: Vec<f64> = (0..band_height)
+        .flat_map(|y| (0..band_width).map(move |x| (y, x)))
+        .map(|(y, x)| {
+            let at = |dy: usize, dx: usize| plane[(2 * y + dy) * width + 2 * x + dx];
+            (at(0, 0) + at(0, 1) + at(1, 0) + at(1, 1)) / 2.0
+        })
+        .collect();
+
+    let mut scores = Vec::new();
+    for by in 0..band_height / BLOCK {
+        for bx in 0..band_width / BLOCK {
+            let mut block = [[0.0; BLOCK]; BLOCK];
+            for (i, row) in block.iter_mut().enumerate() {
+                for (j, value) in row.iter_mut().enumerate() {
+                    *value = band[(by * BLOCK + i) * band_width + bx * BLOCK + j];
+                }
+            }
+            let coefficients = dct(&block);
+            let flat: Vec<f64> = coefficients.iter().flatten().copied().collect();
+            let position = largest_ac(&flat);
+            scores.push(flat[position].abs() % SCALE > SCALE / 2.0);
+        }
+    }
+    scores
+}
+
+/// Index of the largest AC coefficient by magnitude (the first one on ties, like `np.argmax`)
+fn largest_ac(flat: &[f64]) -> usize {
+    (1..flat.len()).fold(1, |best, i| if flat[i].abs() > flat[best].abs() { i } else { best })
+}
+
+/// Orthonormal DCT-II basis, as `cv2.dct` uses
+fn dct_basis() -> [[f64; BLOCK]; BLOCK] {
+    let mut basis = [[0.0; BLOCK]; BLOCK];
+    for (k, row) in basis.iter_mut().enumerate() {
+        let alpha = if k == 0 { (1.0 / BLOCK as f64).sqrt() } else { (2.0 / BLOCK as f64).sqrt() };
+        for (n, value) in row.iter_mut().enumerate() {
+            *value = alpha * (std::f64::consts::PI * (2 * n + 1) as f64 * k as f64 / (2 * BLOCK) as f64).cos();
+        }
+    }
+    basis
+}
+
+/// 2-D DCT of a block: `C * X * C^T`
+fn dct(block: &[[f64; BLOCK]; BLOCK]) -> [[f64; BLOCK]; BLOCK] {
+    let basis = dct_basis();
+    let mut out = [[0.0; BLOCK]; BLOCK];
+    for (u, row) in out.iter_mut().enumerate() {
+        for (v, value) in row.iter_mut().enumerate() {
+            *value = (0..BLOCK)
+                .flat_map(|i| (0..BLOCK).map(move |j| (i, j)))
+                .map(|(i, j)| basis[u][i] * block[i][j] * basis[v][j])
+                .sum();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    fn textured_image(size: u32) -> RgbImage {
+        RgbImage::from_fn(size, size, |x, y| {
+            let wave = |a: u32, b: u32| (((x * a + y * b) % 97) as f64 / 97.0 * 80.0) as u8;
+            Rgb([90 + wave(3, 7), 100 + wave(5, 2), 96 + wave(1, 9)])
+        })
+    }
+
+    /// Embed `bits` the way `imwatermark`'s `dwtDct` encoder does, writing the adjusted U plane
+    /// back through the blue channel
+    fn embed(image: &mut RgbImage, bits: &[bool]) {
+        let (mut plane, width, height) = u_channel(image, false);
+        let basis = dct_basis();
+        let band_width = width / 2;
+        let mut n = 0;
+        for by in 0..height / 2 / BLOCK {
+            for bx in 0..band_width / BLOCK {
+                let pixel = |i: usize, j: usize| (2 * (by * BLOCK + i), 2 * (bx * BLOCK + j));
+                let mut block = [[0.0; BLOCK]; BLOCK];
+                for (i, row) in block.iter_mut().enumerate() {
+                    for (j, value) in row.iter_mut().enumerate() {
+                        let (y, x) = pixel(i, j);
+                        *value = (plane[y * width + x] + plane[y * width + x + 1]
+                            + plane[(y + 1) * width + x] + plane[(y + 1) * width + x + 1])
+                            / 2.0;
+                    }
+                }
+                let flat: Vec<f64> = dct(&block).iter().flatten().copied().collect();
+                let position = largest_ac(&flat);
+                let value = flat[position];
+                let bit = if bits[n % bits.len()] { 0.5 } else { 0.0 };
+                let target = ((value.abs() / SCALE).floor() + 0.25 + bit) * SCALE * value.signum();
+                let delta = target - value;
+
+                // Inverse DCT of the single changed coefficient, then inverse Haar of the band
+                let (u, v) = (position / BLOCK, position % BLOCK);
+                for i in 0..BLOCK {
+                    for j in 0..BLOCK {
+                        let change = delta * basis[u][i] * basis[v][j] / 2.0;
+                        let (y, x) = pixel(i, j);
+                        for (dy, dx) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+                            plane[(y + dy) * width + x + dx] += change;
+                        }
+                    }
+                }
+                n += 1;
+            }
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                let Rgb([r, g, _]) = *image.get_pixel(x as u32, y as u32);
+                let (r, g) = (r as f64, g as f64);
+                let b = ((plane[y * width + x] - 128.0) / 0.492 + 0.299 * r + 0.587 * g) / 0.886;
+                image.get_pixel_mut(x as u32, y as u32).0[2] = b.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+
+    #[test]
+    fn test_detect_embedded_watermarks() {
+        for (name, bits) in known_watermarks() {
+            let mut image = textured_image(256);
+            embed(&mut image, &bits);
+            let found = detect_watermark(&image).unwrap();
+            assert_eq!(found.name, name);
+            assert!(found.bit_accuracy >= MIN_BIT_ACCURACY);
+
+            // RGB handed to the encoder as BGR, as the diffusers pipelines do
+            let mut swapped = textured_image(256);
+            swapped.pixels_mut().for_each(|p| p.0.swap(0, 2));
+            embed(&mut swapped, &bits);
+            swapped.pixels_mut().for_each(|p| p.0.swap(0, 2));
+            assert_eq!(detect_watermark(&swapped).map(|m| m.name), Some(name));
+        }
+    }
+
+    #[test]
+    fn test_unmarked_and_small_images() {
+        assert_eq!(detect_watermark(&textured_image(256)), None);
+        assert_eq!(detect_watermark(&RgbImage::from_pixel(512, 512, Rgb([40, 120, 200]))), None);
+
+        let mut small = textured_image(128);
+        embed(&mut small, &known_watermarks()[0].1);
+        assert_eq!(detect_watermark(&small), None);
+
+        let mut metadata = ExtractedMetadata::empty();
+        apply_watermark_to_metadata(None, &mut metadata);
+        assert_eq!(metadata.entries.len(), 1);
+        assert_eq!(metadata.entries[0].value, "none");
+    }
+}
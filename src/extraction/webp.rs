@@ -1,47 +1,48 @@
-use crate::extraction::ExtractedMetadata;
+use crate::extraction::{ExtractedMetadata, SourceLocation};
+use crate::extraction::png::{set_text_field, TEXT_FIELD_KEYS};
+use crate::extraction::exif::{apply_exif_to_metadata, parse_exif_block};
+use crate::extraction::c2pa::{apply_c2pa_to_metadata, parse_manifest_store};
+use crate::extraction::stealth::apply_stealth_pnginfo;
+use crate::extraction::xmp::{apply_xmp_to_metadata, XmpPacket};
 use std::path::Path;
 
 pub fn extract_webp_metadata<P: AsRef<Path>>(path: P) -> anyhow::Result<ExtractedMetadata> {
-    let path = path.as_ref();
-    
-    // Read the WebP file and parse chunks manually
-    // WebP format is similar to PNG with chunks
-    let file_data = std::fs::read(path)?;
-    let text_chunks = parse_webp_chunks(&file_data)?;
+    let file_data = std::fs::read(path.as_ref())?;
+    extract_webp_metadata_from_bytes(&file_data)
+}
+
+pub fn extract_webp_metadata_from_bytes(file_data: &[u8]) -> anyhow::Result<ExtractedMetadata> {
+    // WebP format is similar to PNG with chunks, which we parse manually
+    let WebpChunks { exif, c2pa, text: text_chunks } = parse_webp_chunks(file_data)?;
 
     let mut metadata = ExtractedMetadata::empty();
 
+    // Same EXIF handling as JPEG: A1111 writes its parameters into UserComment
+    if let Some((offset, exif)) = &exif {
+        if let Some(exif) = parse_exif_block(exif) {
+            apply_exif_to_metadata(&exif, &SourceLocation::new("webp:EXIF", "", Some(*offset), "exif"), &mut metadata);
+        }
+    }
+
     // Parse parameters field (similar to PNG)
-    for (key, value) in &text_chunks {
+    for (key, value, offset) in &text_chunks {
+        let location = |parser: &str| SourceLocation::new("webp:chunk", key, Some(*offset), parser);
         match key.as_str() {
             "parameters" => {
                 metadata.parameters = Some(value.clone());
                 // Try to parse the parameters string
-                crate::extraction::png::parse_parameters_string(value, &mut metadata);
-            }
-            "prompt" => {
-                metadata.prompt = Some(value.clone());
-            }
-            "negative_prompt" => {
-                metadata.negative_prompt = Some(value.clone());
-            }
-            "model" => {
-                metadata.model = Some(value.clone());
-            }
-            "seed" => {
-                metadata.seed = Some(value.clone());
+                metadata.record_source(&location("a1111"), |m| {
+                    crate::extraction::png::parse_parameters_string(value, m)
+                });
             }
-            "steps" => {
-                metadata.steps = Some(value.clone());
-            }
-            "cfg_scale" | "CFG scale" => {
-                metadata.cfg_scale = Some(value.clone());
-            }
-            "sampler" => {
-                metadata.sampler = Some(value.clone());
+            "XMP" => {
+                if let Ok(packet) = XmpPacket::parse(value) {
+                    let location = SourceLocation::new("webp:XMP", "", Some(*offset), "xmp");
+                    apply_xmp_to_metadata(&packet, &location, &mut metadata);
+                }
             }
-            "size" => {
-                metadata.size = Some(value.clone());
+            key if TEXT_FIELD_KEYS.contains(&key) => {
+                metadata.record_source(&location("text"), |m| set_text_field(key, value, m));
             }
             _ => {
                 metadata.other.push((key.clone(), value.clone()));
@@ -49,11 +50,31 @@ pub fn extract_webp_metadata<P: AsRef<Path>>(path: P) -> anyhow::Result<Extracte
         }
     }
 
+    if let Some(jumbf) = c2pa {
+        apply_c2pa_to_metadata(&parse_manifest_store(&jumbf), &mut metadata);
+    }
+
+    // Lossless WebP keeps exact alpha values, so stealth pnginfo can survive conversion
+    if metadata.prompt.is_none() && metadata.parameters.is_none() {
+        metadata.record_source(&SourceLocation::new("webp:pixels", "stealth_pnginfo", None, "stealth"), |m| {
+            apply_stealth_pnginfo(file_data, m);
+        });
+    }
+
     Ok(metadata)
 }
 
-fn parse_webp_chunks(data: &[u8]) -> anyhow::Result<Vec<(String, String)>> {
-    let mut chunks = Vec::new();
+/// Metadata chunks of a WebP file: the raw EXIF block, the C2PA manifest store and text
+/// chunks, with the byte offsets of the EXIF and text chunks
+#[derive(Default)]
+pub(crate) struct WebpChunks {
+    pub exif: Option<(u64, Vec<u8>)>,
+    pub c2pa: Option<Vec<u8>>,
+    pub text: Vec<(String, String, u64)>,
+}
+
+pub(crate) fn parse_webp_chunks(data: &[u8]) -> anyhow::Result<WebpChunks> {
+    let mut chunks = WebpChunks::default();
 
     // WebP file format:
     // - RIFF header (12 bytes): "RIFF" + size + "WEBP"
@@ -102,24 +123,20 @@ fn parse_webp_chunks(data: &[u8]) -> anyhow::Result<Vec<(String, String)>> {
         }
 
         // Handle different chunk types
+        let chunk_start = (offset - 8) as u64;
         match chunk_type.as_str() {
             "EXIF" => {
-                // EXIF data - try to parse
-                // For now, we'll store it as raw data
-                // TODO: Parse EXIF properly
-                let exif_data = &data[offset..offset + length];
-                if let Ok(text) = String::from_utf8(exif_data.to_vec()) {
-                    chunks.push(("EXIF".to_string(), text));
-                }
+                // Binary TIFF data, possibly behind an `Exif\0\0` prefix
+                chunks.exif = Some((chunk_start, data[offset..offset + length].to_vec()));
+            }
+            "C2PA" => {
+                // JUMBF manifest store (Content Credentials)
+                chunks.c2pa = Some(data[offset..offset + length].to_vec());
             }
             "XMP " => {
-                // XMP data - XML format
+                // XMP data - XML format, parsed by the shared XMP reader
                 let xmp_data = &data[offset..offset + length];
-                if let Ok(text) = String::from_utf8(xmp_data.to_vec()) {
-                    // Try to extract prompts from XMP
-                    parse_xmp_for_prompts(&text, &mut chunks);
-                    chunks.push(("XMP".to_string(), text));
-                }
+                chunks.text.push(("XMP".to_string(), String::from_utf8_lossy(xmp_data).to_string(), chunk_start));
             }
             _ => {
                 // Other chunks - skip for now
@@ -128,7 +145,7 @@ fn parse_webp_chunks(data: &[u8]) -> anyhow::Result<Vec<(String, String)>> {
 
         offset += length;
         // Chunk size must be even
-        if length % 2 != 0 {
+        if !length.is_multiple_of(2) {
             offset += 1;
         }
     }
@@ -136,49 +153,52 @@ fn parse_webp_chunks(data: &[u8]) -> anyhow::Result<Vec<(String, String)>> {
     Ok(chunks)
 }
 
-fn parse_xmp_for_prompts(xmp_data: &str, chunks: &mut Vec<(String, String)>) {
-    // Simple XMP parsing - look for description fields
-    // XMP is XML, so we'll do basic string matching
-    
-    // Look for dc:description
-    if let Some(start) = xmp_data.find("<dc:description>") {
-        if let Some(end) = xmp_data[start..].find("</dc:description>") {
-            let desc = &xmp_data[start + 16..start + end];
-            if !desc.is_empty() {
-                chunks.push(("description".to_string(), desc.to_string()));
-            }
-        }
-    }
-
-    // Look for rdf:Description with description attribute
-    if let Some(start) = xmp_data.find("rdf:Description") {
-        // Try to find description attribute
-        if let Some(desc_start) = xmp_data[start..].find("dc:description=\"") {
-            let attr_start = start + desc_start + 16;
-            if let Some(desc_end) = xmp_data[attr_start..].find("\"") {
-                let desc = &xmp_data[attr_start..attr_start + desc_end];
-                if !desc.is_empty() {
-                    chunks.push(("description".to_string(), desc.to_string()));
-                }
-            }
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::extraction::exif::tests::tiff_with_user_comment;
 
     #[test]
-    fn test_parse_xmp_for_prompts() {
-        let xmp = r#"<rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/">
+    fn test_webp_xmp_description_is_prompt() {
+        let xmp = r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+            <rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/">
             <dc:description>beautiful landscape, mountains</dc:description>
-        </rdf:Description>"#;
-        
-        let mut chunks = Vec::new();
-        parse_xmp_for_prompts(xmp, &mut chunks);
-        
-        assert!(!chunks.is_empty());
+        </rdf:Description></rdf:RDF>"#;
+
+        let mut webp = b"RIFF\0\0\0\0WEBPXMP ".to_vec();
+        webp.extend_from_slice(&(xmp.len() as u32).to_le_bytes());
+        webp.extend_from_slice(xmp.as_bytes());
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("xmp.webp");
+        std::fs::write(&path, webp).unwrap();
+
+        let metadata = extract_webp_metadata(&path).unwrap();
+        assert_eq!(metadata.prompt, Some("beautiful landscape, mountains".to_string()));
+    }
+
+    #[test]
+    fn test_webp_exif_user_comment() {
+        let params = "a koi pond at dusk\nNegative prompt: lowres\nSteps: 30, Sampler: Euler a, CFG scale: 6, Seed: 7, Size: 768x768, Version: v1.10.1";
+        let mut comment = b"UNICODE\0".to_vec();
+        comment.extend(params.encode_utf16().flat_map(|u| u.to_be_bytes()));
+
+        // Once with the JPEG-style prefix and once as bare TIFF data
+        for prefix in [&b"Exif\0\0"[..], &b""[..]] {
+            let mut exif = prefix.to_vec();
+            exif.extend(tiff_with_user_comment(&comment));
+            let mut webp = b"RIFF\0\0\0\0WEBPEXIF".to_vec();
+            webp.extend_from_slice(&(exif.len() as u32).to_le_bytes());
+            webp.extend(exif);
+            let dir = tempfile::TempDir::new().unwrap();
+            let path = dir.path().join("exif.webp");
+            std::fs::write(&path, webp).unwrap();
+
+            let metadata = extract_webp_metadata(&path).unwrap();
+            assert_eq!(metadata.prompt, Some("a koi pond at dusk".to_string()));
+            assert_eq!(metadata.negative_prompt, Some("lowres".to_string()));
+            assert_eq!(metadata.steps, Some("30".to_string()));
+            assert_eq!(metadata.parameters, Some(params.to_string()));
+        }
     }
 }
 
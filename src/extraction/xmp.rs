@@ -0,0 +1,339 @@
+use crate::extraction::{ExtractedMetadata, MetadataEntry, SourceLocation};
+use roxmltree::{Document, Node};
+
+pub const NS_RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+pub const NS_DC: &str = "http://purl.org/dc/elements/1.1/";
+pub const NS_XMP: &str = "http://ns.adobe.com/xap/1.0/";
+pub const NS_XMP_NOTE: &str = "http://ns.adobe.com/xmp/note/";
+pub const NS_EXIF: &str = "http://ns.adobe.com/exif/1.0/";
+pub const NS_PHOTOSHOP: &str = "http://ns.adobe.com/photoshop/1.0/";
+pub const NS_IPTC_EXT: &str = "http://iptc.org/std/Iptc4xmpExt/2008-02-29/";
+const NS_XML: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Identifier of the APP1 segment holding the main XMP packet in JPEG files
+pub const JPEG_XMP_ID: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// Identifier of APP1 segments holding Extended XMP chunks
+pub const JPEG_EXTENDED_XMP_ID: &[u8] = b"http://ns.adobe.com/xmp/extension/\0";
+
+/// One XMP property. Arrays (rdf:Bag, rdf:Seq, rdf:Alt) keep every item; for
+/// language alternatives the `x-default` item comes first. Struct fields are
+/// flattened to `parent/field` names.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmpProperty {
+    pub namespace: String,
+    pub prefix: String,
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+impl XmpProperty {
+    pub fn qualified_name(&self) -> String {
+        format!("{}:{}", self.prefix, self.name)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct XmpPacket {
+    pub properties: Vec<XmpProperty>,
+}
+
+impl XmpPacket {
+    /// Parse an XMP packet. Leading `<?xpacket` wrappers and trailing padding are ignored.
+    pub fn parse(xml: &str) -> anyhow::Result<Self> {
+        let xml = trim_packet(xml);
+        let document = Document::parse(xml)?;
+        let mut packet = XmpPacket::default();
+
+        for description in document
+            .descendants()
+            .filter(|n| n.has_tag_name((NS_RDF, "Description")))
+            .filter(|n| !n.ancestors().skip(1).any(|a| is_property_element(&a)))
+        {
+            packet.read_description(&description, "");
+        }
+
+        Ok(packet)
+    }
+
+    /// Values of a property, by namespace URI and local name
+    pub fn get(&self, namespace: &str, name: &str) -> Option<&[String]> {
+        self.properties
+            .iter()
+            .find(|p| p.namespace == namespace && p.name == name)
+            .map(|p| p.values.as_slice())
+    }
+
+    /// First (or `x-default`) value of a property
+    pub fn first(&self, namespace: &str, name: &str) -> Option<&str> {
+        self.get(namespace, name)?
+            .first()
+            .map(|s| s.as_str())
+            .filter(|s| !s.trim().is_empty())
+    }
+
+    /// Add properties from another packet (e.g. Extended XMP), keeping existing values
+    pub fn merge(&mut self, other: XmpPacket) {
+        for property in other.properties {
+            if self.get(&property.namespace, &property.name).is_none() {
+                self.properties.push(property);
+            }
+        }
+    }
+
+    fn read_description(&mut self, description: &Node, parent: &str) {
+        // Simple properties may be written as attributes of rdf:Description
+        for attribute in description.attributes() {
+            let Some(namespace) = attribute.namespace() else {
+                continue;
+            };
+            if namespace == NS_RDF || namespace == NS_XML {
+                continue;
+            }
+            self.push(description, namespace, &join_name(parent, attribute.name()), vec![attribute.value().to_string()]);
+        }
+
+        for property in description.children().filter(|n| n.is_element()) {
+            self.read_property(&property, parent);
+        }
+    }
+
+    fn read_property(&mut self, property: &Node, parent: &str) {
+        let Some(namespace) = property.tag_name().namespace() else {
+            return;
+        };
+        let name = join_name(parent, property.tag_name().name());
+
+        if let Some(resource) = property.attribute((NS_RDF, "resource")) {
+            self.push(property, namespace, &name, vec![resource.to_string()]);
+            return;
+        }
+
+        let child = property.children().find(|n| n.is_element());
+        match child {
+            Some(container) if ["Alt", "Bag", "Seq"].iter().any(|c| container.has_tag_name((NS_RDF, *c))) => {
+                let mut items: Vec<(bool, String)> = container
+                    .children()
+                    .filter(|n| n.has_tag_name((NS_RDF, "li")))
+                    .map(|li| {
+                        let is_default = li.attribute((NS_XML, "lang")) == Some("x-default");
+                        (is_default, text_of(&li))
+                    })
+                    .collect();
+                // Stable sort: x-default first, other languages keep document order
+                items.sort_by_key(|(is_default, _)| !is_default);
+                self.push(property, namespace, &name, items.into_iter().map(|(_, text)| text).collect());
+            }
+            Some(nested) if nested.has_tag_name((NS_RDF, "Description")) => {
+                self.read_description(&nested, &name);
+            }
+            Some(_) if property.attribute((NS_RDF, "parseType")) == Some("Resource") => {
+                self.read_description(property, &name);
+            }
+            _ => {
+                self.push(property, namespace, &name, vec![text_of(property)]);
+            }
+        }
+    }
+
+    fn push(&mut self, node: &Node, namespace: &str, name: &str, values: Vec<String>) {
+        let prefix = node
+            .lookup_prefix(namespace)
+            .map(|p| p.to_string())
+            .unwrap_or_else(|| namespace.to_string());
+        self.properties.push(XmpProperty {
+            namespace: namespace.to_string(),
+            prefix,
+            name: name.to_string(),
+            values,
+        });
+    }
+}
+
+fn is_property_element(node: &Node) -> bool {
+    node.is_element() && node.tag_name().namespace().is_some_and(|ns| ns != NS_RDF)
+        && !node.has_tag_name(("adobe:ns:meta/", "xmpmeta"))
+}
+
+fn join_name(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", parent, name)
+    }
+}
+
+fn text_of(node: &Node) -> String {
+    node.descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Cut the packet down to the `x:xmpmeta` (or bare `rdf:RDF`) element
+fn trim_packet(xml: &str) -> &str {
+    let xml = xml.trim_start_matches('\u{feff}');
+    let start = xml
+        .find("<x:xmpmeta")
+        .or_else(|| xml.find("<rdf:RDF"))
+        .unwrap_or(0);
+    let end = ["</x:xmpmeta>", "</rdf:RDF>"]
+        .iter()
+        .find_map(|tag| xml.rfind(tag).map(|i| i + tag.len()))
+        .filter(|end| *end > start)
+        .unwrap_or(xml.len());
+    &xml[start..end]
+}
+
+/// Read the main XMP packet and any Extended XMP chunks from JPEG APP1 segment payloads
+pub fn xmp_from_jpeg_segments<'a>(app1_segments: impl Iterator<Item = &'a [u8]>) -> Option<XmpPacket> {
+    let mut main = None;
+    // (guid, offset, data)
+    let mut extended: Vec<(&[u8], u32, &[u8])> = Vec::new();
+
+    for segment in app1_segments {
+        if let Some(xml) = segment.strip_prefix(JPEG_XMP_ID) {
+            if main.is_none() {
+                main = XmpPacket::parse(&String::from_utf8_lossy(xml)).ok();
+            }
+        } else if let Some(chunk) = segment.strip_prefix(JPEG_EXTENDED_XMP_ID) {
+            // 32-byte GUID, 4-byte full length, 4-byte offset, then data
+            if chunk.len() >= 40 {
+                let offset = u32::from_be_bytes([chunk[36], chunk[37], chunk[38], chunk[39]]);
+                extended.push((&chunk[..32], offset, &chunk[40..]));
+            }
+        }
+    }
+
+    let mut main = main?;
+
+    // The main packet names the GUID (MD5 of the full extension) it expects
+    let guid = main.first(NS_XMP_NOTE, "HasExtendedXMP").map(|g| g.to_string());
+    if let Some(guid) = guid {
+        let mut chunks: Vec<&(&[u8], u32, &[u8])> = extended.iter().filter(|(g, _, _)| *g == guid.as_bytes()).collect();
+        chunks.sort_by_key(|(_, offset, _)| *offset);
+        let data: Vec<u8> = chunks.iter().flat_map(|(_, _, data)| data.iter().copied()).collect();
+        if let Ok(packet) = XmpPacket::parse(&String::from_utf8_lossy(&data)) {
+            main.merge(packet);
+        }
+    }
+
+    Some(main)
+}
+
+/// Map XMP properties into metadata. Every property is kept as an "xmp" entry named
+/// `prefix:name`; well-known ones also fill prompt fields when the format-specific data did not.
+pub fn apply_xmp_to_metadata(packet: &XmpPacket, origin: &SourceLocation, metadata: &mut ExtractedMetadata) {
+    if let Some(comment) = packet.first(NS_EXIF, "UserComment") {
+        // Some converters carry the A1111 parameters string over into XMP
+        metadata.record_source(&origin.with_key("exif:UserComment").with_parser("a1111"), |m| {
+            if comment.contains("Steps:") {
+                m.parameters = Some(comment.to_string());
+                crate::extraction::png::parse_parameters_string(comment, m);
+            }
+        });
+    }
+
+    let description = packet
+        .first(NS_DC, "description")
+        .map(|d| ("dc:description", d))
+        .or_else(|| packet.first(NS_XMP, "Description").map(|d| ("xmp:Description", d)));
+    if let Some((key, description)) = description {
+        metadata.record_source(&origin.with_key(key), |m| {
+            m.prompt = Some(description.to_string());
+        });
+    }
+
+    if let Some(subjects) = packet.get(NS_DC, "subject") {
+        for subject in subjects {
+            if !subject.trim().is_empty() && !metadata.keywords.contains(subject) {
+                metadata.keywords.push(subject.clone());
+            }
+        }
+    }
+
+    for property in &packet.properties {
+        let value = property.values.join(", ");
+        if !value.trim().is_empty() {
+            metadata.entries.push(MetadataEntry::new(&property.qualified_name(), &value, "xmp"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKET: &str = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:Iptc4xmpExt="http://iptc.org/std/Iptc4xmpExt/2008-02-29/"
+    xmlns:xmpNote="http://ns.adobe.com/xmp/note/"
+    xmp:CreatorTool="Adobe Firefly"
+    xmpNote:HasExtendedXMP="0123456789ABCDEF0123456789ABCDEF">
+   <dc:description>
+    <rdf:Alt>
+     <rdf:li xml:lang="fr-FR">un chat</rdf:li>
+     <rdf:li xml:lang="x-default">a cat &amp; a dog, &quot;watercolor&quot;</rdf:li>
+    </rdf:Alt>
+   </dc:description>
+   <dc:subject>
+    <rdf:Bag><rdf:li>cat</rdf:li><rdf:li>dog</rdf:li></rdf:Bag>
+   </dc:subject>
+   <Iptc4xmpExt:DigitalSourceType rdf:resource="http://cv.iptc.org/newscodes/digitalsourcetype/trainedAlgorithmicMedia"/>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#;
+
+    #[test]
+    fn test_parse_xmp_packet() {
+        let packet = XmpPacket::parse(PACKET).unwrap();
+
+        assert_eq!(packet.first(NS_DC, "description"), Some(r#"a cat & a dog, "watercolor""#));
+        assert_eq!(packet.get(NS_DC, "subject").unwrap(), ["cat", "dog"]);
+        assert_eq!(packet.first(NS_XMP, "CreatorTool"), Some("Adobe Firefly"));
+        assert_eq!(
+            packet.first(NS_IPTC_EXT, "DigitalSourceType"),
+            Some("http://cv.iptc.org/newscodes/digitalsourcetype/trainedAlgorithmicMedia")
+        );
+
+        let mut metadata = ExtractedMetadata::empty();
+        apply_xmp_to_metadata(&packet, &SourceLocation::new("jpeg:APP1", "XMP", Some(20), "xmp"), &mut metadata);
+        assert_eq!(metadata.prompt, Some(r#"a cat & a dog, "watercolor""#.to_string()));
+        assert_eq!(metadata.source_of("prompt").unwrap().location.key, "dc:description");
+        assert_eq!(metadata.keywords, vec!["cat".to_string(), "dog".to_string()]);
+        assert!(metadata.entries.contains(&MetadataEntry::new("dc:subject", "cat, dog", "xmp")));
+        assert!(metadata.entries.contains(&MetadataEntry::new("xmp:CreatorTool", "Adobe Firefly", "xmp")));
+    }
+
+    #[test]
+    fn test_extended_xmp_is_merged() {
+        let guid = b"0123456789ABCDEF0123456789ABCDEF";
+        let extension = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+            <rdf:Description xmlns:photoshop="http://ns.adobe.com/photoshop/1.0/" photoshop:Instructions="long history"/>
+            </rdf:RDF></x:xmpmeta>"#;
+        let (first, second) = extension.as_bytes().split_at(40);
+
+        let mut main = JPEG_XMP_ID.to_vec();
+        main.extend_from_slice(PACKET.as_bytes());
+        let chunk = |offset: usize, data: &[u8]| {
+            let mut segment = JPEG_EXTENDED_XMP_ID.to_vec();
+            segment.extend_from_slice(guid);
+            segment.extend_from_slice(&(extension.len() as u32).to_be_bytes());
+            segment.extend_from_slice(&(offset as u32).to_be_bytes());
+            segment.extend_from_slice(data);
+            segment
+        };
+        // Chunks may arrive out of order
+        let segments = [main, chunk(40, second), chunk(0, first)];
+
+        let packet = xmp_from_jpeg_segments(segments.iter().map(|s| s.as_slice())).unwrap();
+        assert_eq!(packet.first(NS_PHOTOSHOP, "Instructions"), Some("long history"));
+        assert_eq!(packet.first(NS_DC, "description"), Some(r#"a cat & a dog, "watercolor""#));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::extraction::format::{sniff_file, FileFormat};
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
@@ -16,7 +17,6 @@ impl DirectoryScanner {
 
     pub fn scan(&self) -> anyhow::Result<Vec<PathBuf>> {
         let mut image_files = Vec::new();
-        let supported_extensions = ["png", "jpg", "jpeg", "webp"];
 
         let walker = if self.recursive {
             WalkDir::new(&self.root_path)
@@ -28,13 +28,11 @@ impl DirectoryScanner {
             let entry = entry?;
             let path = entry.path();
 
-            if path.is_file() {
-                if let Some(ext) = path.extension() {
-                    let ext_lower = ext.to_string_lossy().to_lowercase();
-                    if supported_extensions.contains(&ext_lower.as_str()) {
-                        image_files.push(path.to_path_buf());
-                    }
-                }
+            // Image extensions, plus images saved without one (or with a foreign one)
+            if path.is_file()
+                && (FileFormat::from_path(path).is_some() || matches!(sniff_file(path), Ok(Some(_))))
+            {
+                image_files.push(path.to_path_buf());
             }
         }
 
@@ -57,11 +55,12 @@ mod tests {
         fs::write(test_dir.join("test.png"), b"fake png").unwrap();
         fs::write(test_dir.join("test.jpg"), b"fake jpg").unwrap();
         fs::write(test_dir.join("test.txt"), b"not an image").unwrap();
+        fs::write(test_dir.join("download"), b"\x89PNG\r\n\x1a\n").unwrap();
 
         let scanner = DirectoryScanner::new(test_dir, false);
         let files = scanner.scan().unwrap();
 
-        assert_eq!(files.len(), 2);
+        assert_eq!(files.len(), 3);
     }
 }
 
@@ -1,15 +1,20 @@
-use crate::extraction::MetadataExtractor;
+use crate::extraction::{MetadataExtractor, ParserRegistry};
+use crate::extraction::isobmff::parse_isobmff;
+use crate::extraction::format::{sniff_format, FileFormat};
+use crate::extraction::watermark::{apply_watermark_to_metadata, detect_watermark};
 use crate::ingestion::scanner::DirectoryScanner;
 use crate::storage::{
     Database, ImageRepository, PromptRepository, MetadataRepository,
-    CollectionRepository, TagRepository,
+    CollectionRepository, TagRepository, StageRepository, ParamsRepository, SourceRepository,
 };
-use crate::utils::{calculate_file_hash, thumbnail};
+use crate::storage::image_repo::Image;
+use crate::utils::{calculate_hash, thumbnail};
 use crate::extraction::tag_extractor::TagExtractor;
 use crate::config::Config;
 use chrono::Utc;
-use image::{open, GenericImageView};
+use image::{DynamicImage, GenericImageView};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use uuid::Uuid;
 use log::{info, warn};
 
@@ -22,7 +27,13 @@ pub struct IngestionService {
     metadata_repo: MetadataRepository,
     collection_repo: CollectionRepository,
     tag_repo: TagRepository,
+    stage_repo: StageRepository,
+    params_repo: ParamsRepository,
+    source_repo: SourceRepository,
     thumbnail_config: Option<ThumbnailConfig>,
+    upload_path: PathBuf,
+    detect_watermarks: bool,
+    parsers: Arc<ParserRegistry>,
 }
 
 #[derive(Debug, Clone)]
@@ -50,8 +61,14 @@ impl IngestionService {
             metadata_repo: MetadataRepository::new(db.clone()),
             collection_repo: CollectionRepository::new(db.clone()),
             tag_repo: TagRepository::new(db.clone()),
+            stage_repo: StageRepository::new(db.clone()),
+            params_repo: ParamsRepository::new(db.clone()),
+            source_repo: SourceRepository::new(db.clone()),
             db,
             thumbnail_config: None,
+            upload_path: PathBuf::from("./data/uploads"),
+            detect_watermarks: false,
+            parsers: Arc::new(ParserRegistry::default()),
         }
     }
 
@@ -73,11 +90,30 @@ impl IngestionService {
             metadata_repo: MetadataRepository::new(db.clone()),
             collection_repo: CollectionRepository::new(db.clone()),
             tag_repo: TagRepository::new(db.clone()),
+            stage_repo: StageRepository::new(db.clone()),
+            params_repo: ParamsRepository::new(db.clone()),
+            source_repo: SourceRepository::new(db.clone()),
             db,
             thumbnail_config,
+            upload_path: PathBuf::from(&config.storage.upload_path),
+            detect_watermarks: config.watermark.detect_on_ingestion,
+            parsers: Arc::new(ParserRegistry::default()),
         }
     }
 
+    /// Extract metadata with the given parsers instead of the built-in ones, e.g. to
+    /// read in-house formats
+    pub fn with_parsers(mut self, parsers: ParserRegistry) -> Self {
+        self.parsers = Arc::new(parsers);
+        self
+    }
+
+    /// Store uploads under `path` instead of the configured directory
+    pub fn with_upload_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.upload_path = path.as_ref().to_path_buf();
+        self
+    }
+
     pub fn scan_directory<P: AsRef<Path>>(
         &self,
         root_path: P,
@@ -232,43 +268,106 @@ impl IngestionService {
             return Ok(false); // Skipped (already exists)
         }
 
-        // Calculate file hash for deduplication
-        let file_hash = calculate_file_hash(file_path)?;
-        
-        // Check for duplicate by hash
-        // TODO: Add hash-based lookup to image_repo
+        let data = std::fs::read(file_path)?;
+        let file_hash = calculate_hash(&data);
+        let image = self.ingest_file(file_path, &data, None, file_hash)?;
 
-        // Get image dimensions
-        let (width, height) = self.get_image_dimensions(file_path)?;
+        // Assign to folder-based collection
+        self.assign_to_folder_collection(file_path, &image.id)?;
+
+        Ok(true) // Processed successfully
+    }
+
+    /// Store an uploaded image in the upload directory and ingest it. Files are named by
+    /// their SHA-256 (`ab/abcdef....png`), so uploads never overwrite each other; content
+    /// that is already in the library returns the existing record. Returns the record and
+    /// whether it already existed.
+    pub fn ingest_upload(&self, data: &[u8], file_name: Option<&str>) -> anyhow::Result<(Image, bool)> {
+        let file_hash = calculate_hash(data);
+        if let Some(existing) = self.image_repo.find_by_hash(&file_hash)? {
+            return Ok((existing, true));
+        }
+
+        let format = sniff_format(data).ok_or_else(|| anyhow::anyhow!("Not a supported image format"))?;
+        let extension = match format {
+            FileFormat::Jpeg => "jpg",
+            other => other.as_str(),
+        };
+        let directory = self.upload_path.join(&file_hash[..2]);
+        std::fs::create_dir_all(&directory)?;
+        let file_path = directory.join(format!("{}.{}", file_hash, extension));
+
+        // The file is already there when its record was deleted since an earlier upload.
+        // Write under a temporary name, so a failed upload never leaves a truncated image.
+        if !file_path.exists() {
+            let partial = directory.join(format!(".{}.part", file_hash));
+            std::fs::write(&partial, data)?;
+            std::fs::rename(&partial, &file_path)?;
+        }
+
+        let image = self.ingest_file(&file_path, data, file_name, file_hash)?;
+        info!("Ingested upload {} as {}", file_name.unwrap_or("(unnamed)"), file_path.display());
+        Ok((image, false))
+    }
+
+    /// Extract and store everything about one image file, from its `data` as already read
+    /// for hashing. `file_name` overrides the name taken from the path, e.g. the original
+    /// name of an upload.
+    fn ingest_file(&self, file_path: &Path, data: &[u8], file_name: Option<&str>, file_hash: String) -> anyhow::Result<Image> {
+        // The content decides the format; the extension only helps when it is not recognized
+        let sniffed = sniff_format(data);
+        let extension_format = FileFormat::from_path(file_path);
 
         // Extract metadata
-        let extracted = MetadataExtractor::extract(file_path)?;
+        let mut extracted = MetadataExtractor::extract_data(data, sniffed.or(extension_format), &self.parsers);
+
+        // Get image dimensions
+        let decoded = decode_image(data);
+        let (width, height) = match &decoded {
+            Some(img) => img.dimensions(),
+            // AVIF/HEIF can't be decoded here, but the container declares the size
+            None => parse_isobmff(data).dimensions.unwrap_or((0, 0)),
+        };
+
+        // Invisible watermarks need the decoded pixels, so they are only looked for when enabled
+        if self.detect_watermarks {
+            match &decoded {
+                Some(img) => apply_watermark_to_metadata(detect_watermark(&img.to_rgb8()).as_ref(), &mut extracted),
+                None => warn!("Failed to check watermark for {}: image could not be decoded", file_path.display()),
+            }
+        }
 
-        // Get file info
-        let metadata = std::fs::metadata(file_path)?;
-        let file_name = file_path.file_name()
-            .and_then(|n| n.to_str())
+        let file_name = file_name
+            .or_else(|| file_path.file_name().and_then(|n| n.to_str()))
             .unwrap_or("unknown")
             .to_string();
 
-        let format = file_path.extension()
-            .and_then(|e| e.to_str())
-            .map(|s| s.to_lowercase())
-            .unwrap_or_default();
+        // Store the format the content has, and flag files whose extension says otherwise
+        let extension_mismatch = sniffed.is_some() && sniffed != extension_format;
+        let format = match sniffed.or(extension_format) {
+            Some(format) => format.as_str().to_string(),
+            None => file_path.extension()
+                .and_then(|e| e.to_str())
+                .map(|s| s.to_lowercase())
+                .unwrap_or_default(),
+        };
 
         // Create image record
         let now = Utc::now().to_rfc3339();
         let image_id = Uuid::new_v4().to_string();
         
-        let image = crate::storage::image_repo::Image {
+        let image = Image {
             id: image_id.clone(),
             file_path: file_path.to_str().unwrap().to_string(),
             file_name,
-            file_size: metadata.len(),
+            file_size: data.len() as u64,
             format,
             width: Some(width),
             height: Some(height),
             hash: Some(file_hash),
+            generator: extracted.generator.clone(),
+            generator_version: extracted.generator_version.clone(),
+            extension_mismatch,
             created_at: now.clone(),
             updated_at: now.clone(),
             last_scanned_at: now.clone(),
@@ -279,7 +378,7 @@ impl IngestionService {
         // Generate thumbnail if enabled
         if let Some(ref thumb_config) = self.thumbnail_config {
             if thumb_config.enabled {
-                self.generate_thumbnail_if_needed(file_path, &thumb_config)?;
+                self.generate_thumbnail_if_needed(file_path, thumb_config)?;
             }
         }
 
@@ -328,7 +427,35 @@ impl IngestionService {
             self.metadata_repo.create(&meta)?;
         }
 
-        // Store generation parameters
+        // Store descriptive metadata (IPTC, XMP, COM) and provenance under its own metadata_type
+        for entry in extracted.entries {
+            let meta = crate::storage::metadata_repo::Metadata {
+                id: Uuid::new_v4().to_string(),
+                image_id: image_id.clone(),
+                key: entry.key,
+                value: entry.value,
+                metadata_type: entry.metadata_type,
+                created_at: now.clone(),
+            };
+            self.metadata_repo.create(&meta)?;
+        }
+
+        // Embedded keywords become tags alongside the ones extracted from the prompt
+        for keyword in &extracted.keywords {
+            let tag = self.tag_repo.find_or_create(&keyword.to_lowercase(), "keyword")?;
+            let image_tag = crate::storage::tag_repo::ImageTag {
+                image_id: image_id.clone(),
+                tag_id: tag.id.clone(),
+                confidence: 1.0,
+                source: "metadata".to_string(),
+                created_at: now.clone(),
+            };
+            self.tag_repo.add_to_image(&image_tag)?;
+        }
+
+        // Store generation parameters as written in the file ("-1", "Euler a", "832x1216").
+        // These rows are what the image detail shows; `generation_params` below holds the
+        // validated, typed copy that filtering and sorting use.
         if let Some(seed) = extracted.seed {
             self.store_metadata(&image_id, "seed", &seed, &now)?;
         }
@@ -345,10 +472,52 @@ impl IngestionService {
             self.store_metadata(&image_id, "size", &size, &now)?;
         }
 
-        // Assign to folder-based collection
-        self.assign_to_folder_collection(file_path, &image_id)?;
+        // Typed parameters go to their own table so they can be range-queried
+        if !extracted.params.is_empty() || !extracted.params.warnings.is_empty() {
+            self.params_repo.create(&image_id, &extracted.params, &now)?;
+        }
 
-        Ok(true) // Processed successfully
+        // Record where each field came from, for debugging wrong prompts or settings
+        for source in extracted.sources {
+            let record = crate::storage::source_repo::FieldSourceRecord {
+                id: Uuid::new_v4().to_string(),
+                image_id: image_id.clone(),
+                field: source.field,
+                value: source.value,
+                container: source.location.container,
+                key: source.location.key,
+                offset: source.location.offset,
+                parser: source.location.parser,
+                selected: source.selected,
+                created_at: now.clone(),
+            };
+            self.source_repo.create(&record)?;
+        }
+
+        // Store generation stages (base, hires fix, refiner, upscale) in pipeline order
+        for (index, stage) in extracted.stages.into_iter().enumerate() {
+            let record = crate::storage::stage_repo::Stage {
+                id: Uuid::new_v4().to_string(),
+                image_id: image_id.clone(),
+                stage_index: index as u32,
+                kind: stage.kind,
+                model: stage.model,
+                sampler: stage.sampler,
+                scheduler: stage.scheduler,
+                steps: stage.steps,
+                cfg_scale: stage.cfg_scale,
+                seed: stage.seed,
+                denoise: stage.denoise,
+                size: stage.size,
+                upscaler: stage.upscaler,
+                start_step: stage.start_step,
+                end_step: stage.end_step,
+                created_at: now.clone(),
+            };
+            self.stage_repo.create(&record)?;
+        }
+
+        Ok(image)
     }
 
     fn store_metadata(&self, image_id: &str, key: &str, value: &str, created_at: &str) -> anyhow::Result<()> {
@@ -365,13 +534,6 @@ impl IngestionService {
         Ok(())
     }
 
-    fn get_image_dimensions(&self, path: &Path) -> anyhow::Result<(u32, u32)> {
-        match open(path) {
-            Ok(img) => Ok(img.dimensions()),
-            Err(_) => Ok((0, 0)), // Could not open/decode image
-        }
-    }
-
     fn generate_thumbnail_if_needed(
         &self,
         image_path: &Path,
@@ -511,3 +673,49 @@ impl IngestionService {
         Ok(())
     }
 }
+
+/// Decode an image held in memory, guessing the decoder from the content
+fn decode_image(data: &[u8]) -> Option<DynamicImage> {
+    image::io::Reader::new(std::io::Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .decode()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DatabaseConfig;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_ingest_upload_is_hash_addressed_and_deduplicated() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(&DatabaseConfig {
+            database_path: temp_dir.path().join("test.db").to_str().unwrap().to_string(),
+        })
+        .unwrap();
+        let mut service = IngestionService::new(db);
+        service.upload_path = temp_dir.path().join("uploads");
+
+        let mut png = Vec::new();
+        image::RgbImage::from_pixel(8, 8, image::Rgb([200, 40, 40]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+
+        let (image, duplicate) = service.ingest_upload(&png, Some("red.png")).unwrap();
+        assert!(!duplicate);
+        assert_eq!(image.file_name, "red.png");
+        let hash = calculate_hash(&png);
+        let stored = service.upload_path.join(&hash[..2]).join(format!("{}.png", hash));
+        assert_eq!(image.file_path, stored.to_str().unwrap());
+        assert_eq!(std::fs::read(&stored).unwrap(), png);
+
+        let (again, duplicate) = service.ingest_upload(&png, Some("copy.png")).unwrap();
+        assert!(duplicate);
+        assert_eq!(again.id, image.id);
+
+        assert!(service.ingest_upload(b"not an image", None).is_err());
+    }
+}
@@ -7,6 +7,6 @@ pub mod utils;
 pub mod services;
 
 // Re-export commonly used types
-pub use storage::{Database, ImageRepository, PromptRepository, MetadataRepository, CollectionRepository, TagRepository};
+pub use storage::{Database, ImageRepository, PromptRepository, MetadataRepository, CollectionRepository, TagRepository, StageRepository};
 pub use ingestion::{IngestionService, ScanProgress};
 
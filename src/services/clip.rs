@@ -31,6 +31,7 @@ impl Default for ClipConfig {
     }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 struct InterrogateRequest {
     image: String, // base64 encoded image
@@ -187,7 +187,7 @@ impl CollectionRepository {
         )?;
 
         let image_ids = stmt.query_map(params![collection_id], |row| {
-            Ok(row.get::<_, String>(0)?)
+            row.get::<_, String>(0)
         })?;
 
         let mut result = Vec::new();
@@ -217,7 +217,7 @@ impl CollectionRepository {
         )?;
 
         let collection_ids = stmt.query_map([], |row| {
-            Ok(row.get::<_, String>(0)?)
+            row.get::<_, String>(0)
         })?;
 
         let mut result = Vec::new();
@@ -13,6 +13,10 @@ pub struct Image {
     pub width: Option<u32>,
     pub height: Option<u32>,
     pub hash: Option<String>,
+    pub generator: Option<String>,
+    pub generator_version: Option<String>,
+    /// The file extension names a different format than the file's content
+    pub extension_mismatch: bool,
     pub created_at: String,
     pub updated_at: String,
     pub last_scanned_at: String,
@@ -33,8 +37,8 @@ impl ImageRepository {
         let conn = conn.lock().unwrap();
 
         conn.execute(
-            "INSERT INTO images (id, file_path, file_name, file_size, format, width, height, hash, created_at, updated_at, last_scanned_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            "INSERT INTO images (id, file_path, file_name, file_size, format, width, height, hash, generator, generator_version, extension_mismatch, created_at, updated_at, last_scanned_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
             params![
                 image.id,
                 image.file_path,
@@ -44,6 +48,9 @@ impl ImageRepository {
                 image.width.map(|w| w as i32),
                 image.height.map(|h| h as i32),
                 image.hash,
+                image.generator,
+                image.generator_version,
+                image.extension_mismatch,
                 image.created_at,
                 image.updated_at,
                 image.last_scanned_at,
@@ -58,7 +65,7 @@ impl ImageRepository {
         let conn = conn.lock().unwrap();
 
         let mut stmt = conn.prepare(
-            "SELECT id, file_path, file_name, file_size, format, width, height, hash, created_at, updated_at, last_scanned_at
+            "SELECT id, file_path, file_name, file_size, format, width, height, hash, generator, generator_version, extension_mismatch, created_at, updated_at, last_scanned_at
              FROM images WHERE file_path = ?1",
         )?;
 
@@ -72,9 +79,48 @@ impl ImageRepository {
                 width: row.get::<_, Option<i32>>(5)?.map(|w| w as u32),
                 height: row.get::<_, Option<i32>>(6)?.map(|h| h as u32),
                 hash: row.get(7)?,
-                created_at: row.get(8)?,
-                updated_at: row.get(9)?,
-                last_scanned_at: row.get(10)?,
+                generator: row.get(8)?,
+                generator_version: row.get(9)?,
+                extension_mismatch: row.get(10)?,
+                created_at: row.get(11)?,
+                updated_at: row.get(12)?,
+                last_scanned_at: row.get(13)?,
+            })
+        });
+
+        match image {
+            Ok(img) => Ok(Some(img)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The first image stored with this content hash
+    pub fn find_by_hash(&self, hash: &str) -> anyhow::Result<Option<Image>> {
+        let conn = self.db.get_connection();
+        let conn = conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path, file_name, file_size, format, width, height, hash, generator, generator_version, extension_mismatch, created_at, updated_at, last_scanned_at
+             FROM images WHERE hash = ?1 ORDER BY created_at LIMIT 1",
+        )?;
+
+        let image = stmt.query_row(params![hash], |row| {
+            Ok(Image {
+                id: row.get(0)?,
+                file_path: row.get(1)?,
+                file_name: row.get(2)?,
+                file_size: row.get::<_, i64>(3)? as u64,
+                format: row.get(4)?,
+                width: row.get::<_, Option<i32>>(5)?.map(|w| w as u32),
+                height: row.get::<_, Option<i32>>(6)?.map(|h| h as u32),
+                hash: row.get(7)?,
+                generator: row.get(8)?,
+                generator_version: row.get(9)?,
+                extension_mismatch: row.get(10)?,
+                created_at: row.get(11)?,
+                updated_at: row.get(12)?,
+                last_scanned_at: row.get(13)?,
             })
         });
 
@@ -90,7 +136,7 @@ impl ImageRepository {
         let conn = conn.lock().unwrap();
 
         let mut stmt = conn.prepare(
-            "SELECT id, file_path, file_name, file_size, format, width, height, hash, created_at, updated_at, last_scanned_at
+            "SELECT id, file_path, file_name, file_size, format, width, height, hash, generator, generator_version, extension_mismatch, created_at, updated_at, last_scanned_at
              FROM images WHERE id = ?1",
         )?;
 
@@ -104,9 +150,12 @@ impl ImageRepository {
                 width: row.get::<_, Option<i32>>(5)?.map(|w| w as u32),
                 height: row.get::<_, Option<i32>>(6)?.map(|h| h as u32),
                 hash: row.get(7)?,
-                created_at: row.get(8)?,
-                updated_at: row.get(9)?,
-                last_scanned_at: row.get(10)?,
+                generator: row.get(8)?,
+                generator_version: row.get(9)?,
+                extension_mismatch: row.get(10)?,
+                created_at: row.get(11)?,
+                updated_at: row.get(12)?,
+                last_scanned_at: row.get(13)?,
             })
         });
 
@@ -135,7 +184,7 @@ impl ImageRepository {
         let conn = conn.lock().unwrap();
 
         let mut stmt = conn.prepare(
-            "SELECT id, file_path, file_name, file_size, format, width, height, hash, created_at, updated_at, last_scanned_at
+            "SELECT id, file_path, file_name, file_size, format, width, height, hash, generator, generator_version, extension_mismatch, created_at, updated_at, last_scanned_at
              FROM images ORDER BY created_at DESC",
         )?;
 
@@ -149,9 +198,12 @@ impl ImageRepository {
                 width: row.get::<_, Option<i32>>(5)?.map(|w| w as u32),
                 height: row.get::<_, Option<i32>>(6)?.map(|h| h as u32),
                 hash: row.get(7)?,
-                created_at: row.get(8)?,
-                updated_at: row.get(9)?,
-                last_scanned_at: row.get(10)?,
+                generator: row.get(8)?,
+                generator_version: row.get(9)?,
+                extension_mismatch: row.get(10)?,
+                created_at: row.get(11)?,
+                updated_at: row.get(12)?,
+                last_scanned_at: row.get(13)?,
             })
         })?;
 
@@ -173,3 +225,62 @@ impl ImageRepository {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DatabaseConfig;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_delete_removes_child_rows() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(&DatabaseConfig {
+            database_path: temp_dir.path().join("test.db").to_str().unwrap().to_string(),
+        })
+        .unwrap();
+        let repo = ImageRepository::new(db.clone());
+        let now = Utc::now().to_rfc3339();
+        repo.create(&Image {
+            id: "img".to_string(),
+            file_path: "/images/img.png".to_string(),
+            file_name: "img.png".to_string(),
+            file_size: 0,
+            format: "png".to_string(),
+            width: None,
+            height: None,
+            hash: None,
+            generator: None,
+            generator_version: None,
+            extension_mismatch: false,
+            created_at: now.clone(),
+            updated_at: now.clone(),
+            last_scanned_at: now,
+        })
+        .unwrap();
+
+        let child_tables = ["generation_stages", "generation_params", "field_sources"];
+        {
+            let conn = db.get_connection();
+            let conn = conn.lock().unwrap();
+            conn.execute_batch(
+                "INSERT INTO generation_stages (id, image_id, stage_index, kind, created_at) VALUES ('s', 'img', 0, 'base', '');
+                 INSERT INTO generation_params (image_id, steps, created_at) VALUES ('img', 20, '');
+                 INSERT INTO field_sources (id, image_id, field, value, container, key, parser, selected, created_at)
+                     VALUES ('f', 'img', 'steps', '20', 'png:tEXt', 'parameters', 'a1111', 1, '');",
+            )
+            .unwrap();
+        }
+
+        repo.delete("img").unwrap();
+
+        let conn = db.get_connection();
+        let conn = conn.lock().unwrap();
+        for table in child_tables {
+            let count: i64 = conn
+                .query_row(&format!("SELECT COUNT(*) FROM {} WHERE image_id = 'img'", table), [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(count, 0, "{} still has rows", table);
+        }
+    }
+}
@@ -70,31 +70,65 @@ impl MetadataRepository {
         Ok(result)
     }
 
-    pub fn find_by_key(&self, image_id: &str, key: &str, metadata_type: &str) -> anyhow::Result<Option<Metadata>> {
+    /// Delete an image's rows of one type whose key starts with `prefix`, e.g. every
+    /// `watermark*` provenance row before a new detection result is stored
+    pub fn delete_by_key_prefix(&self, image_id: &str, metadata_type: &str, prefix: &str) -> anyhow::Result<()> {
         let conn = self.db.get_connection();
         let conn = conn.lock().unwrap();
 
-        let mut stmt = conn.prepare(
-            "SELECT id, image_id, key, value, metadata_type, created_at
-             FROM metadata WHERE image_id = ?1 AND key = ?2 AND metadata_type = ?3",
+        conn.execute(
+            "DELETE FROM metadata WHERE image_id = ?1 AND metadata_type = ?2 AND substr(key, 1, length(?3)) = ?3",
+            params![image_id, metadata_type, prefix],
         )?;
 
-        let metadata = stmt.query_row(params![image_id, key, metadata_type], |row| {
-            Ok(Metadata {
-                id: row.get(0)?,
-                image_id: row.get(1)?,
-                key: row.get(2)?,
-                value: row.get(3)?,
-                metadata_type: row.get(4)?,
-                created_at: row.get(5)?,
-            })
-        });
+        Ok(())
+    }
+}
 
-        match metadata {
-            Ok(meta) => Ok(Some(meta)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(e.into()),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DatabaseConfig;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_delete_by_key_prefix() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(&DatabaseConfig {
+            database_path: temp_dir.path().join("test.db").to_str().unwrap().to_string(),
+        })
+        .unwrap();
+        db.get_connection()
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO images (id, file_path, file_name, file_size, format, created_at, updated_at, last_scanned_at)
+                 VALUES ('img', 'img', 'img', 0, 'png', '', '', '')",
+                [],
+            )
+            .unwrap();
+        let repo = MetadataRepository::new(db);
+        for (key, metadata_type) in [
+            ("watermark", "provenance"),
+            ("watermark.bit_accuracy", "provenance"),
+            ("c2pa.claim_generator", "provenance"),
+            ("watermark", "generation"),
+        ] {
+            repo.create(&Metadata {
+                id: format!("{}-{}", metadata_type, key),
+                image_id: "img".to_string(),
+                key: key.to_string(),
+                value: String::new(),
+                metadata_type: metadata_type.to_string(),
+                created_at: String::new(),
+            })
+            .unwrap();
         }
+
+        repo.delete_by_key_prefix("img", "provenance", "watermark").unwrap();
+
+        let mut left: Vec<_> = repo.find_by_image_id("img").unwrap().into_iter().map(|m| m.id).collect();
+        left.sort();
+        assert_eq!(left, vec!["generation-watermark", "provenance-c2pa.claim_generator"]);
     }
 }
-
@@ -9,12 +9,18 @@ pub mod prompt_repo;
 pub mod metadata_repo;
 pub mod collection_repo;
 pub mod tag_repo;
+pub mod stage_repo;
+pub mod params_repo;
+pub mod source_repo;
 
 pub use image_repo::ImageRepository;
 pub use prompt_repo::PromptRepository;
 pub use metadata_repo::MetadataRepository;
 pub use collection_repo::CollectionRepository;
 pub use tag_repo::TagRepository;
+pub use stage_repo::StageRepository;
+pub use params_repo::ParamsRepository;
+pub use source_repo::SourceRepository;
 
 #[derive(Clone)]
 pub struct Database {
@@ -29,6 +35,9 @@ impl Database {
         }
 
         let conn = Connection::open(&config.database_path)?;
+        // SQLite leaves foreign keys off per connection by default; the image child tables
+        // rely on ON DELETE CASCADE
+        conn.execute_batch("PRAGMA foreign_keys = ON")?;
         let db = Database {
             conn: Arc::new(Mutex::new(conn)),
         };
@@ -50,6 +59,9 @@ impl Database {
                 width INTEGER,
                 height INTEGER,
                 hash TEXT,
+                generator TEXT,
+                generator_version TEXT,
+                extension_mismatch INTEGER NOT NULL DEFAULT 0,
                 created_at TEXT NOT NULL,
                 updated_at TEXT NOT NULL,
                 last_scanned_at TEXT NOT NULL
@@ -57,6 +69,19 @@ impl Database {
             [],
         )?;
 
+        // Columns added after the initial schema; existing databases get them here
+        Self::add_column_if_missing(&conn, "images", "generator", "TEXT")?;
+        Self::add_column_if_missing(&conn, "images", "generator_version", "TEXT")?;
+        Self::add_column_if_missing(&conn, "images", "extension_mismatch", "INTEGER NOT NULL DEFAULT 0")?;
+
+        // Rows ingested before content sniffing hold the lowercased file extension; map the
+        // aliases onto the canonical names in `FileFormat::as_str`
+        conn.execute_batch(
+            "UPDATE images SET format = 'jpeg' WHERE format IN ('jpg', 'jpe', 'jfif');
+             UPDATE images SET format = 'heif' WHERE format = 'heic';
+             UPDATE images SET format = 'tiff' WHERE format = 'tif';",
+        )?;
+
         // Prompts table
         conn.execute(
             "CREATE TABLE IF NOT EXISTS prompts (
@@ -139,6 +164,69 @@ impl Database {
             [],
         )?;
 
+        // Generation stages table (base, hires fix, refiner and upscale passes)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS generation_stages (
+                id TEXT PRIMARY KEY,
+                image_id TEXT NOT NULL,
+                stage_index INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                model TEXT,
+                sampler TEXT,
+                scheduler TEXT,
+                steps TEXT,
+                cfg_scale TEXT,
+                seed TEXT,
+                denoise TEXT,
+                size TEXT,
+                upscaler TEXT,
+                start_step TEXT,
+                end_step TEXT,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (image_id) REFERENCES images(id) ON DELETE CASCADE,
+                UNIQUE(image_id, stage_index)
+            )",
+            [],
+        )?;
+
+        // Typed generation parameters, one row per image, for numeric filtering
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS generation_params (
+                image_id TEXT PRIMARY KEY,
+                seed INTEGER,
+                steps INTEGER,
+                cfg_scale REAL,
+                width INTEGER,
+                height INTEGER,
+                denoise REAL,
+                clip_skip INTEGER,
+                sampler TEXT,
+                scheduler TEXT,
+                warnings TEXT,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (image_id) REFERENCES images(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        // Where each extracted field came from, including the candidates that lost
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS field_sources (
+                id TEXT PRIMARY KEY,
+                image_id TEXT NOT NULL,
+                field TEXT NOT NULL,
+                value TEXT NOT NULL,
+                container TEXT NOT NULL,
+                key TEXT NOT NULL,
+                byte_offset INTEGER,
+                parser TEXT NOT NULL,
+                selected INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (image_id) REFERENCES images(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
         // Scan directories table
         conn.execute(
             "CREATE TABLE IF NOT EXISTS scan_directories (
@@ -165,6 +253,10 @@ impl Database {
             "CREATE INDEX IF NOT EXISTS idx_images_hash ON images(hash)",
             [],
         )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_images_generator ON images(generator)",
+            [],
+        )?;
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_prompts_image ON prompts(image_id)",
             [],
@@ -209,6 +301,30 @@ impl Database {
             "CREATE INDEX IF NOT EXISTS idx_image_tags_tag ON image_tags(tag_id)",
             [],
         )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_generation_stages_image ON generation_stages(image_id)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_field_sources_image ON field_sources(image_id)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_generation_params_seed ON generation_params(seed)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_generation_params_steps ON generation_params(steps)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_generation_params_cfg ON generation_params(cfg_scale)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_generation_params_size ON generation_params(width, height)",
+            [],
+        )?;
 
         // Create FTS5 virtual table for full-text search
         conn.execute(
@@ -224,6 +340,20 @@ impl Database {
         Ok(())
     }
 
+    fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let exists = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .filter_map(|name| name.ok())
+            .any(|name| name == column);
+
+        if !exists {
+            conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+        }
+
+        Ok(())
+    }
+
     pub fn get_connection(&self) -> Arc<Mutex<Connection>> {
         self.conn.clone()
     }
@@ -235,6 +365,7 @@ mod tests {
     use tempfile::TempDir;
 
     #[test]
+    #[allow(unused_variables)]
     fn test_database_initialization() {
         let temp_dir = TempDir::new().unwrap();
         let db_path = temp_dir.path().join("test.db");
@@ -245,5 +376,65 @@ mod tests {
         let db = Database::new(&config).unwrap();
         assert!(db_path.exists());
     }
+
+    #[test]
+    fn test_extension_formats_are_migrated() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = DatabaseConfig {
+            database_path: temp_dir.path().join("test.db").to_str().unwrap().to_string(),
+        };
+
+        let db = Database::new(&config).unwrap();
+        {
+            let conn = db.get_connection();
+            let conn = conn.lock().unwrap();
+            for (id, format) in [("1", "jpg"), ("2", "heic"), ("3", "png")] {
+                conn.execute(
+                    "INSERT INTO images (id, file_path, file_name, file_size, format, created_at, updated_at, last_scanned_at)
+                     VALUES (?1, ?1, ?1, 0, ?2, '', '', '')",
+                    rusqlite::params![id, format],
+                )
+                .unwrap();
+            }
+        }
+        drop(db);
+
+        let db = Database::new(&config).unwrap();
+        let conn = db.get_connection();
+        let conn = conn.lock().unwrap();
+        let formats: Vec<String> = conn
+            .prepare("SELECT format FROM images ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|f| f.unwrap())
+            .collect();
+        assert_eq!(formats, vec!["jpeg", "heif", "png"]);
+    }
+
+    #[test]
+    fn test_existing_database_gains_new_columns() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("old.db");
+        Connection::open(&db_path)
+            .unwrap()
+            .execute(
+                "CREATE TABLE images (
+                    id TEXT PRIMARY KEY, file_path TEXT NOT NULL UNIQUE, file_name TEXT NOT NULL,
+                    file_size INTEGER NOT NULL, format TEXT NOT NULL, width INTEGER, height INTEGER,
+                    hash TEXT, created_at TEXT NOT NULL, updated_at TEXT NOT NULL, last_scanned_at TEXT NOT NULL
+                )",
+                [],
+            )
+            .unwrap();
+
+        let config = DatabaseConfig {
+            database_path: db_path.to_str().unwrap().to_string(),
+        };
+        let db = Database::new(&config).unwrap();
+        let conn = db.get_connection();
+        let conn = conn.lock().unwrap();
+        conn.execute("SELECT generator, generator_version, extension_mismatch FROM images", []).unwrap();
+    }
 }
 
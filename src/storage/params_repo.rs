@@ -0,0 +1,214 @@
+use crate::extraction::GenerationParams;
+use crate::storage::Database;
+use rusqlite::params;
+use rusqlite::types::Value;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredParams {
+    pub image_id: String,
+    #[serde(flatten)]
+    pub params: GenerationParams,
+    pub created_at: String,
+}
+
+/// Numeric constraints on generation parameters; unset bounds don't filter
+#[derive(Debug, Clone, Default)]
+pub struct ParamsFilter {
+    pub seed: Option<u64>,
+    pub min_steps: Option<u32>,
+    pub max_steps: Option<u32>,
+    pub min_cfg: Option<f32>,
+    pub max_cfg: Option<f32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl ParamsFilter {
+    pub fn is_empty(&self) -> bool {
+        self.seed.is_none()
+            && self.min_steps.is_none()
+            && self.max_steps.is_none()
+            && self.min_cfg.is_none()
+            && self.max_cfg.is_none()
+            && self.width.is_none()
+            && self.height.is_none()
+    }
+}
+
+#[derive(Clone)]
+pub struct ParamsRepository {
+    db: Database,
+}
+
+/// SQLite integers are signed, so seeds above `i64::MAX` are stored bit-cast; equality
+/// lookups still work, but seed ranges would not
+fn seed_to_sql(seed: u64) -> i64 {
+    seed as i64
+}
+
+impl ParamsRepository {
+    pub fn new(db: Database) -> Self {
+        ParamsRepository { db }
+    }
+
+    pub fn create(&self, image_id: &str, generation: &GenerationParams, created_at: &str) -> anyhow::Result<()> {
+        let conn = self.db.get_connection();
+        let conn = conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT OR REPLACE INTO generation_params (image_id, seed, steps, cfg_scale, width, height, denoise, clip_skip, sampler, scheduler, warnings, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                image_id,
+                generation.seed.map(seed_to_sql),
+                generation.steps,
+                generation.cfg_scale,
+                generation.width,
+                generation.height,
+                generation.denoise,
+                generation.clip_skip,
+                generation.sampler,
+                generation.scheduler,
+                serde_json::to_string(&generation.warnings)?,
+                created_at,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn find_by_image_id(&self, image_id: &str) -> anyhow::Result<Option<StoredParams>> {
+        let conn = self.db.get_connection();
+        let conn = conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT image_id, seed, steps, cfg_scale, width, height, denoise, clip_skip, sampler, scheduler, warnings, created_at
+             FROM generation_params WHERE image_id = ?1",
+        )?;
+
+        let stored = stmt.query_row(params![image_id], |row| {
+            let warnings: Option<String> = row.get(10)?;
+            Ok(StoredParams {
+                image_id: row.get(0)?,
+                params: GenerationParams {
+                    seed: row.get::<_, Option<i64>>(1)?.map(|seed| seed as u64),
+                    steps: row.get(2)?,
+                    cfg_scale: row.get(3)?,
+                    width: row.get(4)?,
+                    height: row.get(5)?,
+                    denoise: row.get(6)?,
+                    clip_skip: row.get(7)?,
+                    sampler: row.get(8)?,
+                    scheduler: row.get(9)?,
+                    warnings: warnings
+                        .and_then(|w| serde_json::from_str(&w).ok())
+                        .unwrap_or_default(),
+                },
+                created_at: row.get(11)?,
+            })
+        });
+
+        match stored {
+            Ok(stored) => Ok(Some(stored)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Ids of images whose parameters satisfy every bound of the filter
+    pub fn find_image_ids(&self, filter: &ParamsFilter) -> anyhow::Result<Vec<String>> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+        let mut bound = |condition: &str, value: Value| {
+            values.push(value);
+            conditions.push(format!("{} ?{}", condition, values.len()));
+        };
+        if let Some(seed) = filter.seed {
+            bound("seed =", Value::Integer(seed_to_sql(seed)));
+        }
+        if let Some(min) = filter.min_steps {
+            bound("steps >=", Value::Integer(min.into()));
+        }
+        if let Some(max) = filter.max_steps {
+            bound("steps <=", Value::Integer(max.into()));
+        }
+        if let Some(min) = filter.min_cfg {
+            bound("cfg_scale >=", Value::Real(min.into()));
+        }
+        if let Some(max) = filter.max_cfg {
+            bound("cfg_scale <=", Value::Real(max.into()));
+        }
+        if let Some(width) = filter.width {
+            bound("width =", Value::Integer(width.into()));
+        }
+        if let Some(height) = filter.height {
+            bound("height =", Value::Integer(height.into()));
+        }
+
+        let mut sql = "SELECT image_id FROM generation_params".to_string();
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+
+        let conn = self.db.get_connection();
+        let conn = conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let ids = stmt.query_map(rusqlite::params_from_iter(values), |row| row.get(0))?;
+
+        let mut result = Vec::new();
+        for id in ids {
+            result.push(id?);
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DatabaseConfig;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_range_filter_and_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(&DatabaseConfig {
+            database_path: temp_dir.path().join("test.db").to_str().unwrap().to_string(),
+        })
+        .unwrap();
+        let repo = ParamsRepository::new(db.clone());
+
+        for (id, steps, cfg, seed) in [("a", 20, 7.0, 1), ("b", 35, 6.0, u64::MAX), ("c", 40, 9.5, 3)] {
+            db.get_connection()
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT INTO images (id, file_path, file_name, file_size, format, created_at, updated_at, last_scanned_at)
+                     VALUES (?1, ?1, ?1, 0, 'png', '', '', '')",
+                    params![id],
+                )
+                .unwrap();
+            let generation = GenerationParams {
+                steps: Some(steps),
+                cfg_scale: Some(cfg),
+                seed: Some(seed),
+                warnings: vec!["size: invalid value \"1024\"".to_string()],
+                ..Default::default()
+            };
+            repo.create(id, &generation, "2024-01-01T00:00:00Z").unwrap();
+        }
+
+        let filter = ParamsFilter { min_steps: Some(30), min_cfg: Some(5.0), max_cfg: Some(7.0), ..Default::default() };
+        assert_eq!(repo.find_image_ids(&filter).unwrap(), vec!["b".to_string()]);
+
+        let by_seed = ParamsFilter { seed: Some(u64::MAX), ..Default::default() };
+        assert_eq!(repo.find_image_ids(&by_seed).unwrap(), vec!["b".to_string()]);
+
+        let stored = repo.find_by_image_id("b").unwrap().unwrap();
+        assert_eq!(stored.params.seed, Some(u64::MAX));
+        assert_eq!(stored.params.warnings.len(), 1);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::storage::Database;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+/// Where an extracted field value was read from, as recorded at ingestion
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldSourceRecord {
+    pub id: String,
+    pub image_id: String,
+    pub field: String,
+    pub value: String,
+    pub container: String,
+    pub key: String,
+    pub offset: Option<u64>,
+    pub parser: String,
+    pub selected: bool,
+    pub created_at: String,
+}
+
+#[derive(Clone)]
+pub struct SourceRepository {
+    db: Database,
+}
+
+impl SourceRepository {
+    pub fn new(db: Database) -> Self {
+        SourceRepository { db }
+    }
+
+    pub fn create(&self, source: &FieldSourceRecord) -> anyhow::Result<()> {
+        let conn = self.db.get_connection();
+        let conn = conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT OR REPLACE INTO field_sources (id, image_id, field, value, container, key, byte_offset, parser, selected, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                source.id,
+                source.image_id,
+                source.field,
+                source.value,
+                source.container,
+                source.key,
+                source.offset.map(|offset| offset as i64),
+                source.parser,
+                source.selected,
+                source.created_at,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Sources of an image's fields, in the order the parsers produced them
+    pub fn find_by_image_id(&self, image_id: &str) -> anyhow::Result<Vec<FieldSourceRecord>> {
+        let conn = self.db.get_connection();
+        let conn = conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT id, image_id, field, value, container, key, byte_offset, parser, selected, created_at
+             FROM field_sources WHERE image_id = ?1 ORDER BY rowid",
+        )?;
+
+        let sources = stmt.query_map(params![image_id], |row| {
+            Ok(FieldSourceRecord {
+                id: row.get(0)?,
+                image_id: row.get(1)?,
+                field: row.get(2)?,
+                value: row.get(3)?,
+                container: row.get(4)?,
+                key: row.get(5)?,
+                offset: row.get::<_, Option<i64>>(6)?.map(|offset| offset as u64),
+                parser: row.get(7)?,
+                selected: row.get(8)?,
+                created_at: row.get(9)?,
+            })
+        })?;
+
+        let mut result = Vec::new();
+        for source in sources {
+            result.push(source?);
+        }
+
+        Ok(result)
+    }
+}
@@ -0,0 +1,102 @@
+use crate::storage::Database;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stage {
+    pub id: String,
+    pub image_id: String,
+    pub stage_index: u32,
+    pub kind: String, // "base", "hires_fix", "refiner", "upscale"
+    pub model: Option<String>,
+    pub sampler: Option<String>,
+    pub scheduler: Option<String>,
+    pub steps: Option<String>,
+    pub cfg_scale: Option<String>,
+    pub seed: Option<String>,
+    pub denoise: Option<String>,
+    pub size: Option<String>,
+    pub upscaler: Option<String>,
+    pub start_step: Option<String>,
+    pub end_step: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Clone)]
+pub struct StageRepository {
+    db: Database,
+}
+
+impl StageRepository {
+    pub fn new(db: Database) -> Self {
+        StageRepository { db }
+    }
+
+    pub fn create(&self, stage: &Stage) -> anyhow::Result<()> {
+        let conn = self.db.get_connection();
+        let conn = conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT OR REPLACE INTO generation_stages (id, image_id, stage_index, kind, model, sampler, scheduler, steps, cfg_scale, seed, denoise, size, upscaler, start_step, end_step, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+            params![
+                stage.id,
+                stage.image_id,
+                stage.stage_index,
+                stage.kind,
+                stage.model,
+                stage.sampler,
+                stage.scheduler,
+                stage.steps,
+                stage.cfg_scale,
+                stage.seed,
+                stage.denoise,
+                stage.size,
+                stage.upscaler,
+                stage.start_step,
+                stage.end_step,
+                stage.created_at,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn find_by_image_id(&self, image_id: &str) -> anyhow::Result<Vec<Stage>> {
+        let conn = self.db.get_connection();
+        let conn = conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT id, image_id, stage_index, kind, model, sampler, scheduler, steps, cfg_scale, seed, denoise, size, upscaler, start_step, end_step, created_at
+             FROM generation_stages WHERE image_id = ?1 ORDER BY stage_index",
+        )?;
+
+        let stages = stmt.query_map(params![image_id], |row| {
+            Ok(Stage {
+                id: row.get(0)?,
+                image_id: row.get(1)?,
+                stage_index: row.get(2)?,
+                kind: row.get(3)?,
+                model: row.get(4)?,
+                sampler: row.get(5)?,
+                scheduler: row.get(6)?,
+                steps: row.get(7)?,
+                cfg_scale: row.get(8)?,
+                seed: row.get(9)?,
+                denoise: row.get(10)?,
+                size: row.get(11)?,
+                upscaler: row.get(12)?,
+                start_step: row.get(13)?,
+                end_step: row.get(14)?,
+                created_at: row.get(15)?,
+            })
+        })?;
+
+        let mut result = Vec::new();
+        for stage in stages {
+            result.push(stage?);
+        }
+
+        Ok(result)
+    }
+}
@@ -21,6 +21,11 @@ pub fn calculate_file_hash<P: AsRef<Path>>(path: P) -> anyhow::Result<String> {
     Ok(hex::encode(hash))
 }
 
+/// SHA-256 of in-memory data, hex-encoded like `calculate_file_hash`
+pub fn calculate_hash(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,5 +1,5 @@
 pub mod hash;
 pub mod thumbnail;
 
-pub use hash::calculate_file_hash;
+pub use hash::{calculate_file_hash, calculate_hash};
 
@@ -23,8 +23,11 @@ pub fn generate_thumbnail(
             .with_context(|| format!("Failed to create thumbnail directory: {}", parent.display()))?;
     }
 
-    // Load the source image
-    let img = image::open(image_path)
+    // Load the source image, guessing the decoder from the content so renamed files still decode
+    let img = image::io::Reader::open(image_path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(anyhow::Error::from)
+        .and_then(|reader| Ok(reader.decode()?))
         .with_context(|| format!("Failed to open image: {}", image_path.display()))?;
 
     // Calculate thumbnail dimensions (maintain aspect ratio)
@@ -122,7 +125,6 @@ pub fn thumbnail_exists_and_valid(thumbnail_path: &Path, source_path: &Path) ->
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::TempDir;
 
     #[test]
     fn test_calculate_thumbnail_size() {
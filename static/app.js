@@ -897,6 +897,49 @@ async function showImageDetail(imageId) {
             // Metadata is optional, fail silently
         }
         
+        // Provenance (C2PA Content Credentials) comes with the image record
+        let provenanceHtml = '';
+        const provenance = Object.entries(image.provenance || {});
+        if (provenance.length > 0) {
+            provenanceHtml = `
+                <div class="metadata-section">
+                    <h3>Provenance</h3>
+                    <dl class="metadata-list">
+                        ${provenance.map(([key, value]) => `
+                            <dt>${escapeHtml(key)}</dt>
+                            <dd>${escapeHtml(value)}</dd>
+                        `).join('')}
+                    </dl>
+                </div>
+            `;
+        }
+
+        // Generation pipeline (base, hires fix, refiner, upscale passes) comes with the image record
+        let stagesHtml = '';
+        const stages = image.stages || [];
+        if (stages.length > 0) {
+            const fields = [
+                ['model', 'Model'], ['sampler', 'Sampler'], ['scheduler', 'Scheduler'],
+                ['steps', 'Steps'], ['cfg_scale', 'CFG'], ['seed', 'Seed'],
+                ['denoise', 'Denoise'], ['size', 'Size'], ['upscaler', 'Upscaler'],
+                ['start_step', 'Start step'], ['end_step', 'End step']
+            ];
+            stagesHtml = `
+                <div class="metadata-section">
+                    <h3>Generation Pipeline</h3>
+                    ${stages.map((stage, i) => `
+                        <h4>${i + 1}. ${escapeHtml(stage.kind.replace('_', ' '))}</h4>
+                        <dl class="metadata-list">
+                            ${fields.filter(([key]) => stage[key]).map(([key, label]) => `
+                                <dt>${label}</dt>
+                                <dd>${escapeHtml(String(stage[key]))}</dd>
+                            `).join('')}
+                        </dl>
+                    `).join('')}
+                </div>
+            `;
+        }
+        
         content.innerHTML = `
             <div class="image-detail-view">
                 <div class="image-detail-header">
@@ -907,11 +950,16 @@ async function showImageDetail(imageId) {
                     <h3>${escapeHtml(image.file_name)}</h3>
                     <div class="detail-grid">
                         <div class="detail-item">
-                            <strong>Format:</strong> ${image.format.toUpperCase()}
+                            <strong>Format:</strong> ${image.format.toUpperCase()}${image.extension_mismatch ? ' (extension does not match content)' : ''}
                         </div>
                         <div class="detail-item">
                             <strong>Size:</strong> ${formatFileSize(image.file_size)}
                         </div>
+                        ${image.generator ? `
+                            <div class="detail-item">
+                                <strong>Generator:</strong> ${escapeHtml(image.generator)}${image.generator_version ? ` ${escapeHtml(image.generator_version)}` : ''}
+                            </div>
+                        ` : ''}
                         ${image.width && image.height ? `
                             <div class="detail-item">
                                 <strong>Dimensions:</strong> ${image.width} × ${image.height}
@@ -929,6 +977,8 @@ async function showImageDetail(imageId) {
                         <h3>Prompts</h3>
                         ${promptsHtml}
                     </div>
+                    ${stagesHtml}
+                    ${provenanceHtml}
                     ${metadataHtml}
                 </div>
             </div>